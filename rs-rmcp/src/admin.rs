@@ -0,0 +1,337 @@
+//! 会话管理接口
+//!
+//! 需设置 `admin.token`, 请求携带 `Authorization: Bearer <token>`:
+//! - `GET /admin/sessions`: 会话列表
+//! - `GET /admin/sessions/{id}`: 会话详情, 包括执行中的请求与资源订阅
+//! - `DELETE /admin/sessions/{id}`: 强制关闭会话
+//! - `DELETE /admin/sessions/{id}/requests/{request_id}`: 取消执行中的请求
+//!
+//! 设置 `admin.address` 时只在管理端口提供, 否则与 MCP 服务共用端口。
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, Request, State},
+    http::{StatusCode, header::AUTHORIZATION},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+};
+use clap::Subcommand;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    config::Config,
+    error::Error,
+    session_limits::CloseReason,
+    sessions::{SessionDetail, SessionSummary},
+    state::AppState,
+    tls,
+};
+
+const SESSIONS_PATH: &str = "/admin/sessions";
+const NOT_FOUND: (StatusCode, &str) = (StatusCode::NOT_FOUND, "session not found");
+
+pub fn router(state: Arc<AppState>, token: &str) -> axum::Router {
+    axum::Router::new()
+        .route(SESSIONS_PATH, get(list_sessions))
+        .route(
+            &format!("{SESSIONS_PATH}/{{id}}"),
+            get(show_session).delete(close_session),
+        )
+        .route(
+            &format!("{SESSIONS_PATH}/{{id}}/requests/{{request_id}}"),
+            delete(cancel_request),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::<str>::from(token),
+            require_token,
+        ))
+        .with_state(state)
+}
+
+/// 校验 Bearer token, 失败返回 401
+async fn require_token(State(token): State<Arc<str>>, req: Request, next: Next) -> Response {
+    let authorized = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|v| constant_time_eq(v.as_bytes(), token.as_bytes()));
+    if !authorized {
+        return (StatusCode::UNAUTHORIZED, "invalid admin token").into_response();
+    }
+    next.run(req).await
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// `GET /admin/sessions`
+async fn list_sessions(State(state): State<Arc<AppState>>) -> Json<Vec<SessionSummary>> {
+    Json(state.sessions.list().iter().map(|s| s.summary()).collect())
+}
+
+/// `GET /admin/sessions/{id}`
+async fn show_session(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<SessionDetail>, (StatusCode, &'static str)> {
+    let entry = state.sessions.get(&id).ok_or(NOT_FOUND)?;
+    Ok(Json(entry.detail()))
+}
+
+/// `DELETE /admin/sessions/{id}`
+async fn close_session(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Response {
+    if state.close_session(&id, CloseReason::Admin).await {
+        tracing::info!(session = id, "session closed by admin");
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        NOT_FOUND.into_response()
+    }
+}
+
+/// `DELETE /admin/sessions/{id}/requests/{request_id}`
+async fn cancel_request(
+    State(state): State<Arc<AppState>>,
+    Path((id, request_id)): Path<(String, String)>,
+) -> Response {
+    let cancelled = state
+        .sessions
+        .get(&id)
+        .is_some_and(|entry| entry.cancel_request(&request_id));
+    if cancelled {
+        tracing::info!(session = id, request_id, "request cancelled by admin");
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "request not found").into_response()
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SessionsCommand {
+    /// List active sessions
+    List,
+    /// Show client info, in-flight requests and subscriptions of a session
+    Show { id: String },
+    /// Force close a session
+    Close { id: String },
+    /// Cancel an in-flight request of a session
+    Cancel { id: String, request_id: String },
+}
+
+/// 管理接口客户端
+pub struct AdminClient {
+    http: reqwest::Client,
+    base: reqwest::Url,
+    token: String,
+}
+
+impl AdminClient {
+    /// `url` 缺省时使用 `admin.address`, 未设置则使用 `address`
+    ///
+    /// 管理接口与 MCP 端点共用监听且启用了 TLS 时使用 HTTPS, 以 `admin.ca` 校验服务端证书,
+    /// 设置了 `admin.client_cert` 时出示客户端证书。
+    pub fn new(config: &Config, url: Option<&str>) -> Result<Self, Error> {
+        let admin = &config.admin;
+        let error = |message: String| Error::AdminApi {
+            status: None,
+            message,
+        };
+        let token = admin
+            .token
+            .clone()
+            .ok_or_else(|| error("admin.token is not set".to_string()))?;
+        let base = match (url, &admin.address) {
+            (Some(url), _) => url.to_string(),
+            (None, Some(address)) => format!("http://{address}"),
+            (None, None) if config.tls.is_some() => format!("https://{}", config.address),
+            (None, None) => format!("http://{}", config.address),
+        };
+        let base =
+            reqwest::Url::parse(&base).map_err(|e| error(format!("invalid url `{base}`: {e}")))?;
+
+        let mut http = reqwest::Client::builder();
+        if base.scheme() == "https" {
+            let ca = admin
+                .ca
+                .as_deref()
+                .ok_or_else(|| error(format!("admin.ca is required to connect to {base}")))?;
+            let client_auth = admin
+                .client_cert
+                .as_deref()
+                .zip(admin.client_key.as_deref());
+            let mtls = url.is_none()
+                && config
+                    .tls
+                    .as_ref()
+                    .is_some_and(|tls| tls.client_ca.is_some());
+            if mtls && client_auth.is_none() {
+                return Err(error(
+                    "admin.client_cert and admin.client_key are required by tls.client_ca"
+                        .to_string(),
+                ));
+            }
+            http = http.use_preconfigured_tls(tls::build_client_config(ca, client_auth)?);
+        }
+        Ok(Self {
+            http: http.build()?,
+            base,
+            token,
+        })
+    }
+
+    fn url(&self, segments: &[&str]) -> reqwest::Url {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .expect("http url")
+            .pop_if_empty()
+            .extend(SESSIONS_PATH.trim_start_matches('/').split('/'))
+            .extend(segments);
+        url
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, Error> {
+        let response = request.bearer_auth(&self.token).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::AdminApi {
+                status: Some(status.as_u16()),
+                message: response.text().await.unwrap_or_default(),
+            });
+        }
+        Ok(response)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, segments: &[&str]) -> Result<T, Error> {
+        let response = self.send(self.http.get(self.url(segments))).await?;
+        Ok(response.json().await?)
+    }
+
+    async fn delete(&self, segments: &[&str]) -> Result<(), Error> {
+        self.send(self.http.delete(self.url(segments))).await?;
+        Ok(())
+    }
+
+    pub async fn list(&self) -> Result<Vec<SessionSummary>, Error> {
+        self.get(&[]).await
+    }
+
+    pub async fn show(&self, id: &str) -> Result<SessionDetail, Error> {
+        self.get(&[id]).await
+    }
+
+    pub async fn close(&self, id: &str) -> Result<(), Error> {
+        self.delete(&[id]).await
+    }
+
+    pub async fn cancel(&self, id: &str, request_id: &str) -> Result<(), Error> {
+        self.delete(&[id, "requests", request_id]).await
+    }
+
+    /// 执行 `admin sessions` 子命令
+    pub async fn run(&self, command: &SessionsCommand) -> Result<(), Error> {
+        match command {
+            SessionsCommand::List => {
+                for s in self.list().await? {
+                    let client = s
+                        .client_info
+                        .map(|c| format!("{}/{}", c.name, c.version))
+                        .unwrap_or_else(|| "-".to_string());
+                    println!(
+                        "{} {} client={} identity={} created={} last_activity={} in_flight={}",
+                        s.id.as_deref().unwrap_or("-"),
+                        s.transport,
+                        client,
+                        s.identity.as_deref().unwrap_or("-"),
+                        s.created_at
+                            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                        s.last_activity
+                            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                        s.in_flight,
+                    );
+                }
+            }
+            SessionsCommand::Show { id } => {
+                println!("{}", serde_json::to_string_pretty(&self.show(id).await?)?);
+            }
+            SessionsCommand::Close { id } => {
+                self.close(id).await?;
+                println!("session {id} closed");
+            }
+            SessionsCommand::Cancel { id, request_id } => {
+                self.cancel(id, request_id).await?;
+                println!("request {request_id} of session {id} cancelled");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::serve::Listener;
+
+    use super::*;
+    use crate::tls::{TlsConfig, TlsListener, TlsPeer};
+
+    #[test]
+    fn test_client_url() {
+        let mut config = Config::default();
+        config.admin.token = Some("t".to_string());
+        let client = AdminClient::new(&config, None).unwrap();
+        assert_eq!(
+            client.url(&["a b", "requests", "1/2"]).as_str(),
+            "http://127.0.0.1:8000/admin/sessions/a%20b/requests/1%2F2"
+        );
+
+        let client = AdminClient::new(&config, Some("http://h:1/prefix/")).unwrap();
+        assert_eq!(client.url(&[]).as_str(), "http://h:1/prefix/admin/sessions");
+
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+    }
+
+    #[tokio::test]
+    async fn test_client_over_mtls() {
+        let dir = std::env::temp_dir().join(format!("rs-mcpr-admin-tls-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        tls::generate_dev_certificates(&dir, vec!["127.0.0.1".to_string()], "admin").unwrap();
+        let tls = TlsConfig {
+            cert: dir.join("server.pem"),
+            key: dir.join("server.key"),
+            client_ca: Some(dir.join("ca.pem")),
+        };
+        let listener = TlsListener::bind("127.0.0.1:0", tls.clone()).await.unwrap();
+        let mut config = Config {
+            address: listener.local_addr().unwrap().remote_addr.to_string(),
+            tls: Some(tls),
+            ..Default::default()
+        };
+        config.admin.token = Some("t".to_string());
+        let state = Arc::new(AppState::new(config.clone()).unwrap());
+        let app = router(state, "t");
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<TlsPeer>(),
+            )
+            .await
+        });
+
+        // 共用启用 TLS 的监听时默认使用 HTTPS, 需要 CA 与客户端证书
+        assert!(AdminClient::new(&config, None).is_err());
+        config.admin.ca = Some(dir.join("ca.pem"));
+        assert!(AdminClient::new(&config, None).is_err());
+        config.admin.client_cert = Some(dir.join("client.pem"));
+        config.admin.client_key = Some(dir.join("client.key"));
+        config.validate().unwrap();
+        let client = AdminClient::new(&config, None).unwrap();
+        assert_eq!(client.base.scheme(), "https");
+        assert!(client.list().await.unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,545 @@
+//! 审计日志
+//!
+//! 每次工具调用、资源读取与提示词渲染追加一条 JSON Lines 记录, 包括时间、会话、
+//! 调用方身份、传输方式、方法、工具名或 URI、参数摘要、结果与耗时。
+//!
+//! - `file`: 追加写入单个文件
+//! - `rotating`: 超过 `max_bytes` 后轮转为 `<path>.1` .. `<path>.<max_files>`
+//!
+//! 审计文件仅所有者可读写, 通过 `rs-mcpr admin audit` 查询。
+use std::{
+    fmt::Debug,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Instant,
+};
+
+use chrono::{DateTime, Utc};
+use rmcp::{ErrorData as McpError, RoleServer, model::JsonObject, service::RequestContext};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    error::Error,
+    identity::CallerIdentity,
+    instrument::{self, REQUEST_CANCELLED},
+    metrics::SessionTransport,
+};
+
+/// 脱敏后的取值
+const REDACTED: &str = "[REDACTED]";
+
+/// 审计记录写入方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditSinkKind {
+    /// 单个 JSON Lines 文件
+    #[default]
+    File,
+    /// 按大小轮转的 JSON Lines 文件
+    Rotating,
+}
+
+/// 参数记录方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditArguments {
+    /// 只记录参数的 SHA-256
+    #[default]
+    Hash,
+    /// 记录脱敏后的参数
+    Redacted,
+    /// 不记录参数
+    None,
+}
+
+/// 审计配置, 对应配置文件的 `[audit]` 表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    pub enabled: bool,
+    pub sink: AuditSinkKind,
+    /// 审计文件路径
+    pub path: PathBuf,
+    pub arguments: AuditArguments,
+    /// `arguments = "redacted"` 时, 名称包含任一项 (不区分大小写) 的字段被替换
+    pub redact: Vec<String>,
+    /// 单个文件的最大字节数, 仅 `rotating` 使用
+    pub max_bytes: u64,
+    /// 保留的轮转文件数, 仅 `rotating` 使用
+    pub max_files: usize,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sink: AuditSinkKind::File,
+            path: PathBuf::from("audit.jsonl"),
+            arguments: AuditArguments::Hash,
+            redact: ["authorization", "cookie", "token", "password", "secret"]
+                .map(String::from)
+                .to_vec(),
+            max_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+/// 审计记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub session: Option<String>,
+    pub identity: Option<String>,
+    pub transport: Option<String>,
+    pub method: String,
+    /// 工具名、资源 URI 或提示词名
+    pub target: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments_sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<Value>,
+    /// `ok`、`error` 或 `rate_limited`
+    pub outcome: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<i32>,
+    pub latency_ms: f64,
+}
+
+/// 审计记录的写入目标
+pub trait AuditSink: Debug + Send + Sync {
+    fn write(&self, record: &AuditRecord) -> io::Result<()>;
+
+    /// 存储是否可用, 用于就绪检查
+    fn check(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 文件仍然存在, 未被删除或移走
+fn check_file(path: &Path) -> io::Result<()> {
+    fs::metadata(path).map(|_| ())
+}
+
+/// 只追加打开文件, 仅所有者可读写
+pub fn open_append(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = File::options();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+fn to_line(record: &AuditRecord) -> io::Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// 单个 JSON Lines 文件
+#[derive(Debug)]
+pub struct JsonLinesSink {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl JsonLinesSink {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(open_append(path)?),
+        })
+    }
+}
+
+impl AuditSink for JsonLinesSink {
+    fn write(&self, record: &AuditRecord) -> io::Result<()> {
+        self.file.lock().unwrap().write_all(&to_line(record)?)
+    }
+
+    fn check(&self) -> io::Result<()> {
+        check_file(&self.path)
+    }
+}
+
+/// 按大小轮转的 JSON Lines 文件
+#[derive(Debug)]
+pub struct RotatingFileSink {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    /// 当前文件与已写入的字节数
+    current: Mutex<(File, u64)>,
+}
+
+impl RotatingFileSink {
+    pub fn open(path: &Path, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let file = open_append(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            max_files,
+            current: Mutex::new((file, len)),
+        })
+    }
+
+    /// `<path>` -> `<path>.1` -> ... -> `<path>.<max_files>`, 最旧的文件被覆盖
+    fn rotate(&self) -> io::Result<File> {
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, index);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, index + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        open_append(&self.path)
+    }
+}
+
+impl AuditSink for RotatingFileSink {
+    fn write(&self, record: &AuditRecord) -> io::Result<()> {
+        let line = to_line(record)?;
+        let mut current = self.current.lock().unwrap();
+        if current.1 > 0 && current.1 + line.len() as u64 > self.max_bytes {
+            *current = (self.rotate()?, 0);
+        }
+        current.0.write_all(&line)?;
+        current.1 += line.len() as u64;
+        Ok(())
+    }
+
+    fn check(&self) -> io::Result<()> {
+        check_file(&self.path)
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+/// 审计记录器, 未启用时不做任何事
+#[derive(Debug)]
+pub struct Auditor {
+    sink: Option<Box<dyn AuditSink>>,
+    arguments: AuditArguments,
+    redact: Vec<String>,
+}
+
+impl Auditor {
+    pub fn new(config: &AuditConfig) -> Result<Self, Error> {
+        let sink: Option<Box<dyn AuditSink>> = match (config.enabled, config.sink) {
+            (false, _) => None,
+            (true, AuditSinkKind::File) => Some(Box::new(JsonLinesSink::open(&config.path)?)),
+            (true, AuditSinkKind::Rotating) => Some(Box::new(RotatingFileSink::open(
+                &config.path,
+                config.max_bytes,
+                config.max_files,
+            )?)),
+        };
+        Ok(Self::with_sink(sink, config))
+    }
+
+    /// 使用自定义写入目标
+    pub fn with_sink(sink: Option<Box<dyn AuditSink>>, config: &AuditConfig) -> Self {
+        Self {
+            sink,
+            arguments: config.arguments,
+            redact: config.redact.iter().map(|s| s.to_lowercase()).collect(),
+        }
+    }
+
+    /// 审计存储是否可用, 未启用时总是可用
+    pub fn check(&self) -> io::Result<()> {
+        self.sink.as_ref().map_or(Ok(()), |sink| sink.check())
+    }
+
+    /// 开始记录一次操作, 调用 [`AuditEntry::finish`] 时写入
+    pub fn begin(
+        &self,
+        context: &RequestContext<RoleServer>,
+        method: &str,
+        target: &str,
+        arguments: Option<&JsonObject>,
+    ) -> AuditEntry<'_> {
+        if self.sink.is_none() {
+            return AuditEntry(None);
+        }
+
+        let mut record = AuditRecord {
+            timestamp: Utc::now(),
+            session: instrument::session_id(&context.extensions).map(String::from),
+            identity: CallerIdentity::from_context(context).map(|id| id.0.clone()),
+            transport: context
+                .extensions
+                .get::<SessionTransport>()
+                .map(|t| t.as_str().to_string()),
+            method: method.to_string(),
+            target: target.to_string(),
+            arguments_sha256: None,
+            arguments: None,
+            outcome: String::new(),
+            error_code: None,
+            latency_ms: 0.0,
+        };
+        if let Some(arguments) = arguments {
+            match self.arguments {
+                AuditArguments::Hash => record.arguments_sha256 = Some(sha256(arguments)),
+                AuditArguments::Redacted => {
+                    let mut value = Value::Object(arguments.clone());
+                    self.redact_value(&mut value);
+                    record.arguments = Some(value);
+                }
+                AuditArguments::None => {}
+            }
+        }
+        AuditEntry(Some((self, record, Instant::now())))
+    }
+
+    fn redact_value(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object {
+                    let key = key.to_lowercase();
+                    if self.redact.iter().any(|name| key.contains(name.as_str())) {
+                        *value = Value::String(REDACTED.to_string());
+                    } else {
+                        self.redact_value(value);
+                    }
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|v| self.redact_value(v)),
+            _ => {}
+        }
+    }
+
+    fn write(&self, record: &AuditRecord) {
+        if let Some(sink) = &self.sink
+            && let Err(e) = sink.write(record)
+        {
+            tracing::error!("failed to write audit record: {e}");
+        }
+    }
+}
+
+/// 参数的 SHA-256, serde_json 对象按键排序, 相同参数的摘要相同
+fn sha256(arguments: &JsonObject) -> String {
+    let bytes = serde_json::to_vec(arguments).unwrap_or_default();
+    ring::digest::digest(&ring::digest::SHA256, &bytes)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// 进行中的审计记录
+///
+/// 未调用 [`AuditEntry::finish`] 就被丢弃 (请求被取消) 时以 `cancelled` 写入。
+#[derive(Debug)]
+pub struct AuditEntry<'a>(Option<(&'a Auditor, AuditRecord, Instant)>);
+
+impl AuditEntry<'_> {
+    /// 记录结果与耗时并写入
+    pub fn finish(mut self, outcome: &str, error: Option<&McpError>) {
+        self.write(outcome, error.map(|e| e.code.0));
+    }
+
+    fn write(&mut self, outcome: &str, error_code: Option<i32>) {
+        let Some((auditor, mut record, start)) = self.0.take() else {
+            return;
+        };
+        record.outcome = outcome.to_string();
+        record.error_code = error_code;
+        record.latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+        auditor.write(&record);
+    }
+}
+
+impl Drop for AuditEntry<'_> {
+    fn drop(&mut self) {
+        self.write("cancelled", Some(REQUEST_CANCELLED.0));
+    }
+}
+
+/// 审计查询条件
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct AuditQuery {
+    /// Only records of this session
+    #[arg(long)]
+    pub session: Option<String>,
+
+    /// Only records of this caller identity
+    #[arg(long)]
+    pub identity: Option<String>,
+
+    /// Only records of this method, e.g. tools/call
+    #[arg(long)]
+    pub method: Option<String>,
+
+    /// Only records of this tool name, resource URI or prompt name
+    #[arg(long)]
+    pub target: Option<String>,
+
+    /// Only records with this outcome (ok, error, rate_limited or cancelled)
+    #[arg(long)]
+    pub outcome: Option<String>,
+
+    /// Only records at or after this RFC 3339 timestamp
+    #[arg(long)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Show at most this many of the most recent records
+    #[arg(long)]
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        let eq = |filter: &Option<String>, value: Option<&str>| {
+            filter.as_deref().is_none_or(|f| Some(f) == value)
+        };
+        eq(&self.session, record.session.as_deref())
+            && eq(&self.identity, record.identity.as_deref())
+            && eq(&self.method, Some(&record.method))
+            && eq(&self.target, Some(&record.target))
+            && eq(&self.outcome, Some(&record.outcome))
+            && self.since.is_none_or(|since| record.timestamp >= since)
+    }
+}
+
+/// 按时间顺序读取审计文件 (含轮转文件) 中符合条件的记录
+pub fn query(config: &AuditConfig, filter: &AuditQuery) -> Result<Vec<AuditRecord>, Error> {
+    let mut paths: Vec<PathBuf> = (1..=config.max_files)
+        .rev()
+        .map(|index| rotated_path(&config.path, index))
+        .filter(|path| path.exists())
+        .collect();
+    paths.push(config.path.clone());
+
+    let mut records = Vec::new();
+    for path in paths {
+        let file = File::open(&path)?;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: AuditRecord = serde_json::from_str(&line)?;
+            if filter.matches(&record) {
+                records.push(record);
+            }
+        }
+    }
+    if let Some(limit) = filter.limit {
+        records.drain(..records.len().saturating_sub(limit));
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn record(target: &str) -> AuditRecord {
+        AuditRecord {
+            timestamp: Utc::now(),
+            session: Some("s1".to_string()),
+            identity: None,
+            transport: Some("streamable_http".to_string()),
+            method: "tools/call".to_string(),
+            target: target.to_string(),
+            arguments_sha256: None,
+            arguments: None,
+            outcome: "ok".to_string(),
+            error_code: None,
+            latency_ms: 1.5,
+        }
+    }
+
+    #[test]
+    fn test_rotating_sink_and_query() {
+        let dir = std::env::temp_dir().join(format!("rs-mcpr-audit-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = AuditConfig {
+            enabled: true,
+            sink: AuditSinkKind::Rotating,
+            path: dir.join("audit.jsonl"),
+            max_bytes: 400,
+            max_files: 2,
+            ..Default::default()
+        };
+        let sink =
+            RotatingFileSink::open(&config.path, config.max_bytes, config.max_files).unwrap();
+        for i in 0..10 {
+            sink.write(&record(&format!("tool{i}"))).unwrap();
+        }
+        assert!(rotated_path(&config.path, 2).exists());
+        assert!(!rotated_path(&config.path, 3).exists());
+
+        // 最旧的记录已被轮转删除, 其余按时间顺序返回
+        let all = query(&config, &AuditQuery::default()).unwrap();
+        assert!(all.len() < 10);
+        assert_eq!(all.last().unwrap().target, "tool9");
+        let targets: Vec<_> = all.iter().map(|r| r.target.clone()).collect();
+        let mut sorted = targets.clone();
+        sorted.sort();
+        assert_eq!(targets, sorted);
+
+        let filter = AuditQuery {
+            target: Some("tool8".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(query(&config, &filter).unwrap().len(), 1);
+        let filter = AuditQuery {
+            limit: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(query(&config, &filter).unwrap()[0].target, "tool8");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_redact_arguments() {
+        let config = AuditConfig {
+            arguments: AuditArguments::Redacted,
+            ..Default::default()
+        };
+        let auditor = Auditor::with_sink(None, &config);
+        let mut value = json!({
+            "a": 1,
+            "api_token": "abc",
+            "nested": [{ "Password": "x", "b": 2 }],
+        });
+        auditor.redact_value(&mut value);
+        assert_eq!(
+            value,
+            json!({
+                "a": 1,
+                "api_token": REDACTED,
+                "nested": [{ "Password": REDACTED, "b": 2 }],
+            })
+        );
+
+        let a = json!({ "a": 1, "b": 2 });
+        let b = json!({ "b": 2, "a": 1 });
+        assert_eq!(
+            sha256(a.as_object().unwrap()),
+            sha256(b.as_object().unwrap())
+        );
+    }
+}
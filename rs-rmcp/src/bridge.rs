@@ -0,0 +1,571 @@
+//! 传输桥接
+//!
+//! 只支持一种传输的客户端可以通过 `bridge` 子命令使用另一种传输的 MCP 服务:
+//! - `bridge stdio <URL>`: 把远端 streamable HTTP 或 SSE 服务暴露为本地 stdio 服务
+//! - `bridge http -- <COMMAND>`: 把 stdio 服务命令包装为 streamable HTTP 与 SSE 端点,
+//!   每个会话启动一个子进程
+//!
+//! 消息原样转发: 请求 id 不变, 通知与服务端发起的请求同样转发, 每个方向上的消息按收到的顺序发送。
+//! 会话 id 由 HTTP 一侧维护, 一个 stdio 连接对应一个 HTTP 会话。
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io,
+    process::Stdio,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use clap::Subcommand;
+use futures::{FutureExt, future::BoxFuture};
+use rmcp::{
+    ErrorData as McpError, Peer, RoleClient, RoleServer, Service, ServiceExt,
+    model::{
+        ClientJsonRpcMessage, ClientNotification, ClientRequest, GetMeta, JsonRpcMessage,
+        RequestId, ServerInfo, ServerJsonRpcMessage, ServerResult,
+    },
+    service::{NotificationContext, RequestContext, ServiceError},
+    transport::{
+        SseClientTransport, SseServer, StreamableHttpClientTransport, StreamableHttpServerConfig,
+        StreamableHttpService, Transport, async_rw::AsyncRwTransport, sse_client::SseClientConfig,
+        sse_server::SseServerConfig, streamable_http_client::StreamableHttpClientTransportConfig,
+        streamable_http_server::session::local::LocalSessionManager,
+    },
+};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    client::parse_headers,
+    config::{Config, HttpConfig},
+    error::BridgeError,
+    logging::LogOutput,
+};
+
+#[derive(Subcommand, Debug)]
+pub enum BridgeCommand {
+    /// Expose a remote streamable HTTP or SSE MCP server as a stdio server
+    Stdio {
+        /// Remote endpoint, e.g. http://127.0.0.1:8000/mcp
+        url: String,
+
+        /// Connect with the SSE transport instead of streamable HTTP
+        #[arg(long)]
+        sse: bool,
+
+        /// Extra request header (`Name: value`), may be repeated
+        #[arg(long = "header", value_name = "HEADER")]
+        headers: Vec<String>,
+    },
+    /// Serve a stdio MCP server command over streamable HTTP and SSE on
+    /// `address`, one process per session
+    Http {
+        /// Command and arguments of the stdio server
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+}
+
+pub async fn run(config: &Config, command: &BridgeCommand) -> Result<(), BridgeError> {
+    match command {
+        BridgeCommand::Stdio { url, sse, headers } => {
+            if config.log.output == LogOutput::Stdout {
+                return Err(BridgeError::StdoutReserved);
+            }
+            stdio_to_remote(url, *sse, headers).await
+        }
+        BridgeCommand::Http { command } => serve_command(config, command).await,
+    }
+}
+
+/// 把远端服务暴露为 stdio 服务, stdin 关闭或远端断开时结束
+async fn stdio_to_remote(url: &str, sse: bool, headers: &[String]) -> Result<(), BridgeError> {
+    let client = reqwest::Client::builder()
+        .default_headers(parse_headers(headers)?)
+        .build()?;
+    let (stdin, stdout) = rmcp::transport::stdio();
+    let local = AsyncRwTransport::new_server(stdin, stdout);
+    if sse {
+        let config = SseClientConfig {
+            sse_endpoint: url.into(),
+            ..Default::default()
+        };
+        let remote = SseClientTransport::start_with_client(client, config)
+            .await
+            .map_err(|e| BridgeError::Connect {
+                url: url.to_string(),
+                message: e.to_string(),
+            })?;
+        relay(local, remote).await;
+    } else {
+        let config = StreamableHttpClientTransportConfig::with_uri(url);
+        relay(
+            local,
+            StreamableHttpClientTransport::with_client(client, config),
+        )
+        .await;
+    }
+    Ok(())
+}
+
+/// 在客户端一侧与服务端一侧的传输之间转发消息, 任一侧关闭时结束
+async fn relay(mut local: impl Transport<RoleServer>, mut remote: impl Transport<RoleClient>) {
+    let to_remote = Outbox::new();
+    let to_local = Outbox::new();
+    loop {
+        tokio::select! {
+            message = local.receive() => match message {
+                Some(message) => to_remote.push(remote.send(message)),
+                None => break,
+            },
+            message = remote.receive() => match message {
+                Some(message) => to_local.push(local.send(message)),
+                None => {
+                    tracing::info!("remote server closed the connection");
+                    break;
+                }
+            },
+        }
+    }
+    to_remote.finish().await;
+    to_local.finish().await;
+    if let Err(e) = remote.close().await {
+        tracing::debug!("failed to close remote transport: {e}");
+    }
+}
+
+/// 按提交顺序依次完成发送, 不阻塞接收
+struct Outbox {
+    sends: mpsc::UnboundedSender<BoxFuture<'static, Result<(), String>>>,
+    task: JoinHandle<()>,
+}
+
+impl Outbox {
+    fn new() -> Self {
+        let (sends, mut rx) = mpsc::unbounded_channel::<BoxFuture<'static, Result<(), String>>>();
+        let task = tokio::spawn(async move {
+            while let Some(send) = rx.recv().await {
+                if let Err(e) = send.await {
+                    tracing::warn!("failed to forward message: {e}");
+                }
+            }
+        });
+        Self { sends, task }
+    }
+
+    fn push<E: Display>(&self, send: impl Future<Output = Result<(), E>> + Send + 'static) {
+        let _ = self
+            .sends
+            .send(send.map(|r| r.map_err(|e| e.to_string())).boxed());
+    }
+
+    /// 等待已提交的发送完成
+    async fn finish(self) {
+        drop(self.sends);
+        let _ = self.task.await;
+    }
+}
+
+/// 把 stdio 服务命令包装为 streamable HTTP 与 SSE 端点, Ctrl+C 时停止
+async fn serve_command(config: &Config, command: &[String]) -> Result<(), BridgeError> {
+    let http = &config.http;
+    let http_service = command_service(http, command);
+
+    let ct = CancellationToken::new();
+    let (mut sse_server, sse_router) = SseServer::new(SseServerConfig {
+        bind: config
+            .address
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        sse_path: http.sse_path.clone(),
+        post_path: http.post_path.clone(),
+        ct: ct.clone(),
+        sse_keep_alive: http.sse_keep_alive(),
+    });
+    let sse_command = command.to_vec();
+    tokio::spawn(async move {
+        while let Some(transport) = sse_server.next_transport().await {
+            let proxy = match ChildProxy::spawn(&sse_command) {
+                Ok(proxy) => proxy,
+                Err(e) => {
+                    tracing::error!("failed to spawn `{}`: {e}", sse_command[0]);
+                    continue;
+                }
+            };
+            tokio::spawn(async move {
+                match proxy.serve(transport).await {
+                    Ok(server) => {
+                        let _ = server.waiting().await;
+                    }
+                    Err(e) => tracing::error!("sse serving error: {:?}", e),
+                }
+            });
+        }
+    });
+
+    let app = axum::Router::new()
+        .nest_service(&http.mcp_path, http_service)
+        .merge(sse_router);
+    let listener = tokio::net::TcpListener::bind(&config.address).await?;
+    tracing::info!(
+        "bridging `{}` on http://{}{} and {}",
+        command.join(" "),
+        config.address,
+        http.mcp_path,
+        http.sse_path
+    );
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            let _ = tokio::signal::ctrl_c().await;
+            ct.cancel();
+        })
+        .await?;
+    Ok(())
+}
+
+/// streamable HTTP 端点, 每个会话启动一个子进程
+fn command_service(
+    http: &HttpConfig,
+    command: &[String],
+) -> StreamableHttpService<ChildProxy, LocalSessionManager> {
+    let command = command.to_vec();
+    StreamableHttpService::new(
+        move || ChildProxy::spawn(&command),
+        Arc::new(LocalSessionManager::default()),
+        StreamableHttpServerConfig {
+            sse_keep_alive: http.sse_keep_alive(),
+            // 每个子进程只服务一个会话
+            stateful_mode: true,
+        },
+    )
+}
+
+type Pending = Arc<Mutex<HashMap<RequestId, oneshot::Sender<Result<ServerResult, McpError>>>>>;
+
+/// 一个会话的子进程, 客户端请求按原 id 转发, 子进程的响应按 id 交回
+struct ChildProxy {
+    outbox: mpsc::UnboundedSender<ClientJsonRpcMessage>,
+    pending: Pending,
+    peer: Arc<OnceLock<Peer<RoleServer>>>,
+}
+
+impl ChildProxy {
+    fn spawn(command: &[String]) -> io::Result<Self> {
+        let mut child = tokio::process::Command::new(&command[0])
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()?;
+        let (Some(stdout), Some(stdin)) = (child.stdout.take(), child.stdin.take()) else {
+            return Err(io::Error::other("stdio is not piped"));
+        };
+        tracing::info!(pid = child.id(), "spawned `{}`", command.join(" "));
+
+        let (outbox, rx) = mpsc::unbounded_channel();
+        let proxy = Self {
+            outbox,
+            pending: Pending::default(),
+            peer: Arc::default(),
+        };
+        let transport = AsyncRwTransport::new_client(stdout, stdin);
+        tokio::spawn(pump(
+            child,
+            transport,
+            rx,
+            proxy.pending.clone(),
+            proxy.peer.clone(),
+        ));
+        Ok(proxy)
+    }
+}
+
+fn exited() -> McpError {
+    McpError::internal_error("stdio server exited", None)
+}
+
+/// 转发会话与子进程之间的消息, 会话结束或子进程退出时结束
+async fn pump(
+    mut child: tokio::process::Child,
+    mut transport: impl Transport<RoleClient>,
+    mut outbox: mpsc::UnboundedReceiver<ClientJsonRpcMessage>,
+    pending: Pending,
+    peer: Arc<OnceLock<Peer<RoleServer>>>,
+) {
+    let sends = Outbox::new();
+    let (reply_tx, mut replies) = mpsc::unbounded_channel();
+    loop {
+        tokio::select! {
+            message = outbox.recv() => match message {
+                Some(message) => sends.push(transport.send(message)),
+                None => break,
+            },
+            Some(reply) = replies.recv() => sends.push(transport.send(reply)),
+            message = transport.receive() => match message {
+                Some(message) => from_child(message, &pending, &peer, &reply_tx).await,
+                None => {
+                    tracing::warn!(pid = child.id(), "stdio server exited");
+                    break;
+                }
+            },
+        }
+    }
+    // 先停止接收新请求, 再让等待中的请求返回错误
+    drop(outbox);
+    pending.lock().unwrap().clear();
+    sends.finish().await;
+    let _ = child.kill().await;
+}
+
+async fn from_child(
+    message: ServerJsonRpcMessage,
+    pending: &Pending,
+    peer: &OnceLock<Peer<RoleServer>>,
+    replies: &mpsc::UnboundedSender<ClientJsonRpcMessage>,
+) {
+    let resolve = |id: RequestId, result| {
+        if let Some(tx) = pending.lock().unwrap().remove(&id) {
+            let _ = tx.send(result);
+        }
+    };
+    let Some(peer) = peer.get() else {
+        tracing::warn!("dropping message sent before initialize");
+        return;
+    };
+    match message {
+        JsonRpcMessage::Response(response) => resolve(response.id, Ok(response.result)),
+        JsonRpcMessage::Error(error) => resolve(error.id, Err(error.error)),
+        // 等通知发出后再处理下一条消息, 与之后的响应保持顺序
+        JsonRpcMessage::Notification(notification) => {
+            if let Err(e) = peer.send_notification(notification.notification).await {
+                tracing::warn!("failed to forward notification: {e}");
+            }
+        }
+        JsonRpcMessage::Request(request) => {
+            let peer = peer.clone();
+            let replies = replies.clone();
+            tokio::spawn(async move {
+                let id = request.id;
+                let reply = match peer.send_request(request.request).await {
+                    Ok(result) => ClientJsonRpcMessage::response(result, id),
+                    Err(ServiceError::McpError(e)) => ClientJsonRpcMessage::error(e, id),
+                    Err(e) => ClientJsonRpcMessage::error(
+                        McpError::internal_error(e.to_string(), None),
+                        id,
+                    ),
+                };
+                let _ = replies.send(reply);
+            });
+        }
+        JsonRpcMessage::BatchRequest(_) | JsonRpcMessage::BatchResponse(_) => {
+            tracing::warn!("batch messages from the stdio server are not supported");
+        }
+    }
+}
+
+impl Service<RoleServer> for ChildProxy {
+    async fn handle_request(
+        &self,
+        mut request: ClientRequest,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ServerResult, McpError> {
+        // 第一个请求是 initialize
+        let _ = self.peer.set(context.peer.clone());
+        *request.get_meta_mut() = context.meta;
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(context.id.clone(), tx);
+        let message = ClientJsonRpcMessage::request(request, context.id.clone());
+        if self.outbox.send(message).is_err() {
+            self.pending.lock().unwrap().remove(&context.id);
+            return Err(exited());
+        }
+        // 取消通知由 handle_notification 转发给子进程
+        tokio::select! {
+            result = rx => result.unwrap_or_else(|_| Err(exited())),
+            _ = context.ct.cancelled() => {
+                self.pending.lock().unwrap().remove(&context.id);
+                Err(McpError::internal_error("request cancelled", None))
+            }
+        }
+    }
+
+    async fn handle_notification(
+        &self,
+        mut notification: ClientNotification,
+        context: NotificationContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        *notification.get_meta_mut() = context.meta;
+        self.outbox
+            .send(ClientJsonRpcMessage::notification(notification))
+            .map_err(|_| exited())
+    }
+
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    use super::*;
+    use crate::{calculator, metrics::SessionTransport, state::AppState};
+
+    #[tokio::test]
+    async fn test_relay_preserves_ids() {
+        let state = Arc::new(AppState::new(Config::default()).unwrap());
+        let service = StreamableHttpService::new(
+            move || {
+                Ok(calculator::session_service(
+                    state.clone(),
+                    SessionTransport::StreamableHttp,
+                ))
+            },
+            Arc::new(LocalSessionManager::default()),
+            StreamableHttpServerConfig::default(),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        let app = axum::Router::new().nest_service("/mcp", service);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let (client_io, bridge_io) = tokio::io::duplex(4096);
+        let (read, write) = tokio::io::split(bridge_io);
+        let local = AsyncRwTransport::new_server(read, write);
+        tokio::spawn(relay(local, StreamableHttpClientTransport::from_uri(url)));
+
+        let (read, mut write) = tokio::io::split(client_io);
+        let mut lines = BufReader::new(read).lines();
+        let mut send = async |message: Value| {
+            let line = format!("{message}\n");
+            write.write_all(line.as_bytes()).await.unwrap();
+        };
+        send(
+            json!({"jsonrpc": "2.0", "id": "init", "method": "initialize", "params": {
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "clientInfo": {"name": "test", "version": "1"},
+            }}),
+        )
+        .await;
+        let response: Value =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(response["id"], "init");
+        assert!(response["result"]["serverInfo"].is_object());
+
+        send(json!({"jsonrpc": "2.0", "method": "notifications/initialized"})).await;
+        send(
+            json!({"jsonrpc": "2.0", "id": 42, "method": "tools/call", "params": {
+                "name": "sum",
+                "arguments": {"a": 3, "b": 4},
+            }}),
+        )
+        .await;
+        let response: Value =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(response["id"], 42);
+        assert_eq!(response["result"]["content"][0]["text"], "7");
+    }
+
+    /// 按行应答的 stdio 服务: 回显请求 id, 工具调用先发出两条进度通知, `exit` 工具使进程退出
+    const STDIO_SERVER: &str = r#"
+while read -r line; do
+    id=${line#*\"id\":}
+    id=${id%%,*}
+    case "$line" in
+    *'"method":"initialize"'*)
+        echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"protocolVersion\":\"2025-03-26\",\"capabilities\":{\"tools\":{}},\"serverInfo\":{\"name\":\"echo\",\"version\":\"1\"}}}" ;;
+    *'"name":"exit"'*)
+        exit 0 ;;
+    *'"method":"tools/call"'*)
+        for progress in 1 2; do
+            echo "{\"jsonrpc\":\"2.0\",\"method\":\"notifications/progress\",\"params\":{\"progressToken\":\"call\",\"progress\":$progress}}"
+        done
+        echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"content\":[{\"type\":\"text\",\"text\":\"$id\"}]}}" ;;
+    esac
+done
+"#;
+
+    #[tokio::test]
+    async fn test_serve_command() {
+        let command = ["sh", "-c", STDIO_SERVER].map(String::from);
+        let service = command_service(&HttpConfig::default(), &command);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        let app = axum::Router::new().nest_service("/mcp", service);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let http = reqwest::Client::new();
+        let mut session = None;
+        // 发送一条消息, 返回响应流中的全部消息
+        let mut post = async |message: Value| -> Vec<Value> {
+            let mut request = http
+                .post(&url)
+                .header("accept", "application/json, text/event-stream")
+                .json(&message);
+            if let Some(session) = &session {
+                request = request.header("mcp-session-id", session);
+            }
+            let response = request.send().await.unwrap();
+            assert!(response.status().is_success(), "{message}");
+            if let Some(id) = response.headers().get("mcp-session-id") {
+                session = Some(id.to_str().unwrap().to_string());
+            }
+            let text = response.text().await.unwrap();
+            text.lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .filter_map(|data| serde_json::from_str(data.trim()).ok())
+                .collect()
+        };
+
+        let messages = post(
+            json!({"jsonrpc": "2.0", "id": "init", "method": "initialize", "params": {
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "clientInfo": {"name": "test", "version": "1"},
+            }}),
+        )
+        .await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["id"], "init");
+        assert_eq!(messages[0]["result"]["serverInfo"]["name"], "echo");
+        post(json!({"jsonrpc": "2.0", "method": "notifications/initialized"})).await;
+
+        // 子进程收到原 id, 通知在响应之前按顺序到达
+        let messages = post(
+            json!({"jsonrpc": "2.0", "id": 42, "method": "tools/call", "params": {
+                "name": "echo",
+                "_meta": {"progressToken": "call"},
+            }}),
+        )
+        .await;
+        let progress: Vec<_> = messages
+            .iter()
+            .filter(|message| message["method"] == "notifications/progress")
+            .filter_map(|message| message["params"]["progress"].as_f64())
+            .collect();
+        assert_eq!(progress, [1.0, 2.0]);
+        let response = messages.last().unwrap();
+        assert_eq!(response["id"], 42);
+        assert_eq!(response["result"]["content"][0]["text"], "42");
+        assert_eq!(messages.len(), 3);
+
+        // 子进程退出时等待中的请求与之后的请求都返回错误
+        for (id, name) in [(43, "exit"), (44, "echo")] {
+            let messages = post(
+                json!({"jsonrpc": "2.0", "id": id, "method": "tools/call", "params": {
+                    "name": name,
+                }}),
+            )
+            .await;
+            let response = messages.last().unwrap();
+            assert_eq!(response["id"], id);
+            assert_eq!(response["error"]["message"], "stdio server exited");
+        }
+    }
+}
@@ -1,22 +1,38 @@
+use std::{path::Path as FsPath, sync::Arc};
+
 use rmcp::{
     ErrorData as McpError, RoleServer, ServerHandler,
-    handler::server::{router::tool::ToolRouter, tool::Parameters},
+    handler::server::{
+        router::tool::ToolRouter,
+        tool::{Parameters, ToolCallContext},
+    },
     model::{
-        AnnotateAble, CallToolResult, Content, GetPromptRequestMethod, GetPromptRequestParam,
-        GetPromptResult, Implementation, InitializeRequestParam, InitializeResult, JsonObject,
-        ListPromptsResult, ListResourceTemplatesResult, ListResourcesResult, PaginatedRequestParam,
-        Prompt, PromptArgument, PromptMessage, PromptMessageContent, PromptMessageRole,
-        RawResource, RawResourceTemplate, ReadResourceRequestParam, ReadResourceResult,
-        ResourceContents, ServerCapabilities, ServerInfo,
+        CallToolRequestMethod, CallToolRequestParam, CallToolResult, ConstString, Content,
+        GetPromptRequestMethod, GetPromptRequestParam, GetPromptResult, Implementation,
+        InitializeRequestParam, InitializeResult, JsonObject, ListPromptsResult,
+        ListResourceTemplatesResult, ListResourcesResult, ListToolsResult, PaginatedRequestParam,
+        PromptMessage, PromptMessageContent, PromptMessageRole, ReadResourceRequestMethod,
+        ReadResourceRequestParam, ReadResourceResult, ResourceContents, ServerInfo,
+        SetLevelRequestMethod, SetLevelRequestParam, SubscribeRequestParam,
+        UnsubscribeRequestParam,
     },
     schemars,
     service::RequestContext,
-    tool, tool_handler, tool_router,
+    tool, tool_router,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value, json};
 
-use crate::extract::Path;
+use crate::{
+    extract::Path,
+    identity::CallerIdentity,
+    instrument::{self, Instrumented},
+    metrics::{self, SessionTransport, UNKNOWN},
+    protocol,
+    ratelimit::SessionQuota,
+    registry::Registry,
+    state::AppState,
+};
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct SumRequest {
@@ -50,14 +66,26 @@ pub struct EchoRequest {
 #[derive(Debug, Clone)]
 pub struct Calculator {
     tool_router: ToolRouter<Self>,
+    state: Arc<AppState>,
+    quota: Arc<SessionQuota>,
+}
+
+/// 每个会话的服务实例, 各传输共用
+pub fn session_service(
+    state: Arc<AppState>,
+    transport: SessionTransport,
+) -> Instrumented<Calculator> {
+    Instrumented::new(Calculator::new(state.clone()), state, transport)
 }
 
 /// tool
 #[tool_router]
 impl Calculator {
-    pub fn new() -> Self {
+    pub fn new(state: Arc<AppState>) -> Self {
         Self {
             tool_router: Self::tool_router(),
+            quota: Arc::new(state.limiter.session_quota()),
+            state,
         }
     }
 
@@ -109,9 +137,19 @@ impl Calculator {
 /// resource/prompt
 impl Calculator {
     /// Static resource example - exposing a README file
-    pub fn readme(&self, uri: String) -> Result<ReadResourceResult, McpError> {
+    pub fn readme(&self, path: &FsPath, uri: String) -> Result<ReadResourceResult, McpError> {
         // 读取 README.md 文件内容
-        let text = std::fs::read_to_string("./README.md").unwrap();
+        self.read_file(path, uri)
+    }
+
+    /// 读取配置中声明的文件资源
+    pub fn read_file(&self, path: &FsPath, uri: String) -> Result<ReadResourceResult, McpError> {
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            McpError::internal_error(
+                "failed to read resource",
+                Some(json!({ "uri": uri, "reason": e.to_string() })),
+            )
+        })?;
         Ok(ReadResourceResult {
             contents: vec![ResourceContents::text(text, uri)],
         })
@@ -150,6 +188,49 @@ impl Calculator {
         })
     }
 
+    /// 按模板或静态 URI 路由资源读取, 同时返回匹配的模板用作指标标签
+    fn route_resource<'a>(
+        &self,
+        registry: &'a Registry,
+        uri: String,
+    ) -> (&'a str, Result<ReadResourceResult, McpError>) {
+        const DYNAMIC_RESOURCE: &str = "test://dynamic/resource/{id}";
+        const DOCUMENT: &str = "file:///documents/{name}.text";
+
+        if registry.resource_template_enabled(DYNAMIC_RESOURCE)
+            && let Ok((id,)) = Path::<(i32,)>::extract(&uri, DYNAMIC_RESOURCE)
+        {
+            return (DYNAMIC_RESOURCE, self.dynamic_resource_by_id(&uri, id));
+        }
+        if registry.resource_template_enabled(DOCUMENT)
+            && let Ok((name,)) = Path::<(String,)>::extract(&uri, DOCUMENT)
+        {
+            return (DOCUMENT, self.dynamic_resource_by_name(&uri, &name));
+        }
+        if let Some((file_uri, path)) = registry.files.get_key_value(&uri) {
+            return (file_uri, self.read_file(path, uri));
+        }
+
+        // 静态路由匹配
+        match uri.as_str() {
+            "docs://readme" if registry.resource_enabled(&uri) => {
+                ("docs://readme", self.readme(&registry.readme, uri))
+            }
+            "file:///documents/report.pdf" if registry.resource_enabled(&uri) => {
+                ("file:///documents/report.pdf", self.report_pdf(uri))
+            }
+            _ => (
+                UNKNOWN,
+                Err(McpError::resource_not_found(
+                    "resource_not_found",
+                    Some(json!({
+                        "uri": uri
+                    })),
+                )),
+            ),
+        }
+    }
+
     /// Dynamic resource example - user profiles by ID
     pub fn code_review(
         &self,
@@ -167,38 +248,177 @@ impl Calculator {
     }
 }
 
-#[tool_handler]
 impl ServerHandler for Calculator {
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let metrics = &self.state.metrics;
+        let audit = self.state.audit.begin(
+            &context,
+            CallToolRequestMethod::VALUE,
+            &request.name,
+            request.arguments.as_ref(),
+        );
+        let local = self.tool_router.has_route(&request.name);
+        let gateway = &self.state.gateway;
+        let route = match local {
+            true => None,
+            false => gateway.route_tool(&request.name),
+        };
+        // 先拒绝未知或未启用的工具, 限流与并发配额只按已注册的工具名计数
+        if !(local || route.is_some()) || !self.state.registry().tool_enabled(&request.name) {
+            metrics.observe_tool_call(UNKNOWN, "error");
+            let error = McpError::invalid_params("tool not found", None);
+            audit.finish("error", Some(&error));
+            return Err(error);
+        }
+        let tool = request.name.clone();
+
+        // 没有会话 ID 的传输 (stdio) 以传输名称标识会话
+        let session = instrument::session_id(&context.extensions)
+            .or_else(|| {
+                context
+                    .extensions
+                    .get::<SessionTransport>()
+                    .map(|t| t.as_str())
+            })
+            .unwrap_or(UNKNOWN);
+        let _permit = match self.state.limiter.check_tool_call(
+            &self.quota,
+            session,
+            CallerIdentity::from_context(&context),
+            &tool,
+        ) {
+            Ok(permit) => permit,
+            Err(exceeded) => {
+                metrics.observe_rate_limited(exceeded.scope);
+                metrics.observe_tool_call(&tool, "rate_limited");
+                let error: McpError = exceeded.into();
+                audit.finish("rate_limited", Some(&error));
+                return Err(error);
+            }
+        };
+
+        let _in_flight = metrics.tool_call_in_flight(&tool);
+        let result = match route {
+            Some((downstream, name)) => {
+                gateway.call_tool(downstream, request, name, &context).await
+            }
+            None => {
+                let tcc = ToolCallContext::new(self, request, context);
+                self.tool_router.call(tcc).await
+            }
+        };
+        let outcome = match &result {
+            Ok(CallToolResult {
+                is_error: Some(true),
+                ..
+            }) => "error",
+            result => metrics::outcome(result),
+        };
+        metrics.observe_tool_call(&tool, outcome);
+        audit.finish(outcome, result.as_ref().err());
+        result
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        let registry = self.state.registry();
+        // 路由表无序, 按名称排序使列表稳定
+        let mut local = self.tool_router.list_all();
+        local.sort_by(|a, b| a.name.cmp(&b.name));
+        let tools = local
+            .into_iter()
+            .chain(self.state.gateway.tools())
+            .filter(|tool| registry.tool_enabled(&tool.name))
+            .collect();
+        Ok(ListToolsResult::with_all_items(tools))
+    }
+
+    /// 按客户端请求的版本协商, 不支持时返回错误
     async fn initialize(
         &self,
-        _request: InitializeRequestParam,
+        request: InitializeRequestParam,
         context: RequestContext<RoleServer>,
     ) -> Result<InitializeResult, McpError> {
         if let Some(http_request_part) = context.extensions.get::<axum::http::request::Parts>() {
             let initialize_headers = &http_request_part.headers;
             let initialize_uri = &http_request_part.uri;
-            tracing::info!(?initialize_headers, %initialize_uri, "initialize from http server");
+            let caller = CallerIdentity::from_context(&context);
+            tracing::info!(?initialize_headers, %initialize_uri, ?caller, "initialize from http server");
         }
-        Ok(self.get_info())
+        let protocol_version =
+            protocol::negotiate(&self.state.config.protocol, &request.protocol_version)?;
+        Ok(InitializeResult {
+            protocol_version,
+            ..self.get_info()
+        })
     }
 
     /// 服务器信息
     fn get_info(&self) -> ServerInfo {
+        let mut capabilities = self.state.config.capabilities.to_capabilities();
+        // 只有下游资源会更新, 配置了下游时才支持订阅
+        if let Some(resources) = &mut capabilities.resources
+            && !self.state.gateway.downstreams().is_empty()
+        {
+            resources.subscribe = Some(true);
+        }
         ServerInfo {
             instructions: Some("A simple calculator".into()),
             server_info: Implementation::from_build_env(),
-            capabilities: ServerCapabilities::builder()
-                // .enable_logging()
-                .enable_experimental()
-                .enable_prompts()
-                .enable_resources()
-                .enable_tools()
-                .enable_tool_list_changed()
-                .build(),
+            capabilities,
             ..Default::default()
         }
     }
 
+    /// 会话的日志级别由 [`Instrumented`] 记录, 网关按级别转发下游日志
+    async fn set_level(
+        &self,
+        _request: SetLevelRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        match self.state.config.capabilities.logging {
+            true => Ok(()),
+            false => Err(McpError::method_not_found::<SetLevelRequestMethod>()),
+        }
+    }
+
+    /// 订阅由 [`Instrumented`] 记录, 下游资源同时转发给下游
+    async fn subscribe(
+        &self,
+        SubscribeRequestParam { uri }: SubscribeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let gateway = &self.state.gateway;
+        match gateway.route_uri(&uri) {
+            Some((downstream, uri)) => gateway.subscribe(downstream, uri, true, &context).await,
+            // 本地资源不会更新
+            None => Ok(()),
+        }
+    }
+
+    async fn unsubscribe(
+        &self,
+        UnsubscribeRequestParam { uri }: UnsubscribeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let gateway = &self.state.gateway;
+        // 其他会话仍订阅时保留下游的订阅, 本会话的订阅在返回后才移除
+        let shared = self.state.sessions.subscribers(&uri).len() > 1;
+        match gateway.route_uri(&uri) {
+            Some((downstream, uri)) if !shared => {
+                gateway.subscribe(downstream, uri, false, &context).await
+            }
+            _ => Ok(()),
+        }
+    }
+
     /// 动态 resources
     async fn list_resources(
         &self,
@@ -207,25 +427,11 @@ impl ServerHandler for Calculator {
     ) -> Result<ListResourcesResult, McpError> {
         Ok(ListResourcesResult {
             next_cursor: None,
-            resources: vec![
-                RawResource {
-                    uri: "docs://readme".to_string(),
-                    name: "Project README".to_string(),
-                    description: Some("The project's README file".to_string()),
-                    mime_type: Some("text/markdown".to_string()),
-                    size: None,
-                },
-                RawResource {
-                    uri: "file:///documents/report.pdf".to_string(),
-                    name: "Project README".to_string(),
-                    description: Some("The project's README file".to_string()),
-                    mime_type: Some("text/plain".to_string()),
-                    size: None,
-                },
+            resources: [
+                self.state.registry().resources.clone(),
+                self.state.gateway.resources(),
             ]
-            .into_iter()
-            .map(|v| v.no_annotation())
-            .collect(),
+            .concat(),
         })
     }
 
@@ -237,49 +443,39 @@ impl ServerHandler for Calculator {
     ) -> Result<ListResourceTemplatesResult, McpError> {
         Ok(ListResourceTemplatesResult {
             next_cursor: None,
-            resource_templates: vec![
-                RawResourceTemplate {
-                    uri_template: "test://dynamic/resource/{id}".to_string(),
-                    name: "Dynamic Resource".to_string(),
-                    description: Some("A dynamic resource template. This resource contains the URI parameter `{id}` in its name".to_string()),
-                    mime_type: Some("text/plain".to_string()),
-                },
-                RawResourceTemplate {
-                    uri_template: "file:///documents/{name}.text".to_string(),
-                    name: "read file".to_string(),
-                    description: Some("".to_string()),
-                    mime_type: Some("text/plain".to_string()),
-                },
+            resource_templates: [
+                self.state.registry().resource_templates.clone(),
+                self.state.gateway.resource_templates(),
             ]
-            .into_iter()
-            .map(|v| v.no_annotation())
-            .collect(),
+            .concat(),
         })
     }
 
     async fn read_resource(
         &self,
         ReadResourceRequestParam { uri }: ReadResourceRequestParam,
-        _: RequestContext<RoleServer>,
+        context: RequestContext<RoleServer>,
     ) -> Result<ReadResourceResult, McpError> {
-        if let Ok((id,)) = Path::<(i32,)>::extract(&uri, "test://dynamic/resource/{id}") {
-            return self.dynamic_resource_by_id(&uri, id);
-        }
-        if let Ok((name,)) = Path::<(String,)>::extract(&uri, "file:///documents/{name}.text") {
-            return self.dynamic_resource_by_name(&uri, &name);
-        }
-
-        // 静态路由匹配
-        match uri.as_str() {
-            "docs://readme" => self.readme(uri),
-            "file:///documents/report.pdf" => self.report_pdf(uri),
-            _ => Err(McpError::resource_not_found(
-                "resource_not_found",
-                Some(json!({
-                    "uri": uri
-                })),
-            )),
+        let audit = self
+            .state
+            .audit
+            .begin(&context, ReadResourceRequestMethod::VALUE, &uri, None);
+        let gateway = &self.state.gateway;
+        if let Some((downstream, uri)) = gateway.route_uri(&uri) {
+            let result = gateway.read_resource(downstream, uri, &context).await;
+            let outcome = metrics::outcome(&result);
+            self.state
+                .metrics
+                .observe_resource_read(downstream.name(), outcome);
+            audit.finish(outcome, result.as_ref().err());
+            return result;
         }
+        let registry = self.state.registry();
+        let (template, result) = self.route_resource(&registry, uri);
+        let outcome = metrics::outcome(&result);
+        self.state.metrics.observe_resource_read(template, outcome);
+        audit.finish(outcome, result.as_ref().err());
+        result
     }
 
     async fn list_prompts(
@@ -287,16 +483,16 @@ impl ServerHandler for Calculator {
         _request: Option<PaginatedRequestParam>,
         _context: RequestContext<RoleServer>,
     ) -> Result<ListPromptsResult, McpError> {
+        let registry = self.state.registry();
+        let prompts = registry
+            .prompts
+            .iter()
+            .cloned()
+            .chain(self.state.gateway.prompts())
+            .filter(|prompt| registry.prompt_enabled(&prompt.name))
+            .collect();
         Ok(ListPromptsResult {
-            prompts: vec![Prompt::new(
-                "code_review".to_string(),
-                Some("Code review assistance".to_string()),
-                Some(vec![PromptArgument {
-                    name: "pr_number".to_string(),
-                    description: Some("pr_number is required".to_string()),
-                    required: Some(true),
-                }]),
-            )],
+            prompts,
             next_cursor: None,
         })
     }
@@ -304,11 +500,46 @@ impl ServerHandler for Calculator {
     async fn get_prompt(
         &self,
         GetPromptRequestParam { name, arguments }: GetPromptRequestParam,
-        _context: RequestContext<RoleServer>,
+        context: RequestContext<RoleServer>,
     ) -> Result<GetPromptResult, McpError> {
-        match name.as_str() {
-            "code_review" => self.code_review(&arguments),
-            _ => Err(McpError::method_not_found::<GetPromptRequestMethod>()),
+        let metrics = &self.state.metrics;
+        let audit = self.state.audit.begin(
+            &context,
+            GetPromptRequestMethod::VALUE,
+            &name,
+            arguments.as_ref(),
+        );
+        let registry = self.state.registry();
+        let gateway = &self.state.gateway;
+        let local = registry.prompts.iter().any(|p| p.name == name);
+        let route = match local {
+            true => None,
+            false => gateway.route_prompt(&name),
+        };
+        if (!local && route.is_none()) || !registry.prompt_enabled(&name) {
+            metrics.observe_prompt_render(UNKNOWN, "error");
+            let error = McpError::invalid_params("prompt not found", None);
+            audit.finish("error", Some(&error));
+            return Err(error);
         }
+        let result = match route {
+            Some((downstream, prompt)) => {
+                let request = GetPromptRequestParam {
+                    name: name.clone(),
+                    arguments,
+                };
+                gateway
+                    .get_prompt(downstream, request, prompt, &context)
+                    .await
+            }
+            None => match name.as_str() {
+                "code_review" => self.code_review(&arguments),
+                _ => Err(McpError::invalid_params("prompt not found", None)),
+            },
+        };
+        let outcome = metrics::outcome(&result);
+        metrics.observe_prompt_render(&name, outcome);
+        audit.finish(outcome, result.as_ref().err());
+        result
     }
 }
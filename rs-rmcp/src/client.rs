@@ -0,0 +1,639 @@
+//! MCP 客户端
+//!
+//! [`connect`] 通过 stdio (启动子进程)、SSE 或 streamable HTTP 连接 MCP 服务,
+//! 服务端发来的通知通过通道交给调用方。`client` 子命令基于它列出与调用工具、读取资源、
+//! 渲染提示词和订阅通知, 输出可读文本或供脚本使用的 JSON (`--json`)。
+use std::{ops::Deref, path::Path, process::Stdio};
+
+use clap::Subcommand;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use rmcp::{
+    ErrorData as McpError, Peer, RoleClient, Service, ServiceExt,
+    model::{
+        CallToolRequestParam, ClientInfo, ClientResult, ErrorCode, GetPromptRequestParam,
+        Implementation, JsonObject, ListRootsResult, PromptMessageContent, RawContent,
+        ReadResourceRequestParam, ResourceContents, ServerNotification, ServerRequest,
+        SubscribeRequestParam,
+    },
+    service::{NotificationContext, RequestContext, RunningService},
+    transport::{
+        IntoTransport, SseClientTransport, StreamableHttpClientTransport,
+        sse_client::SseClientConfig, streamable_http_client::StreamableHttpClientTransportConfig,
+    },
+};
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+
+use crate::{
+    config::Config,
+    error::{ClientError, InvalidHeader},
+    repl,
+};
+
+/// 连接目标
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// 启动子进程, 第一个元素是命令
+    Stdio(Vec<String>),
+    Sse(String),
+    Http(String),
+}
+
+/// 解析 `Name: value` 形式的请求头
+pub fn parse_headers(headers: &[String]) -> Result<HeaderMap, InvalidHeader> {
+    headers
+        .iter()
+        .map(|header| {
+            let invalid = || InvalidHeader(header.clone());
+            let (name, value) = header.split_once(':').ok_or_else(invalid)?;
+            let name = HeaderName::try_from(name.trim()).map_err(|_| invalid())?;
+            let value = HeaderValue::try_from(value.trim()).map_err(|_| invalid())?;
+            Ok((name, value))
+        })
+        .collect()
+}
+
+/// 已初始化的客户端会话, 解引用为 [`Peer`] 发送请求
+pub struct McpClient {
+    service: RunningService<RoleClient, Notifications>,
+    child: Option<tokio::process::Child>,
+}
+
+impl Deref for McpClient {
+    type Target = Peer<RoleClient>;
+
+    fn deref(&self) -> &Self::Target {
+        self.service.peer()
+    }
+}
+
+impl McpClient {
+    /// 结束会话, stdio 目标同时结束子进程
+    pub async fn close(self) {
+        if let Err(e) = self.service.cancel().await {
+            tracing::debug!("failed to close client: {e}");
+        }
+        if let Some(mut child) = self.child {
+            let _ = child.kill().await;
+        }
+    }
+}
+
+/// 连接并完成初始化, 返回客户端与服务端通知的接收端; `headers` 只用于 HTTP 目标
+pub async fn connect(
+    target: &Target,
+    headers: HeaderMap,
+) -> Result<(McpClient, mpsc::UnboundedReceiver<ServerNotification>), ClientError> {
+    match target {
+        Target::Stdio(command) => {
+            let program = command.first().ok_or(ClientError::EmptyCommand)?;
+            let mut child = tokio::process::Command::new(program)
+                .args(&command[1..])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::inherit())
+                .kill_on_drop(true)
+                .spawn()
+                .map_err(|source| ClientError::Spawn {
+                    command: program.clone(),
+                    source,
+                })?;
+            let (Some(stdout), Some(stdin)) = (child.stdout.take(), child.stdin.take()) else {
+                return Err(ClientError::Connect("stdio is not piped".to_string()));
+            };
+            let (mut client, notifications) = connect_transport((stdout, stdin)).await?;
+            client.child = Some(child);
+            Ok((client, notifications))
+        }
+        Target::Sse(url) => {
+            let http = reqwest::Client::builder()
+                .default_headers(headers)
+                .build()?;
+            let config = SseClientConfig {
+                sse_endpoint: url.as_str().into(),
+                ..Default::default()
+            };
+            let transport = SseClientTransport::start_with_client(http, config)
+                .await
+                .map_err(|e| ClientError::Connect(e.to_string()))?;
+            connect_transport(transport).await
+        }
+        Target::Http(url) => {
+            let http = reqwest::Client::builder()
+                .default_headers(headers)
+                .build()?;
+            let config = StreamableHttpClientTransportConfig::with_uri(url.as_str());
+            connect_transport(StreamableHttpClientTransport::with_client(http, config)).await
+        }
+    }
+}
+
+/// 在已建立的传输上完成初始化
+pub async fn connect_transport<E, A>(
+    transport: impl IntoTransport<RoleClient, E, A>,
+) -> Result<(McpClient, mpsc::UnboundedReceiver<ServerNotification>), ClientError>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    connect_transport_as(client_info(), transport).await
+}
+
+/// 以指定的 initialize 参数 (`clientInfo` 与能力) 在已建立的传输上完成初始化
+pub async fn connect_transport_as<E, A>(
+    info: ClientInfo,
+    transport: impl IntoTransport<RoleClient, E, A>,
+) -> Result<(McpClient, mpsc::UnboundedReceiver<ServerNotification>), ClientError>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let (tx, rx) = mpsc::unbounded_channel();
+    let service = Notifications(info, tx)
+        .serve(transport)
+        .await
+        .map_err(|e| ClientError::Connect(e.to_string()))?;
+    Ok((
+        McpClient {
+            service,
+            child: None,
+        },
+        rx,
+    ))
+}
+
+/// 本客户端的 initialize 参数
+pub fn client_info() -> ClientInfo {
+    ClientInfo {
+        client_info: Implementation {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        },
+        ..Default::default()
+    }
+}
+
+/// 把服务端通知发到通道, 服务端请求只支持 ping 与 roots/list
+struct Notifications(ClientInfo, mpsc::UnboundedSender<ServerNotification>);
+
+impl Service<RoleClient> for Notifications {
+    async fn handle_request(
+        &self,
+        request: ServerRequest,
+        _context: RequestContext<RoleClient>,
+    ) -> Result<ClientResult, McpError> {
+        match request {
+            ServerRequest::PingRequest(_) => Ok(ClientResult::empty(())),
+            ServerRequest::ListRootsRequest(_) => {
+                Ok(ClientResult::ListRootsResult(ListRootsResult::default()))
+            }
+            ServerRequest::CreateMessageRequest(_) => Err(McpError::new(
+                ErrorCode::METHOD_NOT_FOUND,
+                "sampling is not supported by this client",
+                None,
+            )),
+        }
+    }
+
+    async fn handle_notification(
+        &self,
+        notification: ServerNotification,
+        _context: NotificationContext<RoleClient>,
+    ) -> Result<(), McpError> {
+        let _ = self.1.send(notification);
+        Ok(())
+    }
+
+    fn get_info(&self) -> ClientInfo {
+        self.0.clone()
+    }
+}
+
+/// 连接目标, `client` 与 `conformance` 子命令共用
+#[derive(clap::Args, Debug)]
+pub struct TargetArgs {
+    /// Streamable HTTP endpoint [default: http://<address><http.mcp_path>]
+    #[arg(long, conflicts_with = "stdio")]
+    url: Option<String>,
+
+    /// Connect to --url with the SSE transport
+    #[arg(long, requires = "url")]
+    sse: bool,
+
+    /// Spawn a stdio server, e.g. "rs-mcpr -t stdio --log-level warn"
+    #[arg(long, value_name = "COMMAND")]
+    stdio: Option<String>,
+
+    /// Spawn this binary's server over stdio, no network needed
+    #[arg(long, conflicts_with_all = ["url", "stdio"])]
+    local: bool,
+
+    /// Extra request header (`Name: value`), may be repeated
+    #[arg(long = "header", value_name = "HEADER")]
+    pub headers: Vec<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ClientArgs {
+    #[command(flatten)]
+    target: TargetArgs,
+
+    /// Print results and notifications as JSON
+    #[arg(long)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: ClientCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ClientCommand {
+    /// List tools
+    Tools,
+    /// Call a tool; arguments are KEY=VALUE, VALUE is parsed as JSON and
+    /// falls back to a string
+    Call { name: String, args: Vec<String> },
+    /// List resources and resource templates
+    Resources,
+    /// Read a resource
+    Read { uri: String },
+    /// List prompts
+    Prompts,
+    /// Render a prompt; arguments are KEY=VALUE
+    Prompt { name: String, args: Vec<String> },
+    /// Subscribe to resource updates and print notifications until Ctrl+C
+    Watch { uris: Vec<String> },
+    /// Interactive shell with completion of tool names, resource URIs and
+    /// prompt names
+    Repl,
+}
+
+impl TargetArgs {
+    /// `--stdio` 按 shell 规则拆分, `--local` 以 stdio 传输启动本程序并沿用配置文件,
+    /// 未指定目标时连接本机配置的 streamable HTTP 端点
+    pub fn target(
+        &self,
+        config: &Config,
+        config_path: Option<&Path>,
+    ) -> Result<Target, ClientError> {
+        if self.local {
+            let exe = std::env::current_exe().map_err(|source| ClientError::Spawn {
+                command: "rs-mcpr".to_string(),
+                source,
+            })?;
+            let mut command = vec![exe.to_string_lossy().into_owned()];
+            if let Some(path) = config_path {
+                command.extend(["--config".to_string(), path.to_string_lossy().into_owned()]);
+            }
+            command.extend(["--transport", "stdio", "--log-level", "warn"].map(String::from));
+            return Ok(Target::Stdio(command));
+        }
+        if let Some(command) = &self.stdio {
+            let command = shlex::split(command).ok_or(ClientError::EmptyCommand)?;
+            return Ok(Target::Stdio(command));
+        }
+        Ok(match &self.url {
+            Some(url) if self.sse => Target::Sse(url.clone()),
+            Some(url) => Target::Http(url.clone()),
+            None => Target::Http(format!("http://{}{}", config.address, config.http.mcp_path)),
+        })
+    }
+}
+
+/// 执行 `client` 子命令
+pub async fn run(
+    config: &Config,
+    config_path: Option<&Path>,
+    args: &ClientArgs,
+) -> Result<(), ClientError> {
+    let target = args.target.target(config, config_path)?;
+    let headers = parse_headers(&args.target.headers)?;
+    let (client, mut notifications) = connect(&target, headers).await?;
+    let json = args.json;
+
+    if let ClientCommand::Repl = &args.command {
+        return repl::run(client, notifications, &target, json).await;
+    }
+
+    if let ClientCommand::Watch { uris } = &args.command {
+        for uri in uris {
+            client
+                .subscribe(SubscribeRequestParam { uri: uri.clone() })
+                .await?;
+        }
+        loop {
+            tokio::select! {
+                notification = notifications.recv() => match notification {
+                    Some(notification) => println!("{}", format_notification(&notification, json)),
+                    None => break,
+                },
+                _ = tokio::signal::ctrl_c() => break,
+            }
+        }
+        client.close().await;
+        return Ok(());
+    }
+
+    // 其他命令执行期间的通知 (如进度) 输出到 stderr
+    let printer = tokio::spawn(async move {
+        while let Some(notification) = notifications.recv().await {
+            eprintln!("{}", format_notification(&notification, json));
+        }
+    });
+    let result = execute(&client, &args.command, json).await;
+    client.close().await;
+    printer.abort();
+    result
+}
+
+pub(crate) async fn execute(
+    client: &McpClient,
+    command: &ClientCommand,
+    json: bool,
+) -> Result<(), ClientError> {
+    match command {
+        ClientCommand::Tools => {
+            let tools = client.list_all_tools().await?;
+            if json {
+                return print_json(&json!({ "tools": tools }));
+            }
+            for tool in tools {
+                println!("{}{}", tool.name, describe(tool.description.as_deref()));
+                let required = tool.input_schema.get("required").and_then(Value::as_array);
+                let properties = tool
+                    .input_schema
+                    .get("properties")
+                    .and_then(Value::as_object);
+                for (name, schema) in properties.into_iter().flatten() {
+                    let kind = schema.get("type").and_then(Value::as_str).unwrap_or("any");
+                    let required = required.is_some_and(|r| r.iter().any(|v| v == name));
+                    println!(
+                        "    {name}: {kind}{}",
+                        if required { " (required)" } else { "" }
+                    );
+                }
+            }
+        }
+        ClientCommand::Call { name, args } => {
+            call(client, name, parse_arguments(args, true)?, json).await?;
+        }
+        ClientCommand::Resources => {
+            let resources = client.list_all_resources().await?;
+            let templates = client.list_all_resource_templates().await?;
+            if json {
+                return print_json(&json!({
+                    "resources": resources,
+                    "resourceTemplates": templates,
+                }));
+            }
+            for resource in resources {
+                let mime = resource.mime_type.as_deref().unwrap_or("-");
+                println!("{} {} ({mime})", resource.uri, resource.name);
+            }
+            for template in templates {
+                println!("{} {} (template)", template.uri_template, template.name);
+            }
+        }
+        ClientCommand::Read { uri } => {
+            let result = client
+                .read_resource(ReadResourceRequestParam { uri: uri.clone() })
+                .await?;
+            if json {
+                return print_json(&result);
+            }
+            for contents in result.contents {
+                match contents {
+                    ResourceContents::TextResourceContents { text, .. } => println!("{text}"),
+                    ResourceContents::BlobResourceContents {
+                        blob, mime_type, ..
+                    } => println!(
+                        "[blob {}, {} base64 bytes]",
+                        mime_type.as_deref().unwrap_or("-"),
+                        blob.len()
+                    ),
+                }
+            }
+        }
+        ClientCommand::Prompts => {
+            let prompts = client.list_all_prompts().await?;
+            if json {
+                return print_json(&json!({ "prompts": prompts }));
+            }
+            for prompt in prompts {
+                println!("{}{}", prompt.name, describe(prompt.description.as_deref()));
+                for argument in prompt.arguments.iter().flatten() {
+                    println!(
+                        "    {}{}{}",
+                        argument.name,
+                        if argument.required == Some(true) {
+                            " (required)"
+                        } else {
+                            ""
+                        },
+                        describe(argument.description.as_deref())
+                    );
+                }
+            }
+        }
+        ClientCommand::Prompt { name, args } => {
+            render_prompt(client, name, parse_arguments(args, false)?, json).await?;
+        }
+        ClientCommand::Watch { .. } | ClientCommand::Repl => unreachable!("handled by run"),
+    }
+    Ok(())
+}
+
+/// 调用工具并输出结果, 工具返回 `isError` 时返回错误
+pub(crate) async fn call(
+    client: &McpClient,
+    name: &str,
+    arguments: JsonObject,
+    json: bool,
+) -> Result<(), ClientError> {
+    let result = client
+        .call_tool(CallToolRequestParam {
+            name: name.to_string().into(),
+            arguments: Some(arguments),
+        })
+        .await?;
+    if json {
+        print_json(&result)?;
+    } else {
+        for content in result.content.iter().flatten() {
+            println!("{}", format_content(&content.raw));
+        }
+        if let Some(structured) = &result.structured_content {
+            println!("{}", serde_json::to_string_pretty(structured)?);
+        }
+    }
+    if result.is_error == Some(true) {
+        return Err(ClientError::ToolFailed(name.to_string()));
+    }
+    Ok(())
+}
+
+/// 渲染提示词并输出消息
+pub(crate) async fn render_prompt(
+    client: &McpClient,
+    name: &str,
+    arguments: JsonObject,
+    json: bool,
+) -> Result<(), ClientError> {
+    let result = client
+        .get_prompt(GetPromptRequestParam {
+            name: name.to_string(),
+            arguments: Some(arguments),
+        })
+        .await?;
+    if json {
+        return print_json(&result);
+    }
+    for message in result.messages {
+        let content = match &message.content {
+            PromptMessageContent::Text { text } => text.clone(),
+            PromptMessageContent::Image { image } => format!("[image {}]", image.mime_type),
+            PromptMessageContent::Resource { resource } => {
+                format_content(&RawContent::Resource(resource.raw.clone()))
+            }
+        };
+        println!("{:?}: {content}", message.role);
+    }
+    Ok(())
+}
+
+/// 解析 `KEY=VALUE` 参数, `json` 为 true 时 VALUE 优先按 JSON 解析
+pub(crate) fn parse_arguments(args: &[String], json: bool) -> Result<JsonObject, ClientError> {
+    args.iter()
+        .map(|arg| {
+            let (key, value) = arg
+                .split_once('=')
+                .ok_or_else(|| ClientError::InvalidArgument(arg.clone()))?;
+            let value = match serde_json::from_str(value) {
+                Ok(value) if json => value,
+                _ => Value::String(value.to_string()),
+            };
+            Ok((key.to_string(), value))
+        })
+        .collect()
+}
+
+pub(crate) fn describe(description: Option<&str>) -> String {
+    description.map(|d| format!(" - {d}")).unwrap_or_default()
+}
+
+pub(crate) fn print_json(value: &impl serde::Serialize) -> Result<(), ClientError> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn format_content(content: &RawContent) -> String {
+    match content {
+        RawContent::Text(text) => text.text.clone(),
+        RawContent::Image(image) => format!("[image {}]", image.mime_type),
+        RawContent::Audio(audio) => format!("[audio {}]", audio.raw.mime_type),
+        RawContent::Resource(resource) => match &resource.resource {
+            ResourceContents::TextResourceContents { text, .. } => text.clone(),
+            ResourceContents::BlobResourceContents { uri, .. } => format!("[blob {uri}]"),
+        },
+    }
+}
+
+pub(crate) fn format_notification(notification: &ServerNotification, json: bool) -> String {
+    if json {
+        return serde_json::to_string(notification).unwrap_or_default();
+    }
+    match notification {
+        ServerNotification::ProgressNotification(n) => {
+            let p = &n.params;
+            let total = p.total.map(|t| format!("/{t}")).unwrap_or_default();
+            let message = p
+                .message
+                .as_deref()
+                .map(|m| format!(" {m}"))
+                .unwrap_or_default();
+            format!("progress {}{total}{message}", p.progress)
+        }
+        ServerNotification::LoggingMessageNotification(n) => {
+            let p = &n.params;
+            let logger = p
+                .logger
+                .as_deref()
+                .map(|l| format!(" {l}:"))
+                .unwrap_or_default();
+            format!("[{:?}]{logger} {}", p.level, p.data)
+        }
+        ServerNotification::ResourceUpdatedNotification(n) => {
+            format!("resource updated: {}", n.params.uri)
+        }
+        ServerNotification::ResourceListChangedNotification(_) => "resource list changed".into(),
+        ServerNotification::ToolListChangedNotification(_) => "tool list changed".into(),
+        ServerNotification::PromptListChangedNotification(_) => "prompt list changed".into(),
+        ServerNotification::CancelledNotification(n) => format!(
+            "request {} cancelled{}",
+            n.params.request_id,
+            n.params
+                .reason
+                .as_deref()
+                .map(|r| format!(": {r}"))
+                .unwrap_or_default()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rmcp::transport::{
+        StreamableHttpServerConfig, StreamableHttpService,
+        streamable_http_server::session::local::LocalSessionManager,
+    };
+
+    use super::*;
+    use crate::{calculator, metrics::SessionTransport, state::AppState};
+
+    #[tokio::test]
+    async fn test_connect_and_call() {
+        let state = Arc::new(AppState::new(Config::default()).unwrap());
+        let service = StreamableHttpService::new(
+            move || {
+                Ok(calculator::session_service(
+                    state.clone(),
+                    SessionTransport::StreamableHttp,
+                ))
+            },
+            Arc::new(LocalSessionManager::default()),
+            StreamableHttpServerConfig::default(),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        let app = axum::Router::new().nest_service("/mcp", service);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let headers = parse_headers(&["X-Test: 1".to_string()]).unwrap();
+        let (client, _notifications) = connect(&Target::Http(url), headers).await.unwrap();
+        assert!(
+            client
+                .list_all_tools()
+                .await
+                .unwrap()
+                .iter()
+                .any(|t| t.name == "sum")
+        );
+        let arguments = parse_arguments(&["a=3".to_string(), "b=4".to_string()], true).unwrap();
+        let result = client
+            .call_tool(CallToolRequestParam {
+                name: "sum".into(),
+                arguments: Some(arguments),
+            })
+            .await
+            .unwrap();
+        let content = result.content.unwrap();
+        assert_eq!(format_content(&content[0].raw), "7");
+        client.close().await;
+
+        // 提示词参数保持字符串
+        let arguments = parse_arguments(&["pr_number=12".to_string()], false).unwrap();
+        assert_eq!(arguments["pr_number"], "12");
+        assert!(parse_arguments(&["missing".to_string()], true).is_err());
+        assert!(parse_headers(&["no colon".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+//! 客户端画像
+//!
+//! initialize 成功后, [`crate::instrument::Instrumented`] 根据客户端信息、声明的能力与
+//! 协商的协议版本生成 [`ClientProfile`] 并保存在会话中, 之后每个请求与通知都能从扩展中取得
+//! (`context.extensions.get::<ClientProfile>()`)。
+//!
+//! 已知有兼容问题的客户端按 `[[clients.quirks]]` 表识别, 以 `clientInfo.name` 的子串匹配
+//! (不区分大小写)。服务端据此自动调整行为: 不向忽略 list_changed 的客户端推送通知,
+//! 为无法解析新字段的客户端按旧版本降级响应。
+//!
+//! rmcp 0.5 的 `ClientCapabilities` 只有 `roots`、`sampling` 与 `experimental`,
+//! 客户端声明的 `elicitation` 在解析时丢失; 服务端也不会发起 elicitation。
+use rmcp::model::{Implementation, InitializeRequestParam, ProtocolVersion};
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{Feature, Negotiated};
+
+/// 客户端的兼容问题
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quirk {
+    /// 不处理 `notifications/*/list_changed`, 不再推送
+    IgnoresListChanged,
+    /// 无法解析 `outputSchema` 与 `structuredContent`
+    NoStructuredOutput,
+    /// 无法解析工具的 `annotations`
+    NoToolAnnotations,
+}
+
+/// 一条识别规则
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuirkRule {
+    /// `clientInfo.name` 的子串, 不区分大小写
+    pub client: String,
+    pub quirks: Vec<Quirk>,
+}
+
+/// 客户端识别配置, 对应配置文件的 `[clients]` 表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientsConfig {
+    pub quirks: Vec<QuirkRule>,
+}
+
+impl Default for ClientsConfig {
+    fn default() -> Self {
+        let rule = |client: &str, quirks: &[Quirk]| QuirkRule {
+            client: client.to_string(),
+            quirks: quirks.to_vec(),
+        };
+        Self {
+            quirks: vec![
+                // 返回 Json<T> 的工具在 Inspector 中解析异常
+                rule("inspector", &[Quirk::NoStructuredOutput]),
+                rule("cline", &[Quirk::IgnoresListChanged]),
+                rule("codebuddy", &[Quirk::IgnoresListChanged]),
+            ],
+        }
+    }
+}
+
+impl ClientsConfig {
+    /// 校验识别规则, 返回出错的字段与原因
+    pub fn check(&self) -> Result<(), (String, String)> {
+        match self
+            .quirks
+            .iter()
+            .position(|rule| rule.client.trim().is_empty())
+        {
+            Some(i) => Err((
+                format!("clients.quirks[{i}].client"),
+                "must not be empty".into(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// 匹配 `name` 的所有规则的兼容问题, 已排序去重
+    pub fn quirks(&self, name: &str) -> Vec<Quirk> {
+        let name = name.to_lowercase();
+        let mut quirks: Vec<Quirk> = self
+            .quirks
+            .iter()
+            .filter(|rule| name.contains(&rule.client.to_lowercase()))
+            .flat_map(|rule| rule.quirks.iter().copied())
+            .collect();
+        quirks.sort();
+        quirks.dedup();
+        quirks
+    }
+}
+
+/// 会话的客户端画像
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientProfile {
+    /// initialize 中的 `clientInfo`, 无状态的 streamable HTTP 请求没有
+    pub client_info: Option<Implementation>,
+    pub negotiated: Negotiated,
+    pub quirks: Vec<Quirk>,
+}
+
+impl ClientProfile {
+    pub fn new(
+        request: &InitializeRequestParam,
+        version: ProtocolVersion,
+        config: &ClientsConfig,
+    ) -> Self {
+        Self {
+            quirks: config.quirks(&request.client_info.name),
+            client_info: Some(request.client_info.clone()),
+            negotiated: Negotiated {
+                version,
+                capabilities: request.capabilities.clone(),
+            },
+        }
+    }
+
+    /// 没有 initialize 的请求, 只知道协议版本
+    pub fn from_version(version: ProtocolVersion) -> Self {
+        Self {
+            negotiated: Negotiated {
+                version,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn has_quirk(&self, quirk: Quirk) -> bool {
+        self.quirks.contains(&quirk)
+    }
+
+    /// 协商的版本包含该特性, 且客户端没有相应的兼容问题
+    pub fn supports(&self, feature: Feature) -> bool {
+        let blocked = match feature {
+            Feature::StructuredOutput => self.has_quirk(Quirk::NoStructuredOutput),
+            Feature::ToolAnnotations => self.has_quirk(Quirk::NoToolAnnotations),
+        };
+        !blocked && self.negotiated.supports(feature)
+    }
+
+    /// 是否推送工具、提示词与资源的 list_changed 通知
+    pub fn wants_list_changed(&self) -> bool {
+        !self.has_quirk(Quirk::IgnoresListChanged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use rmcp::model::{ClientCapabilities, ClientInfo, ServerNotification};
+
+    use super::*;
+    use crate::{
+        calculator,
+        config::Config,
+        metrics::SessionTransport,
+        registry::{Registry, RegistryConfig},
+        reload,
+        state::AppState,
+        testing::Harness,
+    };
+
+    fn client(name: &str) -> ClientInfo {
+        ClientInfo {
+            client_info: Implementation {
+                name: name.to_string(),
+                version: "1.0.0".to_string(),
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_quirks() {
+        let config = ClientsConfig::default();
+        assert_eq!(config.quirks("mcp-inspector"), [Quirk::NoStructuredOutput]);
+        assert_eq!(config.quirks("Cline"), [Quirk::IgnoresListChanged]);
+        assert_eq!(config.quirks("CodeBuddy"), [Quirk::IgnoresListChanged]);
+        assert!(config.quirks("claude-ai").is_empty());
+
+        let mut request = client("Cline");
+        request.capabilities = ClientCapabilities::builder()
+            .enable_roots()
+            .enable_roots_list_changed()
+            .build();
+        let profile = ClientProfile::new(&request, ProtocolVersion::V_2025_03_26, &config);
+        assert!(!profile.wants_list_changed());
+        assert_eq!(profile.negotiated.capabilities, request.capabilities);
+
+        let inspector = client("mcp-inspector");
+        let version = crate::protocol::protocol_version("2025-06-18");
+        let profile = ClientProfile::new(&inspector, version, &config);
+        assert!(!profile.supports(Feature::StructuredOutput));
+        assert!(profile.supports(Feature::ToolAnnotations));
+        assert!(profile.wants_list_changed());
+
+        let config = ClientsConfig {
+            quirks: vec![QuirkRule {
+                client: " ".to_string(),
+                quirks: vec![],
+            }],
+        };
+        assert!(config.check().is_err());
+    }
+
+    /// 以指定 clientInfo 连接共享状态的计算器
+    async fn connect(state: &Arc<AppState>, name: &str) -> Harness {
+        let service = calculator::session_service(state.clone(), SessionTransport::Stdio);
+        Harness::duplex_as(client(name), service).await
+    }
+
+    #[tokio::test]
+    async fn test_list_changed_by_profile() {
+        let state = Arc::new(AppState::new(Config::default()).unwrap());
+        let mut cline = connect(&state, "Cline").await;
+        let mut other = connect(&state, "other-client").await;
+        for _ in 0..50 {
+            let sessions = state.sessions.list();
+            if sessions.iter().filter(|s| s.is_initialized()).count() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let profiles: Vec<_> = state
+            .sessions
+            .list()
+            .iter()
+            .filter_map(|session| session.profile())
+            .collect();
+        assert_eq!(profiles.len(), 2);
+        assert!(
+            profiles
+                .iter()
+                .any(|profile| profile.quirks == [Quirk::IgnoresListChanged])
+        );
+
+        let config = Config {
+            registry: RegistryConfig {
+                tools: vec!["sum".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let changes = state.replace_registry(Registry::new(&config));
+        reload::notify(&state, &changes);
+        other
+            .expect_notification(|n| {
+                matches!(n, ServerNotification::ToolListChangedNotification(_))
+            })
+            .await;
+        cline
+            .assert_no_notification(Duration::from_millis(100))
+            .await;
+
+        cline.close().await;
+        other.close().await;
+    }
+}
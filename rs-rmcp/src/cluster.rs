@@ -0,0 +1,641 @@
+//! 多副本部署的 streamable HTTP 会话
+//!
+//! rmcp 的会话是进程内的 worker, 无法在副本之间迁移。[`crate::http_sessions::HttpSessionManager`]
+//! 创建会话时把会话 ID 与所在副本登记到共享的 [`SessionStore`],
+//! 请求落到其他副本时由 [`forward`] 中间件转发给会话所在的副本, 因此负载均衡器
+//! 无需会话保持。
+//!
+//! 转发的请求带 [`FORWARDED_BY`] 头, 收到该头的副本不再转发, 避免循环。
+//!
+//! 启用 TLS 时副本之间使用 HTTPS, 以 `cluster.ca` 校验对方证书, 对方要求 mTLS 时出示
+//! `cluster.client_cert`。配置了 `cluster.token` 时转发的请求携带该令牌, 并在
+//! [`FORWARDED_IDENTITY`] 头中传递原调用方的身份; [`authenticate`] 中间件只接受携带正确
+//! 令牌的转发头, 用其替换转发副本自身的证书身份, 使限流、会话上限与审计仍按原调用方计算。
+use std::{
+    fmt::Debug,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    admin,
+    error::{ClusterError, Error},
+    identity::CallerIdentity,
+    session_limits::{SESSION_EXPIRED, error_body},
+    state::AppState,
+    tls,
+};
+
+/// 转发请求的来源副本
+pub const FORWARDED_BY: HeaderName = HeaderName::from_static("x-rs-mcpr-forwarded-by");
+/// 转发请求的原调用方身份
+pub const FORWARDED_IDENTITY: HeaderName = HeaderName::from_static("x-rs-mcpr-forwarded-identity");
+/// 副本之间共享的令牌
+pub const CLUSTER_TOKEN: HeaderName = HeaderName::from_static("x-rs-mcpr-cluster-token");
+
+/// 会话存储类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    /// 共享目录, 每个会话一个文件
+    File,
+}
+
+/// 多副本配置, 对应配置文件的 `[cluster]` 表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClusterConfig {
+    pub enabled: bool,
+    /// 其他副本访问本副本的地址, 如 `http://10.0.0.1:8000`, 启用 TLS 时为 `https://`
+    pub advertise_url: Option<String>,
+    /// 副本之间共享的令牌, 设置后才转发调用方身份, 启用 mTLS 时必填
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// 校验其他副本服务端证书的 CA (PEM), `https://` 地址必填
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca: Option<PathBuf>,
+    /// 连接要求 mTLS 的副本时出示的客户端证书 (PEM)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<PathBuf>,
+    /// 客户端证书私钥 (PEM)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<PathBuf>,
+    pub store: SessionStoreKind,
+    /// `file` 存储使用的共享目录
+    pub path: PathBuf,
+    /// 连接其他副本的超时 (秒)
+    pub connect_timeout_secs: u64,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            advertise_url: None,
+            token: None,
+            ca: None,
+            client_cert: None,
+            client_key: None,
+            store: SessionStoreKind::File,
+            path: PathBuf::from("sessions"),
+            connect_timeout_secs: 5,
+        }
+    }
+}
+
+/// 会话 ID 到所在副本地址的共享存储
+pub trait SessionStore: Debug + Send + Sync {
+    /// 登记会话所在副本
+    fn insert(&self, id: &str, owner: &str) -> io::Result<()>;
+
+    /// 会话所在副本, 未登记时返回 `None`
+    fn owner(&self, id: &str) -> io::Result<Option<String>>;
+
+    fn remove(&self, id: &str) -> io::Result<()>;
+
+    /// 移除某个副本的所有会话
+    fn remove_owner(&self, owner: &str) -> io::Result<()>;
+}
+
+/// 共享目录存储, 适用于本地测试或挂载了共享卷的部署
+///
+/// 每个会话一个文件, 文件名为会话 ID, 内容为所在副本地址, 先写临时文件再重命名。
+#[derive(Debug)]
+pub struct FileSessionStore {
+    dir: PathBuf,
+}
+
+impl FileSessionStore {
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn file(&self, id: &str) -> io::Result<PathBuf> {
+        let valid = !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid session id `{id}`"),
+            ));
+        }
+        Ok(self.dir.join(id))
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn insert(&self, id: &str, owner: &str) -> io::Result<()> {
+        let file = self.file(id)?;
+        let tmp = self.dir.join(format!(".{id}.tmp"));
+        std::fs::write(&tmp, owner)?;
+        std::fs::rename(tmp, file)
+    }
+
+    fn owner(&self, id: &str) -> io::Result<Option<String>> {
+        match std::fs::read_to_string(self.file(id)?) {
+            Ok(owner) => Ok(Some(owner)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn remove(&self, id: &str) -> io::Result<()> {
+        match std::fs::remove_file(self.file(id)?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn remove_owner(&self, owner: &str) -> io::Result<()> {
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(id) = name.to_str().filter(|name| !name.starts_with('.')) else {
+                continue;
+            };
+            if self.owner(id)?.as_deref() == Some(owner) {
+                self.remove(id)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 本副本在集群中的身份与转发客户端
+#[derive(Debug)]
+pub struct Cluster {
+    store: Box<dyn SessionStore>,
+    advertise_url: String,
+    token: Option<String>,
+    http: reqwest::Client,
+}
+
+impl Cluster {
+    pub fn new(config: &ClusterConfig) -> Result<Self, Error> {
+        let store = match config.store {
+            SessionStoreKind::File => Box::new(FileSessionStore::new(&config.path)?),
+        };
+        Self::with_store(store, config)
+    }
+
+    pub fn with_store(store: Box<dyn SessionStore>, config: &ClusterConfig) -> Result<Self, Error> {
+        let advertise_url = config
+            .advertise_url
+            .clone()
+            .ok_or(ClusterError::MissingAdvertiseUrl)?;
+        // 上次异常退出时遗留的会话
+        store.remove_owner(&advertise_url)?;
+        let mut http = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .redirect(reqwest::redirect::Policy::none())
+            .no_proxy();
+        if let Some(ca) = &config.ca {
+            let client_auth = config
+                .client_cert
+                .as_deref()
+                .zip(config.client_key.as_deref());
+            http = http.use_preconfigured_tls(tls::build_client_config(ca, client_auth)?);
+        }
+        Ok(Self {
+            store,
+            advertise_url,
+            token: config.token.clone(),
+            http: http.build()?,
+        })
+    }
+
+    /// 登记本副本的会话
+    pub fn register(&self, id: &str) -> io::Result<()> {
+        self.store.insert(id, &self.advertise_url)
+    }
+
+    pub fn unregister(&self, id: &str) -> io::Result<()> {
+        self.store.remove(id)
+    }
+
+    /// 移除本副本的所有会话, 副本退出时调用
+    pub fn deregister(&self) -> io::Result<()> {
+        self.store.remove_owner(&self.advertise_url)
+    }
+
+    /// 把请求原样转发给会话所在的副本
+    async fn forward(&self, id: &str, owner: &str, req: Request) -> Response {
+        let (parts, body) = req.into_parts();
+        let path = parts.uri.path_and_query().map_or("/", |p| p.as_str());
+        let url = format!("{}{path}", owner.trim_end_matches('/'));
+        let mut headers = parts.headers;
+        strip_hop_by_hop(&mut headers);
+        headers.insert(FORWARDED_BY, self.advertise_url.parse().expect("valid url"));
+        if let Some(token) = &self.token
+            && let Ok(token) = token.parse()
+        {
+            headers.insert(CLUSTER_TOKEN, token);
+            if let Some(identity) = parts.extensions.get::<CallerIdentity>()
+                && let Ok(identity) = identity.0.parse()
+            {
+                headers.insert(FORWARDED_IDENTITY, identity);
+            }
+        }
+
+        let result = self
+            .http
+            .request(parts.method, &url)
+            .headers(headers)
+            .body(reqwest::Body::wrap_stream(body.into_data_stream()))
+            .send()
+            .await;
+        match result {
+            Ok(response) => {
+                let mut builder = Response::builder().status(response.status());
+                let mut headers = response.headers().clone();
+                strip_hop_by_hop(&mut headers);
+                if let Some(h) = builder.headers_mut() {
+                    *h = headers;
+                }
+                builder
+                    .body(Body::from_stream(response.bytes_stream()))
+                    .unwrap_or_else(|_| StatusCode::BAD_GATEWAY.into_response())
+            }
+            Err(e) if e.is_connect() => {
+                tracing::warn!(session = id, owner, "replica is unreachable: {e}");
+                if let Err(e) = self.store.remove(id) {
+                    tracing::error!(session = id, "failed to remove session: {e}");
+                }
+                let message = format!(
+                    "session {id} expired: replica {owner} is unreachable; send a new initialize request"
+                );
+                (StatusCode::NOT_FOUND, error_body(SESSION_EXPIRED, message)).into_response()
+            }
+            Err(e) => {
+                tracing::error!(session = id, owner, "failed to forward request: {e}");
+                (StatusCode::BAD_GATEWAY, "failed to forward request").into_response()
+            }
+        }
+    }
+}
+
+fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    for name in [
+        header::CONNECTION,
+        header::TRANSFER_ENCODING,
+        header::TE,
+        header::TRAILER,
+        header::UPGRADE,
+        header::PROXY_AUTHORIZATION,
+        header::PROXY_AUTHENTICATE,
+        HeaderName::from_static("keep-alive"),
+    ] {
+        headers.remove(name);
+    }
+}
+
+/// 校验其他副本转发的请求, 以转发头中的原调用方身份替换转发副本的身份
+///
+/// 不是由持有 `cluster.token` 的副本发出却带有转发头的请求返回 403。
+pub async fn authenticate(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let headers = req.headers();
+    if ![&FORWARDED_BY, &FORWARDED_IDENTITY, &CLUSTER_TOKEN]
+        .into_iter()
+        .any(|name| headers.contains_key(name))
+    {
+        return next.run(req).await;
+    }
+    let presented = headers.get(CLUSTER_TOKEN).map(|v| v.as_bytes());
+    let authenticated = match state.http_sessions.cluster() {
+        Some(cluster) => match (&cluster.token, presented) {
+            (Some(token), Some(presented)) => admin::constant_time_eq(presented, token.as_bytes()),
+            // 未配置令牌时只接受不带身份的转发
+            (None, None) => !headers.contains_key(FORWARDED_IDENTITY),
+            _ => false,
+        },
+        None => false,
+    };
+    if !authenticated {
+        tracing::warn!("rejected a forwarded request without a valid cluster token");
+        return (
+            StatusCode::FORBIDDEN,
+            "forwarded request is not authenticated",
+        )
+            .into_response();
+    }
+
+    let identity = headers
+        .get(FORWARDED_IDENTITY)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| CallerIdentity(v.to_string()));
+    req.headers_mut().remove(CLUSTER_TOKEN);
+    req.headers_mut().remove(FORWARDED_IDENTITY);
+    // mTLS 时扩展中是转发副本的证书主题, 不是调用方
+    req.extensions_mut().remove::<CallerIdentity>();
+    if let Some(identity) = identity {
+        req.extensions_mut().insert(identity);
+    }
+    next.run(req).await
+}
+
+/// streamable HTTP 中间件: 会话不在本副本时转发给所在副本
+pub async fn forward(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let sessions = &state.http_sessions;
+    let Some(cluster) = sessions.cluster() else {
+        return next.run(req).await;
+    };
+    let Some(id) = req
+        .headers()
+        .get("mcp-session-id")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+    else {
+        return next.run(req).await;
+    };
+    if req.headers().contains_key(FORWARDED_BY) || sessions.is_local(&id).await {
+        return next.run(req).await;
+    }
+
+    match cluster.store.owner(&id) {
+        Ok(Some(owner)) if owner != cluster.advertise_url => {
+            tracing::debug!(session = id, owner, "forwarding request");
+            cluster.forward(&id, &owner, req).await
+        }
+        Ok(_) => next.run(req).await,
+        Err(e) => {
+            tracing::error!(session = id, "failed to look up session: {e}");
+            next.run(req).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Extension, routing::get, serve::Listener};
+    use rmcp::transport::streamable_http_server::SessionManager;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        config::Config,
+        http_sessions::HttpSessionManager,
+        recording::Recorder,
+        tls::{TlsConfig, TlsListener, TlsPeer},
+    };
+
+    #[tokio::test]
+    async fn test_file_store_and_manager() {
+        let dir = std::env::temp_dir().join(format!("rs-mcpr-cluster-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = FileSessionStore::new(&dir).unwrap();
+        store.insert("s1", "http://a").unwrap();
+        store.insert("s2", "http://b").unwrap();
+        store.insert("s3", "http://a").unwrap();
+        assert_eq!(store.owner("s1").unwrap().as_deref(), Some("http://a"));
+        assert_eq!(store.owner("s4").unwrap(), None);
+        assert!(store.owner("../s1").is_err());
+        store.remove("s1").unwrap();
+        store.remove("s1").unwrap();
+        assert_eq!(store.owner("s1").unwrap(), None);
+
+        // 启动时清理本副本遗留的会话
+        let config = Config {
+            cluster: ClusterConfig {
+                enabled: true,
+                advertise_url: Some("http://a".to_string()),
+                path: dir.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let manager = HttpSessionManager::new(&config, Recorder::default()).unwrap();
+        assert_eq!(store.owner("s3").unwrap(), None);
+        assert_eq!(store.owner("s2").unwrap().as_deref(), Some("http://b"));
+
+        let (id, _transport) = manager.create_session().await.unwrap();
+        assert!(manager.is_local(&id).await);
+        assert_eq!(store.owner(&id).unwrap().as_deref(), Some("http://a"));
+        manager.close_session(&id).await.unwrap();
+        assert_eq!(store.owner(&id).unwrap(), None);
+
+        let (id, _transport) = manager.create_session().await.unwrap();
+        manager.deregister();
+        assert_eq!(store.owner(&id).unwrap(), None);
+        assert_eq!(store.owner("s2").unwrap().as_deref(), Some("http://b"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 按 `start_http_server` 的方式启动一个启用 mTLS 的副本
+    async fn replica(dir: &Path, store: &Path) -> (String, Arc<AppState>) {
+        let tls = TlsConfig {
+            cert: dir.join("server.pem"),
+            key: dir.join("server.key"),
+            client_ca: Some(dir.join("ca.pem")),
+        };
+        let listener = TlsListener::bind("127.0.0.1:0", tls.clone()).await.unwrap();
+        let address = listener.local_addr().unwrap().remote_addr;
+        let url = format!("https://localhost:{}", address.port());
+        let config = Config {
+            address: address.to_string(),
+            tls: Some(tls),
+            cluster: ClusterConfig {
+                enabled: true,
+                advertise_url: Some(url.clone()),
+                token: Some("secret".to_string()),
+                ca: Some(dir.join("ca.pem")),
+                client_cert: Some(dir.join("client.pem")),
+                client_key: Some(dir.join("client.key")),
+                path: store.to_path_buf(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        config.validate().unwrap();
+        let state = Arc::new(AppState::new(config).unwrap());
+        let app = crate::http_transport_router(state.clone())
+            .unwrap()
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                authenticate,
+            ))
+            .layer(axum::middleware::from_fn(tls::propagate_identity));
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<TlsPeer>(),
+            )
+            .await
+        });
+        (url, state)
+    }
+
+    #[tokio::test]
+    async fn test_forward_over_mtls() {
+        let dir = std::env::temp_dir().join(format!("rs-mcpr-cluster-tls-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        tls::generate_dev_certificates(&dir, vec!["localhost".to_string()], "test-client").unwrap();
+        let store = dir.join("sessions");
+        let (a, _) = replica(&dir, &store).await;
+        let (b, _) = replica(&dir, &store).await;
+
+        let config = tls::build_client_config(
+            &dir.join("ca.pem"),
+            Some((&dir.join("client.pem"), &dir.join("client.key"))),
+        )
+        .unwrap();
+        let http = reqwest::Client::builder()
+            .use_preconfigured_tls(config)
+            .no_proxy()
+            .build()
+            .unwrap();
+        let post = |url: &str, body: serde_json::Value| {
+            http.post(format!("{url}/mcp"))
+                .header(header::ACCEPT, "application/json, text/event-stream")
+                .json(&body)
+        };
+
+        let response = post(
+            &a,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "initialize",
+                "params": {
+                    "protocolVersion": "2025-03-26",
+                    "capabilities": {},
+                    "clientInfo": { "name": "test", "version": "0" },
+                },
+            }),
+        )
+        .send()
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let id = response.headers()["mcp-session-id"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        response.text().await.unwrap();
+
+        // 会话在 a 上, 经 b 以 HTTPS 转发
+        let response = post(
+            &b,
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+        )
+        .header("mcp-session-id", &id)
+        .send()
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = post(
+            &b,
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/list" }),
+        )
+        .header("mcp-session-id", &id)
+        .send()
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.text().await.unwrap().contains("\"sum\""));
+
+        // 持有客户端证书但没有令牌的调用方不能冒充其他身份
+        let response = post(
+            &a,
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "tools/list" }),
+        )
+        .header("mcp-session-id", &id)
+        .header(FORWARDED_BY, &b)
+        .header(FORWARDED_IDENTITY, "CN=admin")
+        .header(CLUSTER_TOKEN, "guess")
+        .send()
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_replaces_identity() {
+        let dir = std::env::temp_dir().join(format!("rs-mcpr-cluster-auth-{}", std::process::id()));
+        let config = Config {
+            cluster: ClusterConfig {
+                enabled: true,
+                advertise_url: Some("http://a".to_string()),
+                token: Some("secret".to_string()),
+                path: dir.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = Arc::new(AppState::new(config).unwrap());
+        let app = axum::Router::new()
+            .route(
+                "/",
+                get(
+                    |identity: Option<Extension<CallerIdentity>>, headers: HeaderMap| async move {
+                        assert!(!headers.contains_key(CLUSTER_TOKEN));
+                        identity
+                            .map(|Extension(identity)| identity.0)
+                            .unwrap_or_default()
+                    },
+                ),
+            )
+            .layer(axum::middleware::from_fn_with_state(state, authenticate))
+            .layer(Extension(CallerIdentity("CN=replica".to_string())));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let http = reqwest::Client::builder().no_proxy().build().unwrap();
+        let send = |headers: &[(&HeaderName, &str)]| {
+            let mut req = http.get(&url);
+            for (name, value) in headers {
+                req = req.header(*name, *value);
+            }
+            async move {
+                let response = req.send().await.unwrap();
+                (response.status(), response.text().await.unwrap())
+            }
+        };
+
+        assert_eq!(send(&[]).await, (StatusCode::OK, "CN=replica".to_string()));
+        assert_eq!(
+            send(&[
+                (&FORWARDED_BY, "http://b"),
+                (&CLUSTER_TOKEN, "secret"),
+                (&FORWARDED_IDENTITY, "CN=alice"),
+            ])
+            .await,
+            (StatusCode::OK, "CN=alice".to_string())
+        );
+        // 没有原调用方身份时不沿用转发副本的证书身份
+        assert_eq!(
+            send(&[(&FORWARDED_BY, "http://b"), (&CLUSTER_TOKEN, "secret")]).await,
+            (StatusCode::OK, String::new())
+        );
+        for headers in [
+            &[(&FORWARDED_IDENTITY, "CN=alice")][..],
+            &[(&FORWARDED_BY, "http://b"), (&CLUSTER_TOKEN, "secre")][..],
+        ] {
+            assert_eq!(send(headers).await.0, StatusCode::FORBIDDEN);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
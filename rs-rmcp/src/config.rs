@@ -0,0 +1,700 @@
+//! 分层配置
+//!
+//! 优先级从低到高:
+//! 1. 内置默认值
+//! 2. 配置文件 (TOML / YAML), 由 `--config` 指定或在标准位置查找
+//! 3. `RS_MCPR_*` 环境变量, 嵌套字段用 `__` 分隔, 如 `RS_MCPR_RATE_LIMIT__SESSION_RATE=20/s`
+//! 4. 命令行参数
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use rmcp::model::{PromptsCapability, ResourcesCapability, ServerCapabilities, ToolsCapability};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    audit::{AuditConfig, AuditSinkKind},
+    client_profile::ClientsConfig,
+    cluster::ClusterConfig,
+    cors::OriginConfig,
+    error::{ConfigError, Error},
+    gateway::GatewayConfig,
+    logging::{LogConfig, LogOutput},
+    metrics::MetricsConfig,
+    protocol::ProtocolConfig,
+    ratelimit::RateLimitConfig,
+    recording::RecordConfig,
+    registry::RegistryConfig,
+    resumable::EventLogConfig,
+    session_limits::SessionLimitsConfig,
+    telemetry::TelemetryConfig,
+    tls::TlsConfig,
+    unix::UnixConfig,
+};
+
+/// 环境变量前缀
+pub const ENV_PREFIX: &str = "RS_MCPR_";
+/// 指定配置文件路径的环境变量
+pub const ENV_CONFIG: &str = "RS_MCPR_CONFIG";
+/// 配置文件名 (不含扩展名)
+const FILE_STEM: &str = "rs-mcpr";
+const FILE_EXTENSIONS: [&str; 3] = ["toml", "yaml", "yml"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Stdio,
+    Http,
+    Websocket,
+    Unix,
+}
+
+/// 服务配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// 传输方式
+    pub transport: Transport,
+    /// HTTP 监听地址
+    pub address: String,
+    pub log: LogConfig,
+    pub http: HttpConfig,
+    pub paths: PathsConfig,
+    pub capabilities: CapabilitiesConfig,
+    pub tls: Option<TlsConfig>,
+    pub rate_limit: RateLimitConfig,
+    pub origin: OriginConfig,
+    pub registry: RegistryConfig,
+    pub metrics: MetricsConfig,
+    pub admin: AdminConfig,
+    pub telemetry: TelemetryConfig,
+    pub audit: AuditConfig,
+    pub sessions: SessionLimitsConfig,
+    pub cluster: ClusterConfig,
+    pub event_log: EventLogConfig,
+    pub unix: UnixConfig,
+    pub gateway: GatewayConfig,
+    pub record: RecordConfig,
+    pub protocol: ProtocolConfig,
+    pub clients: ClientsConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            transport: Transport::Http,
+            address: "127.0.0.1:8000".to_string(),
+            log: LogConfig::default(),
+            http: HttpConfig::default(),
+            paths: PathsConfig::default(),
+            capabilities: CapabilitiesConfig::default(),
+            tls: None,
+            rate_limit: RateLimitConfig::default(),
+            origin: OriginConfig::default(),
+            registry: RegistryConfig::default(),
+            metrics: MetricsConfig::default(),
+            admin: AdminConfig::default(),
+            telemetry: TelemetryConfig::default(),
+            audit: AuditConfig::default(),
+            sessions: SessionLimitsConfig::default(),
+            cluster: ClusterConfig::default(),
+            event_log: EventLogConfig::default(),
+            unix: UnixConfig::default(),
+            gateway: GatewayConfig::default(),
+            record: RecordConfig::default(),
+            protocol: ProtocolConfig::default(),
+            clients: ClientsConfig::default(),
+        }
+    }
+}
+
+/// HTTP 传输配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// streamable HTTP 路径
+    pub mcp_path: String,
+    /// SSE 连接路径
+    pub sse_path: String,
+    /// SSE 消息提交路径
+    pub post_path: String,
+    /// SSE 保活间隔 (秒), 0 表示关闭
+    pub sse_keep_alive_secs: u64,
+    /// streamable HTTP 是否为每个客户端保持会话
+    pub stateful_mode: bool,
+    /// 收到关闭信号后继续接受连接的时间 (秒), 期间 `/readyz` 返回 503
+    pub shutdown_drain_secs: u64,
+    /// WebSocket 路径
+    pub ws_path: String,
+    /// WebSocket ping 间隔 (秒), 0 表示不发送; 两个间隔内没有收到任何帧时关闭连接
+    pub ws_ping_interval_secs: u64,
+    /// WebSocket 单条消息的最大字节数
+    pub ws_max_message_bytes: usize,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            mcp_path: "/mcp".to_string(),
+            sse_path: "/sse".to_string(),
+            post_path: "/message".to_string(),
+            sse_keep_alive_secs: 15,
+            stateful_mode: true,
+            shutdown_drain_secs: 0,
+            ws_path: "/ws".to_string(),
+            ws_ping_interval_secs: 30,
+            ws_max_message_bytes: 1024 * 1024,
+        }
+    }
+}
+
+impl HttpConfig {
+    pub fn sse_keep_alive(&self) -> Option<std::time::Duration> {
+        (self.sse_keep_alive_secs > 0)
+            .then(|| std::time::Duration::from_secs(self.sse_keep_alive_secs))
+    }
+
+    pub fn ws_ping_interval(&self) -> Option<std::time::Duration> {
+        (self.ws_ping_interval_secs > 0)
+            .then(|| std::time::Duration::from_secs(self.ws_ping_interval_secs))
+    }
+}
+
+/// 资源文件路径
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsConfig {
+    /// `docs://readme` 资源对应的文件
+    pub readme: PathBuf,
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        Self {
+            readme: PathBuf::from("./README.md"),
+        }
+    }
+}
+
+/// 管理端口配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// 独立的管理监听地址, 设置后 `/metrics` 与会话管理接口只在该地址提供
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// 会话管理接口的 Bearer token, 未设置时不提供该接口
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// `admin sessions` 以 HTTPS 连接时校验服务端证书的 CA (PEM)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca: Option<PathBuf>,
+    /// `admin sessions` 连接启用 mTLS 的监听时出示的客户端证书 (PEM)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<PathBuf>,
+    /// 客户端证书的私钥 (PEM)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<PathBuf>,
+}
+
+/// 对外声明的服务能力
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CapabilitiesConfig {
+    pub tools: bool,
+    pub tool_list_changed: bool,
+    pub prompts: bool,
+    pub prompt_list_changed: bool,
+    pub resources: bool,
+    pub resource_list_changed: bool,
+    pub logging: bool,
+    pub experimental: bool,
+}
+
+impl Default for CapabilitiesConfig {
+    fn default() -> Self {
+        Self {
+            tools: true,
+            tool_list_changed: true,
+            prompts: true,
+            prompt_list_changed: true,
+            resources: true,
+            resource_list_changed: true,
+            logging: false,
+            experimental: true,
+        }
+    }
+}
+
+impl CapabilitiesConfig {
+    /// 转换为 MCP 服务能力声明
+    pub fn to_capabilities(&self) -> ServerCapabilities {
+        ServerCapabilities {
+            experimental: self.experimental.then(Default::default),
+            logging: self.logging.then(Default::default),
+            completions: None,
+            prompts: self.prompts.then(|| PromptsCapability {
+                list_changed: self.prompt_list_changed.then_some(true),
+            }),
+            resources: self.resources.then(|| ResourcesCapability {
+                subscribe: None,
+                list_changed: self.resource_list_changed.then_some(true),
+            }),
+            tools: self.tools.then(|| ToolsCapability {
+                list_changed: self.tool_list_changed.then_some(true),
+            }),
+        }
+    }
+}
+
+impl Config {
+    /// 加载配置, 返回配置与使用的配置文件路径
+    ///
+    /// `overrides` 为命令行参数构造的覆盖值, 只包含显式指定的字段。
+    pub fn load(path: Option<&Path>, overrides: Value) -> Result<(Self, Option<PathBuf>), Error> {
+        let path = match path {
+            Some(path) => Some(path.to_path_buf()),
+            None => std::env::var_os(ENV_CONFIG)
+                .map(PathBuf::from)
+                .or_else(find_config_file),
+        };
+
+        let mut value = serde_json::to_value(Config::default())?;
+        if let Some(path) = &path {
+            merge(&mut value, read_file(path)?);
+        }
+        merge(&mut value, env_overrides(std::env::vars()));
+        merge(&mut value, overrides);
+
+        let config: Config =
+            serde_path_to_error::deserialize(value).map_err(|e| ConfigError::Invalid {
+                field: e.path().to_string(),
+                message: e.into_inner().to_string(),
+            })?;
+        config.validate()?;
+        Ok((config, path))
+    }
+
+    /// 校验配置取值
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |field: &str, message: String| ConfigError::Invalid {
+            field: field.to_string(),
+            message,
+        };
+
+        if self.transport != Transport::Stdio {
+            self.address
+                .parse::<SocketAddr>()
+                .map_err(|e| invalid("address", format!("`{}`: {e}", self.address)))?;
+        }
+        tracing_subscriber::EnvFilter::try_new(&self.log.level)
+            .map_err(|e| invalid("log.level", format!("`{}`: {e}", self.log.level)))?;
+        if self.transport == Transport::Stdio && self.log.output == LogOutput::Stdout {
+            return Err(invalid(
+                "log.output",
+                "stdout is reserved for the stdio transport".to_string(),
+            ));
+        }
+
+        if self
+            .admin
+            .token
+            .as_ref()
+            .is_some_and(|t| t.trim().is_empty())
+        {
+            return Err(invalid("admin.token", "must not be empty".to_string()));
+        }
+        if let Some(address) = &self.admin.address {
+            address
+                .parse::<SocketAddr>()
+                .map_err(|e| invalid("admin.address", format!("`{address}`: {e}")))?;
+        }
+        let admin = &self.admin;
+        if admin.client_cert.is_some() != admin.client_key.is_some() {
+            return Err(invalid(
+                "admin.client_key",
+                "admin.client_cert and admin.client_key must be set together".to_string(),
+            ));
+        }
+        for (field, path) in [
+            ("admin.ca", admin.ca.as_ref()),
+            ("admin.client_cert", admin.client_cert.as_ref()),
+            ("admin.client_key", admin.client_key.as_ref()),
+        ] {
+            if let Some(path) = path
+                && !path.is_file()
+            {
+                return Err(invalid(field, format!("{} does not exist", path.display())));
+            }
+        }
+
+        for (field, path) in [
+            ("metrics.path", &self.metrics.path),
+            ("http.mcp_path", &self.http.mcp_path),
+            ("http.sse_path", &self.http.sse_path),
+            ("http.post_path", &self.http.post_path),
+            ("http.ws_path", &self.http.ws_path),
+        ] {
+            if !path.starts_with('/') {
+                return Err(invalid(field, format!("`{path}` must start with `/`")));
+            }
+        }
+
+        if let Some(tls) = &self.tls {
+            for (field, path) in [
+                ("tls.cert", Some(&tls.cert)),
+                ("tls.key", Some(&tls.key)),
+                ("tls.client_ca", tls.client_ca.as_ref()),
+            ] {
+                if let Some(path) = path
+                    && !path.is_file()
+                {
+                    return Err(invalid(field, format!("{} does not exist", path.display())));
+                }
+            }
+        }
+
+        if self.http.ws_max_message_bytes == 0 {
+            return Err(invalid(
+                "http.ws_max_message_bytes",
+                "must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.audit.sink == AuditSinkKind::Rotating && self.audit.max_bytes == 0 {
+            return Err(invalid(
+                "audit.max_bytes",
+                "must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.cluster.enabled {
+            let cluster = &self.cluster;
+            let url = cluster.advertise_url.as_deref().unwrap_or_default();
+            let scheme = match reqwest::Url::parse(url) {
+                Ok(parsed) => parsed.scheme().to_string(),
+                Err(e) => return Err(invalid("cluster.advertise_url", format!("`{url}`: {e}"))),
+            };
+            // 副本之间直接访问彼此的监听地址, 协议必须与本副本的监听一致
+            let expected = if self.tls.is_some() { "https" } else { "http" };
+            if scheme != expected {
+                return Err(invalid(
+                    "cluster.advertise_url",
+                    format!("`{url}` must be an {expected}:// url"),
+                ));
+            }
+            if let Some(tls) = &self.tls {
+                if cluster.ca.is_none() {
+                    return Err(invalid(
+                        "cluster.ca",
+                        "is required to verify other replicas when tls is enabled".to_string(),
+                    ));
+                }
+                if tls.client_ca.is_some() {
+                    for (field, value) in [
+                        ("cluster.client_cert", cluster.client_cert.is_some()),
+                        ("cluster.client_key", cluster.client_key.is_some()),
+                        ("cluster.token", cluster.token.is_some()),
+                    ] {
+                        if !value {
+                            return Err(invalid(
+                                field,
+                                "is required when tls.client_ca is set".to_string(),
+                            ));
+                        }
+                    }
+                }
+            }
+            if cluster.client_cert.is_some() != cluster.client_key.is_some() {
+                return Err(invalid(
+                    "cluster.client_key",
+                    "cluster.client_cert and cluster.client_key must be set together".to_string(),
+                ));
+            }
+            if cluster.token.as_deref() == Some("") {
+                return Err(invalid("cluster.token", "must not be empty".to_string()));
+            }
+            for (field, path) in [
+                ("cluster.ca", cluster.ca.as_ref()),
+                ("cluster.client_cert", cluster.client_cert.as_ref()),
+                ("cluster.client_key", cluster.client_key.as_ref()),
+            ] {
+                if let Some(path) = path
+                    && !path.is_file()
+                {
+                    return Err(invalid(field, format!("{} does not exist", path.display())));
+                }
+            }
+        }
+
+        if self.event_log.enabled() && self.event_log.max_age_secs == 0 {
+            return Err(invalid(
+                "event_log.max_age_secs",
+                "must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.transport == Transport::Unix {
+            if !cfg!(unix) {
+                return Err(invalid(
+                    "transport",
+                    "unix is only supported on Unix platforms".to_string(),
+                ));
+            }
+            if self.unix.path.as_os_str().is_empty() {
+                return Err(invalid("unix.path", "must not be empty".to_string()));
+            }
+            if self.tls.is_some() {
+                return Err(invalid(
+                    "tls",
+                    "not supported with the unix transport".to_string(),
+                ));
+            }
+        }
+        self.unix.mode().map_err(|e| invalid("unix.mode", e))?;
+        self.gateway
+            .check()
+            .map_err(|(field, message)| invalid(&field, message))?;
+        self.protocol
+            .check()
+            .map_err(|message| invalid("protocol.versions", message))?;
+        self.clients
+            .check()
+            .map_err(|(field, message)| invalid(&field, message))?;
+
+        if self.rate_limit.max_concurrent_calls == Some(0) {
+            return Err(invalid(
+                "rate_limit.max_concurrent_calls",
+                "must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// 序列化为 TOML
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string_pretty(self).map_err(|e| {
+            ConfigError::Serialize {
+                message: e.to_string(),
+            }
+            .into()
+        })
+    }
+}
+
+/// 标准位置: 当前目录、`$XDG_CONFIG_HOME/rs-mcpr/` (或 `~/.config/rs-mcpr/`)、`/etc/rs-mcpr/`
+fn search_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for ext in FILE_EXTENSIONS {
+        paths.push(PathBuf::from(format!("{FILE_STEM}.{ext}")));
+    }
+
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    for dir in config_home
+        .map(|dir| dir.join(FILE_STEM))
+        .into_iter()
+        .chain([PathBuf::from("/etc").join(FILE_STEM)])
+    {
+        for ext in FILE_EXTENSIONS {
+            paths.push(dir.join(format!("config.{ext}")));
+        }
+    }
+    paths
+}
+
+fn find_config_file() -> Option<PathBuf> {
+    search_paths().into_iter().find(|path| path.is_file())
+}
+
+/// 按扩展名读取 TOML / YAML 配置文件
+fn read_file(path: &Path) -> Result<Value, ConfigError> {
+    let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let parse_error = |message: String| ConfigError::Parse {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    let value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str::<Value>(&text).map_err(|e| parse_error(e.to_string()))?,
+        Some("yaml" | "yml") => {
+            serde_yaml::from_str::<Value>(&text).map_err(|e| parse_error(e.to_string()))?
+        }
+        _ => return Err(ConfigError::UnsupportedFormat(path.to_path_buf())),
+    };
+    // 空 YAML 文件解析为 null
+    Ok(if value.is_null() {
+        Value::Object(Map::new())
+    } else {
+        value
+    })
+}
+
+/// 将 `RS_MCPR_*` 环境变量转换为配置树
+///
+/// 变量值按 TOML 值解析 (如 `true`、`8`、`["a", "b"]`), 解析失败时视为字符串。
+fn env_overrides(vars: impl Iterator<Item = (String, String)>) -> Value {
+    let mut root = Value::Object(Map::new());
+    for (key, raw) in vars {
+        let Some(key) = key.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        if key == ENV_CONFIG.trim_start_matches(ENV_PREFIX) || key.is_empty() {
+            continue;
+        }
+
+        let value = toml::from_str::<Map<String, Value>>(&format!("v = {raw}"))
+            .ok()
+            .and_then(|mut table| table.remove("v"))
+            .unwrap_or(Value::String(raw));
+
+        // `A__B=v` 转换为 `{"a": {"b": v}}` 后合并
+        let nested = key.rsplit("__").fold(value, |value, segment| {
+            let mut map = Map::new();
+            map.insert(segment.to_ascii_lowercase(), value);
+            Value::Object(map)
+        });
+        merge(&mut root, nested);
+    }
+    root
+}
+
+/// 深度合并, `overlay` 中的值覆盖 `base`
+pub fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) if existing.is_object() && value.is_object() => {
+                        merge(existing, value)
+                    }
+                    _ => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_env_overrides() {
+        let vars = [
+            ("RS_MCPR_ADDRESS", "0.0.0.0:9000"),
+            ("RS_MCPR_RATE_LIMIT__SESSION_RATE", "20/s"),
+            ("RS_MCPR_RATE_LIMIT__MAX_CONCURRENT_CALLS", "4"),
+            ("RS_MCPR_HTTP__STATEFUL_MODE", "false"),
+            (
+                "RS_MCPR_ORIGIN__ALLOWED_ORIGINS",
+                r#"["http://localhost:6274"]"#,
+            ),
+            ("RS_MCPR_CONFIG", "ignored.toml"),
+            ("PATH", "/usr/bin"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+
+        assert_eq!(
+            env_overrides(vars.into_iter()),
+            json!({
+                "address": "0.0.0.0:9000",
+                "rate_limit": { "session_rate": "20/s", "max_concurrent_calls": 4 },
+                "http": { "stateful_mode": false },
+                "origin": { "allowed_origins": ["http://localhost:6274"] },
+            })
+        );
+    }
+
+    #[test]
+    fn test_layering_and_errors() {
+        let dir = std::env::temp_dir().join(format!("rs-mcpr-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let file = dir.join("config.yaml");
+        std::fs::write(
+            &file,
+            "address: 0.0.0.0:9000\nlog:\n  level: info\nrate_limit:\n  tool_rates:\n    sum: 5/s\n",
+        )
+        .unwrap();
+        let (config, path) =
+            Config::load(Some(&file), json!({ "address": "127.0.0.1:7000" })).unwrap();
+        assert_eq!(path.as_deref(), Some(file.as_path()));
+        assert_eq!(config.address, "127.0.0.1:7000");
+        assert_eq!(config.log.level, "info");
+        assert_eq!(config.rate_limit.tool_rates[0].0, "sum");
+        assert!(config.to_toml().unwrap().contains("sum = \"5/s\""));
+
+        let file = dir.join("config.toml");
+        std::fs::write(&file, "[rate_limit]\nsession_rate = \"fast\"\n").unwrap();
+        let err = Config::load(Some(&file), json!({})).unwrap_err();
+        assert!(
+            err.to_string().starts_with("rate_limit.session_rate:"),
+            "{err}"
+        );
+
+        std::fs::write(&file, "[http]\nsse_path = \"sse\"\n").unwrap();
+        let err = Config::load(Some(&file), json!({})).unwrap_err();
+        assert!(err.to_string().starts_with("http.sse_path:"), "{err}");
+
+        std::fs::write(&file, "adress = \"0.0.0.0:9000\"\n").unwrap();
+        let err = Config::load(Some(&file), json!({})).unwrap_err();
+        assert!(err.to_string().contains("unknown field `adress`"), "{err}");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_cluster_with_tls() {
+        let pem = PathBuf::from("Cargo.toml");
+        let field = |config: &Config| match config.validate() {
+            Err(ConfigError::Invalid { field, .. }) => field,
+            other => panic!("unexpected {other:?}"),
+        };
+        let mut config = Config {
+            cluster: ClusterConfig {
+                enabled: true,
+                advertise_url: Some("https://10.0.0.1:8000".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(field(&config), "cluster.advertise_url");
+
+        config.tls = Some(TlsConfig {
+            cert: pem.clone(),
+            key: pem.clone(),
+            client_ca: None,
+        });
+        assert_eq!(field(&config), "cluster.ca");
+        config.cluster.ca = Some(pem.clone());
+        config.validate().unwrap();
+
+        config.cluster.advertise_url = Some("http://10.0.0.1:8000".to_string());
+        assert_eq!(field(&config), "cluster.advertise_url");
+        config.cluster.advertise_url = Some("https://10.0.0.1:8000".to_string());
+
+        // mTLS 时副本需要出示客户端证书并以令牌转发调用方身份
+        config.tls.as_mut().unwrap().client_ca = Some(pem.clone());
+        assert_eq!(field(&config), "cluster.client_cert");
+        config.cluster.client_cert = Some(pem.clone());
+        config.cluster.client_key = Some(pem.join("missing"));
+        assert_eq!(field(&config), "cluster.token");
+        config.cluster.token = Some("secret".to_string());
+        assert_eq!(field(&config), "cluster.client_key");
+        config.cluster.client_key = Some(pem);
+        config.validate().unwrap();
+    }
+}
@@ -0,0 +1,1057 @@
+//! 协议一致性检查
+//!
+//! 按 MCP 规范检查任意服务端: 初始化与版本协商、声明的能力与实际行为、JSON-RPC 错误码、
+//! ping、批量请求、通知处理, 以及 streamable HTTP 的会话头语义。
+//!
+//! 检查直接收发 JSON-RPC 消息而不经过 rmcp 的类型化客户端, 以便发送未知方法、批量请求与
+//! 错误的会话头。每项检查的结果为通过、失败或跳过 (未声明相应能力或传输不适用)。
+//!
+//! `rs-mcpr conformance` 检查外部服务, `cargo test` 检查本服务的 stdio、SSE 与
+//! streamable HTTP 传输。
+use std::{collections::VecDeque, fmt, path::Path, process::Stdio, time::Duration};
+
+use reqwest::{
+    StatusCode,
+    header::{ACCEPT, CONTENT_TYPE, HeaderMap},
+};
+use serde::Serialize;
+use serde_json::{Value, json};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines},
+    sync::mpsc,
+    task::JoinHandle,
+    time::Instant,
+};
+
+use crate::{
+    client::{Target, TargetArgs, parse_headers},
+    config::Config,
+    error::ConformanceError,
+    protocol::KNOWN_VERSIONS,
+};
+
+/// 比已知版本更旧与更新的版本
+const UNKNOWN_VERSIONS: [&str; 2] = ["1999-01-01", "2999-01-01"];
+/// 初始化时请求的版本
+const REQUESTED_VERSION: &str = "2025-03-26";
+/// 只有该版本的规范包含批量请求
+const BATCH_VERSION: &str = "2025-03-26";
+const SESSION_HEADER: &str = "mcp-session-id";
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const RESOURCE_NOT_FOUND: i64 = -32002;
+
+/// 一致性检查参数
+#[derive(clap::Args, Debug)]
+pub struct ConformanceArgs {
+    #[command(flatten)]
+    target: TargetArgs,
+
+    /// Seconds to wait for each response
+    #[arg(long, default_value_t = 5)]
+    timeout_secs: u64,
+
+    /// Print results as JSON Lines
+    #[arg(long)]
+    json: bool,
+}
+
+/// 检查结果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", content = "detail", rename_all = "lowercase")]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    Skip(String),
+}
+
+impl From<Result<(), String>> for Outcome {
+    fn from(result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => Outcome::Pass,
+            Err(message) => Outcome::Fail(message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+impl fmt::Display for CheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outcome {
+            Outcome::Pass => write!(f, "PASS  {}", self.name),
+            Outcome::Fail(detail) => write!(f, "FAIL  {}: {detail}", self.name),
+            Outcome::Skip(detail) => write!(f, "SKIP  {}: {detail}", self.name),
+        }
+    }
+}
+
+/// 被检查的服务
+#[derive(Clone)]
+pub enum Endpoint {
+    Target(Target, HeaderMap),
+    /// 每次连接创建一个进程内服务, 返回按行 JSON 的管道
+    #[cfg(test)]
+    Duplex(std::sync::Arc<dyn Fn() -> tokio::io::DuplexStream + Send + Sync>),
+}
+
+/// 读取 SSE 流的后台任务, 连接关闭时结束
+struct EventReader(JoinHandle<()>);
+
+impl Drop for EventReader {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+type LineReader = Lines<BufReader<Box<dyn AsyncRead + Send + Unpin>>>;
+
+/// 收发原始 JSON-RPC 消息的连接
+enum Connection {
+    /// stdio 与内存管道, 每行一条消息
+    Lines {
+        reader: LineReader,
+        writer: Box<dyn AsyncWrite + Send + Unpin>,
+        _child: Option<Box<tokio::process::Child>>,
+    },
+    /// streamable HTTP, 每条消息一个 POST, 响应体中的消息放入 `inbox`
+    Http {
+        http: reqwest::Client,
+        url: String,
+        session: Option<String>,
+        inbox: VecDeque<Value>,
+    },
+    /// 旧版 SSE, 消息 POST 到 `endpoint` 事件给出的地址, 响应从事件流读取
+    Sse {
+        http: reqwest::Client,
+        endpoint: String,
+        events: mpsc::UnboundedReceiver<(String, String)>,
+        _reader: EventReader,
+    },
+}
+
+fn http_client(headers: &HeaderMap) -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .default_headers(headers.clone())
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// 解析完整的 SSE 文本, 返回 (事件名, 数据)
+fn parse_events(text: &str) -> Vec<(String, String)> {
+    text.split("\n\n")
+        .filter_map(|block| {
+            let mut event = String::new();
+            let mut data: Vec<&str> = Vec::new();
+            for line in block.lines() {
+                let line = line.trim_end_matches('\r');
+                if let Some(value) = line.strip_prefix("event:") {
+                    event = value.trim().to_string();
+                } else if let Some(value) = line.strip_prefix("data:") {
+                    data.push(value.strip_prefix(' ').unwrap_or(value));
+                }
+            }
+            (!data.is_empty()).then(|| (event, data.join("\n")))
+        })
+        .collect()
+}
+
+/// 响应消息的 ID, 请求与通知返回 `None`
+fn response_id(message: &Value) -> Option<&Value> {
+    (message.get("result").is_some() || message.get("error").is_some())
+        .then(|| message.get("id"))
+        .flatten()
+}
+
+async fn post(
+    http: &reqwest::Client,
+    url: &str,
+    session: Option<&str>,
+    message: &Value,
+) -> Result<reqwest::Response, String> {
+    let mut request = http
+        .post(url)
+        .header(ACCEPT, "application/json, text/event-stream")
+        .json(message);
+    if let Some(session) = session {
+        request = request.header(SESSION_HEADER, session);
+    }
+    request.send().await.map_err(|e| e.to_string())
+}
+
+impl Connection {
+    async fn open(endpoint: &Endpoint, timeout: Duration) -> Result<Self, String> {
+        match endpoint {
+            Endpoint::Target(Target::Stdio(command), _) => {
+                let program = command.first().ok_or("empty stdio command")?;
+                let mut child = tokio::process::Command::new(program)
+                    .args(&command[1..])
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::inherit())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|e| format!("failed to start `{program}`: {e}"))?;
+                let (Some(stdout), Some(stdin)) = (child.stdout.take(), child.stdin.take()) else {
+                    return Err("stdio is not piped".to_string());
+                };
+                Ok(Self::lines(stdout, stdin, Some(Box::new(child))))
+            }
+            Endpoint::Target(Target::Http(url), headers) => Ok(Connection::Http {
+                http: http_client(headers)?,
+                url: url.clone(),
+                session: None,
+                inbox: VecDeque::new(),
+            }),
+            Endpoint::Target(Target::Sse(url), headers) => {
+                Self::open_sse(http_client(headers)?, url, timeout).await
+            }
+            #[cfg(test)]
+            Endpoint::Duplex(connect) => {
+                let (read, write) = tokio::io::split(connect());
+                Ok(Self::lines(read, write, None))
+            }
+        }
+    }
+
+    fn lines(
+        read: impl AsyncRead + Send + Unpin + 'static,
+        write: impl AsyncWrite + Send + Unpin + 'static,
+        child: Option<Box<tokio::process::Child>>,
+    ) -> Self {
+        let read: Box<dyn AsyncRead + Send + Unpin> = Box::new(read);
+        Connection::Lines {
+            reader: BufReader::new(read).lines(),
+            writer: Box::new(write),
+            _child: child,
+        }
+    }
+
+    async fn open_sse(http: reqwest::Client, url: &str, timeout: Duration) -> Result<Self, String> {
+        let mut response = http
+            .get(url)
+            .header(ACCEPT, "text/event-stream")
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("GET {url}: HTTP {}", response.status()));
+        }
+        let (tx, mut events) = mpsc::unbounded_channel();
+        let reader = EventReader(tokio::spawn(async move {
+            let mut buffer = Vec::new();
+            while let Ok(Some(chunk)) = response.chunk().await {
+                buffer.extend_from_slice(&chunk);
+                // 只解析完整的事件, 剩余部分等待下一块
+                let Some(end) = buffer.windows(2).rposition(|w| w == b"\n\n") else {
+                    continue;
+                };
+                let complete: Vec<u8> = buffer.drain(..end + 2).collect();
+                for event in parse_events(&String::from_utf8_lossy(&complete)) {
+                    if tx.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        }));
+        let endpoint = loop {
+            match tokio::time::timeout(timeout, events.recv()).await {
+                Ok(Some((event, data))) if event == "endpoint" => break data,
+                Ok(Some(_)) => continue,
+                Ok(None) => return Err("SSE stream closed before the endpoint event".to_string()),
+                Err(_) => return Err(format!("no endpoint event within {timeout:?}")),
+            }
+        };
+        let endpoint = reqwest::Url::parse(url)
+            .and_then(|base| base.join(&endpoint))
+            .map_err(|e| format!("invalid endpoint `{endpoint}`: {e}"))?;
+        Ok(Connection::Sse {
+            http,
+            endpoint: endpoint.to_string(),
+            events,
+            _reader: reader,
+        })
+    }
+
+    async fn send(&mut self, message: &Value) -> Result<(), String> {
+        match self {
+            Connection::Lines { writer, .. } => {
+                let mut line = serde_json::to_vec(message).map_err(|e| e.to_string())?;
+                line.push(b'\n');
+                writer.write_all(&line).await.map_err(|e| e.to_string())?;
+                writer.flush().await.map_err(|e| e.to_string())
+            }
+            Connection::Http {
+                http,
+                url,
+                session,
+                inbox,
+            } => {
+                let response = post(http, url, session.as_deref(), message).await?;
+                if let Some(id) = response
+                    .headers()
+                    .get(SESSION_HEADER)
+                    .and_then(|v| v.to_str().ok())
+                {
+                    *session = Some(id.to_string());
+                }
+                let status = response.status();
+                let is_sse = response
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .is_some_and(|v| v.starts_with("text/event-stream"));
+                let body = response.text().await.map_err(|e| e.to_string())?;
+                if !status.is_success() {
+                    return Err(format!("HTTP {status}: {}", body.trim()));
+                }
+                if is_sse {
+                    inbox.extend(
+                        parse_events(&body)
+                            .into_iter()
+                            .filter_map(|(_, data)| serde_json::from_str(&data).ok()),
+                    );
+                } else if !body.trim().is_empty() {
+                    inbox.push_back(serde_json::from_str(&body).map_err(|e| e.to_string())?);
+                }
+                Ok(())
+            }
+            Connection::Sse { http, endpoint, .. } => {
+                let response = http
+                    .post(endpoint.as_str())
+                    .json(message)
+                    .send()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                match response.status() {
+                    status if status.is_success() => Ok(()),
+                    status => Err(format!(
+                        "HTTP {status}: {}",
+                        response.text().await.unwrap_or_default().trim()
+                    )),
+                }
+            }
+        }
+    }
+
+    /// 下一条服务端消息, `timeout` 内没有消息时返回 `None`
+    async fn recv(&mut self, timeout: Duration) -> Result<Option<Value>, String> {
+        match self {
+            Connection::Lines { reader, .. } => loop {
+                match tokio::time::timeout(timeout, reader.next_line()).await {
+                    Err(_) => return Ok(None),
+                    Ok(Err(e)) => return Err(e.to_string()),
+                    Ok(Ok(None)) => return Err("connection closed".to_string()),
+                    Ok(Ok(Some(line))) if line.trim().is_empty() => continue,
+                    Ok(Ok(Some(line))) => {
+                        return serde_json::from_str(&line)
+                            .map(Some)
+                            .map_err(|e| format!("invalid JSON from server: {e}"));
+                    }
+                }
+            },
+            Connection::Http { inbox, .. } => Ok(inbox.pop_front()),
+            Connection::Sse { events, .. } => loop {
+                match tokio::time::timeout(timeout, events.recv()).await {
+                    Err(_) => return Ok(None),
+                    Ok(None) => return Err("connection closed".to_string()),
+                    Ok(Some((event, data))) if event.is_empty() || event == "message" => {
+                        return serde_json::from_str(&data)
+                            .map(Some)
+                            .map_err(|e| format!("invalid JSON from server: {e}"));
+                    }
+                    Ok(Some(_)) => continue,
+                }
+            },
+        }
+    }
+
+    /// 等待指定 ID 的响应, 跳过通知与服务端请求
+    async fn response(&mut self, id: &Value, timeout: Duration) -> Result<Value, String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let Some(message) = self.recv(remaining).await? else {
+                return Err(format!("no response to request {id} within {timeout:?}"));
+            };
+            let messages = match message {
+                Value::Array(messages) => messages,
+                message => vec![message],
+            };
+            if let Some(message) = messages
+                .into_iter()
+                .find(|message| response_id(message) == Some(id))
+            {
+                return Ok(message);
+            }
+        }
+    }
+
+    async fn request(
+        &mut self,
+        id: &str,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<Value, String> {
+        let mut message = json!({ "jsonrpc": "2.0", "id": id, "method": method });
+        if !params.is_null() {
+            message["params"] = params;
+        }
+        let sent = tokio::time::timeout(timeout, self.send(&message)).await;
+        sent.map_err(|_| format!("{method} not accepted within {timeout:?}"))??;
+        self.response(&json!(id), timeout).await
+    }
+
+    /// 请求成功时返回 `result`
+    async fn call(
+        &mut self,
+        id: &str,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<Value, String> {
+        let response = self.request(id, method, params, timeout).await?;
+        match response.get("result") {
+            Some(result) => Ok(result.clone()),
+            None => Err(format!("{method} failed: {}", response["error"])),
+        }
+    }
+
+    async fn notify(&mut self, method: &str, params: Value) -> Result<(), String> {
+        let mut message = json!({ "jsonrpc": "2.0", "method": method });
+        if !params.is_null() {
+            message["params"] = params;
+        }
+        self.send(&message).await
+    }
+
+    /// 结束 streamable HTTP 会话, 其它传输在连接释放时关闭
+    async fn close(self) {
+        if let Connection::Http {
+            http,
+            url,
+            session: Some(session),
+            ..
+        } = self
+        {
+            let _ = http
+                .delete(url.as_str())
+                .header(SESSION_HEADER, session)
+                .send()
+                .await;
+        }
+    }
+}
+
+struct Checker {
+    endpoint: Endpoint,
+    timeout: Duration,
+    results: Vec<CheckResult>,
+}
+
+impl Checker {
+    fn record(&mut self, name: &'static str, outcome: impl Into<Outcome>) {
+        self.results.push(CheckResult {
+            name,
+            outcome: outcome.into(),
+        });
+    }
+
+    /// 新建连接并以 `version` 完成初始化, 返回连接与初始化结果
+    async fn initialize(&self, version: &str) -> Result<(Connection, Value), String> {
+        let mut connection = Connection::open(&self.endpoint, self.timeout).await?;
+        let result = connection
+            .call(
+                "initialize",
+                "initialize",
+                initialize_params(version),
+                self.timeout,
+            )
+            .await?;
+        connection
+            .notify("notifications/initialized", Value::Null)
+            .await?;
+        Ok((connection, result))
+    }
+
+    /// 每个已知版本与更旧、更新的未知版本各请求一次初始化; 原样返回的版本视为支持,
+    /// 其余请求必须得到一个支持的版本, 或以 -32602 拒绝
+    async fn version_negotiation(&self) -> Result<(), String> {
+        fn answer(response: &Value) -> Option<&str> {
+            response["result"]["protocolVersion"].as_str()
+        }
+
+        let mut answers = Vec::new();
+        for requested in KNOWN_VERSIONS.into_iter().chain(UNKNOWN_VERSIONS) {
+            let mut connection = Connection::open(&self.endpoint, self.timeout).await?;
+            let params = initialize_params(requested);
+            let response = connection
+                .request("initialize", "initialize", params, self.timeout)
+                .await;
+            connection.close().await;
+            answers.push((requested, response?));
+        }
+        let supported: Vec<&str> = answers
+            .iter()
+            .filter(|(requested, response)| answer(response) == Some(requested))
+            .map(|(requested, _)| *requested)
+            .collect();
+        if supported.is_empty() {
+            return Err(format!("no known version is accepted: {answers:?}"));
+        }
+        for (requested, response) in &answers {
+            match answer(response) {
+                Some(answer) if supported.contains(&answer) => {}
+                Some(answer) => {
+                    return Err(format!(
+                        "requested {requested}, got {answer} which is not supported ({supported:?})"
+                    ));
+                }
+                None if response["error"]["code"].as_i64() == Some(INVALID_PARAMS) => {}
+                None => return Err(format!("requested {requested}, got {response}")),
+            }
+        }
+        Ok(())
+    }
+
+    /// 未知方法使用单独的连接, 服务端无法解析时可能关闭连接
+    async fn method_not_found(&self) -> Result<(), String> {
+        let (mut connection, _) = self.initialize(REQUESTED_VERSION).await?;
+        let result = self
+            .expect_error(
+                &mut connection,
+                "unknown-method",
+                "conformance/unknown-method",
+                json!({}),
+                METHOD_NOT_FOUND,
+            )
+            .await;
+        connection.close().await;
+        result
+    }
+
+    async fn expect_error(
+        &self,
+        connection: &mut Connection,
+        id: &str,
+        method: &str,
+        params: Value,
+        code: i64,
+    ) -> Result<(), String> {
+        let response = connection.request(id, method, params, self.timeout).await?;
+        match response["error"]["code"].as_i64() {
+            Some(actual) if actual == code => Ok(()),
+            Some(actual) => Err(format!(
+                "expected error {code}, got {actual}: {}",
+                response["error"]["message"]
+            )),
+            None => Err(format!("expected error {code}, got {response}")),
+        }
+    }
+
+    async fn list(
+        &self,
+        connection: &mut Connection,
+        method: &str,
+        field: &str,
+        required: &[&str],
+    ) -> Result<Vec<Value>, String> {
+        let result = connection
+            .call(method, method, Value::Null, self.timeout)
+            .await?;
+        let Some(items) = result[field].as_array() else {
+            return Err(format!("{method}: `{field}` is not an array"));
+        };
+        for item in items {
+            for key in required {
+                if !item[key].is_string() {
+                    return Err(format!("{method}: `{key}` missing in {item}"));
+                }
+            }
+        }
+        Ok(items.clone())
+    }
+
+    async fn tools(&self, connection: &mut Connection) -> Result<(), String> {
+        let tools = self
+            .list(connection, "tools/list", "tools", &["name"])
+            .await?;
+        match tools
+            .iter()
+            .find(|tool| tool["inputSchema"]["type"] != "object")
+        {
+            Some(tool) => Err(format!("tool {} has no object inputSchema", tool["name"])),
+            None => Ok(()),
+        }
+    }
+
+    async fn resources(&self, connection: &mut Connection, subscribe: bool) -> Result<(), String> {
+        let resources = self
+            .list(connection, "resources/list", "resources", &["uri", "name"])
+            .await?;
+        self.list(
+            connection,
+            "resources/templates/list",
+            "resourceTemplates",
+            &["uriTemplate", "name"],
+        )
+        .await?;
+        if let (true, Some(resource)) = (subscribe, resources.first()) {
+            let params = json!({ "uri": resource["uri"] });
+            connection
+                .call(
+                    "subscribe",
+                    "resources/subscribe",
+                    params.clone(),
+                    self.timeout,
+                )
+                .await?;
+            connection
+                .call("unsubscribe", "resources/unsubscribe", params, self.timeout)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// 批量请求的响应可以是数组, 也可以逐条返回; 服务端拒绝批量请求时跳过
+    async fn batch(&self, connection: &mut Connection) -> Outcome {
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": "batch-1", "method": "ping" },
+            { "jsonrpc": "2.0", "id": "batch-2", "method": "ping" },
+        ]);
+        if let Err(e) = connection.send(&batch).await {
+            return Outcome::Skip(format!("batch rejected: {e}"));
+        }
+        let mut pending = vec![json!("batch-1"), json!("batch-2")];
+        let deadline = Instant::now() + self.timeout;
+        while !pending.is_empty() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let message = match connection.recv(remaining).await {
+                Ok(Some(message)) => message,
+                Ok(None) => return Outcome::Fail(format!("no response to {pending:?}")),
+                Err(e) => return Outcome::Fail(e),
+            };
+            let messages = match message {
+                Value::Array(messages) => messages,
+                message => vec![message],
+            };
+            for message in messages {
+                match response_id(&message) {
+                    Some(Value::Null) => {
+                        return Outcome::Skip(format!("batch rejected: {}", message["error"]));
+                    }
+                    Some(id) => pending.retain(|pending| pending != id),
+                    None => {}
+                }
+            }
+        }
+        Outcome::Pass
+    }
+
+    /// 通知之后的请求应当正常响应, 且通知本身没有响应
+    /// 通知之后发送 ping, 服务端必须只回复 ping; 每项检查使用单独的连接
+    async fn notification(&self, method: &str, params: Value) -> Result<(), String> {
+        let (mut connection, _) = self.initialize(REQUESTED_VERSION).await?;
+        let result = self.notification_on(&mut connection, method, params).await;
+        connection.close().await;
+        result
+    }
+
+    async fn notification_on(
+        &self,
+        connection: &mut Connection,
+        method: &str,
+        params: Value,
+    ) -> Result<(), String> {
+        connection.notify(method, params).await?;
+        let id = json!(format!("after-{method}"));
+        let ping = json!({ "jsonrpc": "2.0", "id": id, "method": "ping" });
+        connection.send(&ping).await?;
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let Some(message) = connection.recv(remaining).await? else {
+                return Err(format!("no response to ping within {:?}", self.timeout));
+            };
+            let is_response = message.get("result").is_some() || message.get("error").is_some();
+            match response_id(&message) {
+                Some(actual) if *actual == id => return Ok(()),
+                Some(Value::Null) | None if is_response => {
+                    return Err(format!("server responded to the notification: {message}"));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// streamable HTTP 的会话头: 会话 ID 为可见 ASCII, 缺少会话头返回 400,
+    /// 未知或已删除的会话返回 404
+    async fn http_sessions(&mut self) {
+        const CHECKS: [&str; 4] = [
+            "http/session-id",
+            "http/missing-session",
+            "http/unknown-session",
+            "http/delete-session",
+        ];
+        let (http, url) = match &self.endpoint {
+            Endpoint::Target(Target::Http(url), headers) => match http_client(headers) {
+                Ok(http) => (http, url.clone()),
+                Err(e) => return self.record(CHECKS[0], Outcome::Fail(e)),
+            },
+            _ => {
+                for name in CHECKS {
+                    self.record(name, Outcome::Skip("streamable HTTP only".to_string()));
+                }
+                return;
+            }
+        };
+        let session = match self.initialize(REQUESTED_VERSION).await {
+            Ok((Connection::Http { session, .. }, _)) => session,
+            Ok(_) => None,
+            Err(e) => return self.record(CHECKS[0], Outcome::Fail(e)),
+        };
+        let Some(session) = session else {
+            for name in CHECKS {
+                self.record(
+                    name,
+                    Outcome::Skip("server runs without sessions".to_string()),
+                );
+            }
+            return;
+        };
+
+        let visible = !session.is_empty() && session.bytes().all(|b| (0x21..=0x7e).contains(&b));
+        self.record(
+            CHECKS[0],
+            match visible {
+                true => Ok(()),
+                false => Err(format!("session id {session:?} is not visible ASCII")),
+            },
+        );
+
+        let ping = json!({ "jsonrpc": "2.0", "id": "session", "method": "ping" });
+        let status = |session: Option<&str>| {
+            let (http, url, ping) = (&http, &url, &ping);
+            let session = session.map(str::to_string);
+            async move {
+                post(http, url, session.as_deref(), ping)
+                    .await
+                    .map(|response| response.status())
+            }
+        };
+        let expect = |actual: Result<StatusCode, String>, expected: StatusCode| match actual {
+            Ok(actual) if actual == expected => Ok(()),
+            Ok(actual) => Err(format!("expected HTTP {expected}, got {actual}")),
+            Err(e) => Err(e),
+        };
+        self.record(
+            CHECKS[1],
+            expect(status(None).await, StatusCode::BAD_REQUEST),
+        );
+        self.record(
+            CHECKS[2],
+            expect(
+                status(Some("conformance-unknown-session")).await,
+                StatusCode::NOT_FOUND,
+            ),
+        );
+
+        let deleted = http
+            .delete(url.as_str())
+            .header(SESSION_HEADER, &session)
+            .send()
+            .await
+            .map(|response| response.status());
+        let outcome = match deleted {
+            Ok(StatusCode::METHOD_NOT_ALLOWED) => {
+                Outcome::Skip("server does not allow clients to end sessions".to_string())
+            }
+            Ok(deleted) if deleted.is_success() => {
+                expect(status(Some(&session)).await, StatusCode::NOT_FOUND).into()
+            }
+            Ok(deleted) => Outcome::Fail(format!("DELETE returned HTTP {deleted}")),
+            Err(e) => Outcome::Fail(e.to_string()),
+        };
+        self.record(CHECKS[3], outcome);
+    }
+}
+
+/// 初始化结果包含已知的协议版本、服务端信息与能力
+fn initialize_params(version: &str) -> Value {
+    json!({
+        "protocolVersion": version,
+        "capabilities": {},
+        "clientInfo": { "name": "rs-mcpr-conformance", "version": env!("CARGO_PKG_VERSION") },
+    })
+}
+
+fn check_initialize_result(result: &Value) -> Result<(), String> {
+    let version = result["protocolVersion"].as_str().unwrap_or_default();
+    if !KNOWN_VERSIONS.contains(&version) {
+        return Err(format!(
+            "unknown protocolVersion {}",
+            result["protocolVersion"]
+        ));
+    }
+    if !result["serverInfo"]["name"].is_string() || !result["serverInfo"]["version"].is_string() {
+        return Err(format!("invalid serverInfo {}", result["serverInfo"]));
+    }
+    if !result["capabilities"].is_object() {
+        return Err("capabilities is not an object".to_string());
+    }
+    Ok(())
+}
+
+/// 运行全部检查
+pub async fn run_suite(endpoint: Endpoint, timeout: Duration) -> Vec<CheckResult> {
+    let mut checker = Checker {
+        endpoint,
+        timeout,
+        results: Vec::new(),
+    };
+    let (mut connection, init) = match checker.initialize(REQUESTED_VERSION).await {
+        Ok(initialized) => initialized,
+        Err(e) => {
+            checker.record("initialize/result", Outcome::Fail(e));
+            return checker.results;
+        }
+    };
+    checker.record("initialize/result", check_initialize_result(&init));
+    let outcome = checker.version_negotiation().await;
+    checker.record("initialize/version-negotiation", outcome);
+
+    let connection = &mut connection;
+    let ping = connection
+        .call("ping", "ping", Value::Null, timeout)
+        .await
+        .and_then(|result| match result == json!({}) {
+            true => Ok(()),
+            false => Err(format!("expected an empty result, got {result}")),
+        });
+    checker.record("ping", ping);
+
+    let outcome = checker.method_not_found().await;
+    checker.record("errors/method-not-found", outcome);
+
+    let capabilities = &init["capabilities"];
+    let not_advertised = |capability: &str| Outcome::Skip(format!("{capability} not advertised"));
+    let outcome = match capabilities.get("tools") {
+        Some(_) => checker
+            .expect_error(
+                connection,
+                "unknown-tool",
+                "tools/call",
+                json!({ "name": "conformance-unknown-tool", "arguments": {} }),
+                INVALID_PARAMS,
+            )
+            .await
+            .into(),
+        None => not_advertised("tools"),
+    };
+    checker.record("errors/unknown-tool", outcome);
+    let outcome = match capabilities.get("resources") {
+        Some(_) => checker
+            .expect_error(
+                connection,
+                "missing-resource",
+                "resources/read",
+                json!({ "uri": "conformance://missing" }),
+                RESOURCE_NOT_FOUND,
+            )
+            .await
+            .into(),
+        None => not_advertised("resources"),
+    };
+    checker.record("errors/resource-not-found", outcome);
+    let outcome = match capabilities.get("prompts") {
+        Some(_) => checker
+            .expect_error(
+                connection,
+                "unknown-prompt",
+                "prompts/get",
+                json!({ "name": "conformance-unknown-prompt" }),
+                INVALID_PARAMS,
+            )
+            .await
+            .into(),
+        None => not_advertised("prompts"),
+    };
+    checker.record("errors/unknown-prompt", outcome);
+
+    let outcome = match capabilities.get("tools") {
+        Some(_) => checker.tools(connection).await.into(),
+        None => not_advertised("tools"),
+    };
+    checker.record("capabilities/tools", outcome);
+    let outcome = match capabilities.get("resources") {
+        Some(resources) => {
+            let subscribe = resources["subscribe"] == json!(true);
+            checker.resources(connection, subscribe).await.into()
+        }
+        None => not_advertised("resources"),
+    };
+    checker.record("capabilities/resources", outcome);
+    let outcome = match capabilities.get("prompts") {
+        Some(_) => checker
+            .list(connection, "prompts/list", "prompts", &["name"])
+            .await
+            .map(|_| ())
+            .into(),
+        None => not_advertised("prompts"),
+    };
+    checker.record("capabilities/prompts", outcome);
+    let outcome = match capabilities.get("logging") {
+        Some(_) => connection
+            .call(
+                "set-level",
+                "logging/setLevel",
+                json!({ "level": "info" }),
+                timeout,
+            )
+            .await
+            .map(|_| ())
+            .into(),
+        None => not_advertised("logging"),
+    };
+    checker.record("capabilities/logging", outcome);
+
+    let outcome = checker
+        .notification("notifications/conformance", json!({}))
+        .await;
+    checker.record("notifications/unknown", outcome);
+    let params = json!({ "requestId": "conformance-never-sent", "reason": "conformance" });
+    let outcome = checker
+        .notification("notifications/cancelled", params)
+        .await;
+    checker.record("notifications/cancelled-unknown", outcome);
+
+    // 批量请求放在最后, 被拒绝时可能影响同一连接上的后续消息
+    let version = init["protocolVersion"].as_str().unwrap_or_default();
+    let outcome = match version {
+        BATCH_VERSION => checker.batch(connection).await,
+        version => Outcome::Skip(format!(
+            "batching is not part of protocol version {version}"
+        )),
+    };
+    checker.record("batch", outcome);
+
+    checker.http_sessions().await;
+    checker.results
+}
+
+/// `rs-mcpr conformance`
+pub async fn run(
+    config: &Config,
+    config_path: Option<&Path>,
+    args: &ConformanceArgs,
+) -> Result<(), ConformanceError> {
+    let target = args.target.target(config, config_path)?;
+    let headers = parse_headers(&args.target.headers)?;
+    let timeout = Duration::from_secs(args.timeout_secs);
+    let results = run_suite(Endpoint::Target(target, headers), timeout).await;
+
+    let count = |f: fn(&Outcome) -> bool| results.iter().filter(|r| f(&r.outcome)).count();
+    let failed = count(|outcome| matches!(outcome, Outcome::Fail(_)));
+    for result in &results {
+        match args.json {
+            true => println!("{}", serde_json::to_string(result)?),
+            false => println!("{result}"),
+        }
+    }
+    if !args.json {
+        println!(
+            "{} passed, {failed} failed, {} skipped",
+            count(|outcome| *outcome == Outcome::Pass),
+            count(|outcome| matches!(outcome, Outcome::Skip(_))),
+        );
+    }
+    match failed {
+        0 => Ok(()),
+        failed => Err(ConformanceError::Failed(failed)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, sync::Arc};
+
+    use rmcp::ServiceExt;
+
+    use super::*;
+    use crate::{calculator, jsonrpc, metrics::SessionTransport, state::AppState};
+
+    fn failures(results: &[CheckResult]) -> Vec<&'static str> {
+        results
+            .iter()
+            .filter(|result| matches!(result.outcome, Outcome::Fail(_)))
+            .map(|result| result.name)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_conformance() {
+        let state = Arc::new(AppState::new(Config::default()).unwrap());
+        let duplex = Endpoint::Duplex(Arc::new(move || {
+            let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+            let service = calculator::session_service(state.clone(), SessionTransport::Stdio);
+            let (read, write) = tokio::io::split(server_io);
+            tokio::spawn(async move {
+                // 与 stdio 传输相同的按行读写
+                if let Ok(server) = service.serve(jsonrpc::line_transport(read, write)).await {
+                    let _ = server.waiting().await;
+                }
+            });
+            client_io
+        }));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let config = Config {
+            address: address.to_string(),
+            ..Default::default()
+        };
+        let state = Arc::new(AppState::new(config).unwrap());
+        let app = crate::http_transport_router(state).unwrap();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+        });
+        let http = Endpoint::Target(
+            Target::Http(format!("http://{address}/mcp")),
+            HeaderMap::new(),
+        );
+        let sse = Endpoint::Target(
+            Target::Sse(format!("http://{address}/sse")),
+            HeaderMap::new(),
+        );
+
+        for (name, endpoint) in [("stdio", duplex), ("http", http), ("sse", sse)] {
+            let results = run_suite(endpoint, Duration::from_secs(2)).await;
+            let report: Vec<String> = results.iter().map(ToString::to_string).collect();
+            let failed: &[&str] = &[];
+            assert_eq!(failures(&results), failed, "{name}:\n{}", report.join("\n"));
+        }
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let text = "event: endpoint\ndata: /message?sessionId=1\n\n: keep-alive\n\ndata: {\"a\":\ndata: 1}\n\n";
+        assert_eq!(
+            parse_events(text),
+            [
+                ("endpoint".to_string(), "/message?sessionId=1".to_string()),
+                (String::new(), "{\"a\":\n1}".to_string()),
+            ]
+        );
+    }
+}
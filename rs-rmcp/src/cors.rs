@@ -0,0 +1,223 @@
+//! CORS 与 Origin 校验
+//!
+//! MCP 规范要求 streamable HTTP 服务端校验 `Origin` 头以防御 DNS 重绑定攻击:
+//! - `Host` 头必须在允许列表中, 绑定到回环地址时默认只允许本机主机名
+//! - 带 `Origin` 头的请求必须同源或在允许列表中, 否则返回 403
+//! - 处理浏览器的 CORS 预检请求
+use std::{net::IpAddr, sync::Arc};
+
+use axum::{
+    extract::{Request, State},
+    http::{
+        HeaderMap, HeaderValue, Method, StatusCode,
+        header::{
+            ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+            ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE,
+            ACCESS_CONTROL_REQUEST_METHOD, HOST, ORIGIN, VARY,
+        },
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+const ALLOW_METHODS: &str = "GET, POST, DELETE, OPTIONS";
+const ALLOW_HEADERS: &str =
+    "content-type, accept, authorization, mcp-session-id, mcp-protocol-version, last-event-id";
+const EXPOSE_HEADERS: &str = "mcp-session-id";
+const PREFLIGHT_MAX_AGE: u32 = 600;
+
+/// 回环地址默认允许的主机名
+const LOOPBACK_HOSTS: [&str; 3] = ["localhost", "127.0.0.1", "[::1]"];
+
+/// Origin 校验配置, 同时作为命令行参数与配置文件的 `[origin]` 表
+#[derive(Debug, Clone, Default, clap::Args, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OriginConfig {
+    /// Allowed cross-origin `Origin`, e.g. http://localhost:6274, `*` allows any
+    #[arg(long = "allowed-origin")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_origins: Vec<String>,
+
+    /// Allowed `Host` header (host name, port is ignored). Defaults to loopback
+    /// names when bound to a loopback address, otherwise any host
+    #[arg(long = "allowed-host")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_hosts: Vec<String>,
+}
+
+/// Origin 校验策略
+#[derive(Debug, Clone)]
+pub struct OriginPolicy {
+    allowed_origins: Vec<String>,
+    /// `None` 表示不校验 Host
+    allowed_hosts: Option<Vec<String>>,
+}
+
+impl OriginPolicy {
+    /// 根据配置与监听地址创建策略
+    pub fn new(config: &OriginConfig, bind_address: &str) -> Self {
+        let allowed_hosts = if !config.allowed_hosts.is_empty() {
+            Some(
+                config
+                    .allowed_hosts
+                    .iter()
+                    .map(|h| h.to_ascii_lowercase())
+                    .collect(),
+            )
+        } else if is_loopback(bind_address) {
+            Some(LOOPBACK_HOSTS.iter().map(|h| h.to_string()).collect())
+        } else {
+            None
+        };
+
+        Self {
+            allowed_origins: config
+                .allowed_origins
+                .iter()
+                .map(|o| o.trim_end_matches('/').to_ascii_lowercase())
+                .collect(),
+            allowed_hosts,
+        }
+    }
+
+    fn is_allowed_host(&self, host: Option<&str>) -> bool {
+        let Some(allowed) = &self.allowed_hosts else {
+            return true;
+        };
+        host.is_some_and(|host| {
+            let host = strip_port(host).to_ascii_lowercase();
+            allowed.contains(&host)
+        })
+    }
+
+    fn is_allowed_origin(&self, origin: &str, host: Option<&str>) -> bool {
+        let origin = origin.trim_end_matches('/').to_ascii_lowercase();
+        // 同源请求
+        if let (Some((_, authority)), Some(host)) = (origin.split_once("://"), host)
+            && authority.eq_ignore_ascii_case(host)
+        {
+            return true;
+        }
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || *allowed == origin)
+    }
+}
+
+/// 监听地址是否为回环地址
+fn is_loopback(bind_address: &str) -> bool {
+    let host = strip_port(bind_address);
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// 去掉 `host:port` 中的端口, 兼容 `[::1]:8000`
+fn strip_port(authority: &str) -> &str {
+    if authority.starts_with('[') {
+        return authority
+            .find(']')
+            .map_or(authority, |end| &authority[..=end]);
+    }
+    authority
+        .rsplit_once(':')
+        .map_or(authority, |(host, _)| host)
+}
+
+fn forbidden(reason: &'static str) -> Response {
+    tracing::warn!(reason, "request rejected by origin policy");
+    (StatusCode::FORBIDDEN, reason).into_response()
+}
+
+fn add_cors_headers(headers: &mut HeaderMap, origin: HeaderValue) {
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    headers.insert(
+        ACCESS_CONTROL_EXPOSE_HEADERS,
+        HeaderValue::from_static(EXPOSE_HEADERS),
+    );
+    headers.append(VARY, HeaderValue::from_static("origin"));
+}
+
+/// Origin 校验与 CORS 中间件
+pub async fn validate_origin(
+    State(policy): State<Arc<OriginPolicy>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let headers = req.headers();
+    let host = headers.get(HOST).and_then(|h| h.to_str().ok());
+    if !policy.is_allowed_host(host) {
+        return forbidden("host not allowed");
+    }
+
+    let Some(origin) = headers.get(ORIGIN).cloned() else {
+        // 非浏览器客户端不携带 Origin
+        return next.run(req).await;
+    };
+    let allowed = origin
+        .to_str()
+        .is_ok_and(|o| policy.is_allowed_origin(o, host));
+    if !allowed {
+        return forbidden("origin not allowed");
+    }
+
+    // 预检请求
+    if req.method() == Method::OPTIONS && headers.contains_key(ACCESS_CONTROL_REQUEST_METHOD) {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        add_cors_headers(headers, origin);
+        headers.insert(
+            ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static(ALLOW_METHODS),
+        );
+        headers.insert(
+            ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static(ALLOW_HEADERS),
+        );
+        headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from(PREFLIGHT_MAX_AGE));
+        return response;
+    }
+
+    let mut response = next.run(req).await;
+    add_cors_headers(response.headers_mut(), origin);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loopback_defaults() {
+        let policy = OriginPolicy::new(&OriginConfig::default(), "127.0.0.1:8000");
+
+        assert!(policy.is_allowed_host(Some("localhost:8000")));
+        assert!(policy.is_allowed_host(Some("[::1]:8000")));
+        assert!(!policy.is_allowed_host(Some("attacker.example:8000")));
+        assert!(!policy.is_allowed_host(None));
+
+        assert!(policy.is_allowed_origin("http://localhost:8000", Some("localhost:8000")));
+        assert!(!policy.is_allowed_origin("http://localhost:6274", Some("localhost:8000")));
+    }
+
+    #[test]
+    fn test_configured_policy() {
+        let config = OriginConfig {
+            allowed_origins: vec!["http://localhost:6274/".to_string()],
+            allowed_hosts: vec!["mcp.example.com".to_string()],
+        };
+        let policy = OriginPolicy::new(&config, "0.0.0.0:8000");
+
+        assert!(policy.is_allowed_host(Some("MCP.example.com")));
+        assert!(!policy.is_allowed_host(Some("localhost:8000")));
+        assert!(policy.is_allowed_origin("http://localhost:6274", Some("mcp.example.com")));
+        assert!(!policy.is_allowed_origin("http://evil.example", Some("mcp.example.com")));
+
+        let policy = OriginPolicy::new(&OriginConfig::default(), "0.0.0.0:8000");
+        assert!(policy.is_allowed_host(Some("anything:8000")));
+    }
+}
@@ -1,8 +1,15 @@
 //! 错误处理
 
+use std::path::PathBuf;
+
+use rmcp::transport::streamable_http_server::session::local::LocalSessionManagerError;
+
 #[allow(unused)]
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
     #[error(transparent)]
     SerdeJson(#[from] serde_json::error::Error),
 
@@ -11,6 +18,30 @@ pub enum Error {
 
     #[error(transparent)]
     PathExtractionError(#[from] PathExtractionError),
+
+    #[error(transparent)]
+    Tls(#[from] TlsError),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error("log file: {0}")]
+    LogFile(#[from] tracing_appender::rolling::InitError),
+
+    #[error("trace exporter: {0}")]
+    TraceExporter(#[from] opentelemetry_otlp::ExporterBuildError),
+
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    Cluster(#[from] ClusterError),
+
+    #[error("admin api{}: {message}", status.map(|s| format!(" ({s})")).unwrap_or_default())]
+    AdminApi {
+        status: Option<u16>,
+        message: String,
+    },
 }
 
 /// Path 自定义错误类型
@@ -24,3 +55,158 @@ pub enum PathExtractionError {
     #[error("Unsupported target type")]
     UnsupportedType,
 }
+
+/// TLS 自定义错误类型
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    #[error("failed to read {path}: {source}")]
+    ReadPem {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("no certificate found in {0}")]
+    NoCertificate(PathBuf),
+    #[error("no private key found in {0}")]
+    NoPrivateKey(PathBuf),
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+    #[error(transparent)]
+    ClientVerifier(#[from] rustls::server::VerifierBuilderError),
+    #[error(transparent)]
+    CertGen(#[from] rcgen::Error),
+}
+
+/// 多副本自定义错误类型
+#[derive(Debug, thiserror::Error)]
+pub enum ClusterError {
+    #[error("cluster.advertise_url is not set")]
+    MissingAdvertiseUrl,
+    #[error("session store: {0}")]
+    Store(#[from] std::io::Error),
+}
+
+/// streamable HTTP 会话自定义错误类型
+#[derive(Debug, thiserror::Error)]
+pub enum HttpSessionError {
+    #[error(transparent)]
+    Local(#[from] LocalSessionManagerError),
+    #[error("session store: {0}")]
+    Store(#[from] std::io::Error),
+    #[error("event {0} is no longer available")]
+    EventUnavailable(String),
+}
+
+/// 网关自定义错误类型
+#[derive(Debug, thiserror::Error)]
+pub enum GatewayError {
+    #[error("failed to start downstream server {name}: {source}")]
+    Spawn {
+        name: String,
+        source: std::io::Error,
+    },
+    #[error("failed to connect to downstream server {name}: {message}")]
+    Connect { name: String, message: String },
+}
+
+/// 请求头格式错误
+#[derive(Debug, thiserror::Error)]
+#[error("invalid header `{0}`, expected `Name: value`")]
+pub struct InvalidHeader(pub String);
+
+/// 传输桥接自定义错误类型
+#[derive(Debug, thiserror::Error)]
+pub enum BridgeError {
+    #[error(transparent)]
+    InvalidHeader(#[from] InvalidHeader),
+    #[error("failed to connect to {url}: {message}")]
+    Connect { url: String, message: String },
+    #[error("log.output: stdout is reserved for the stdio side of the bridge")]
+    StdoutReserved,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+/// 客户端自定义错误类型
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error(transparent)]
+    InvalidHeader(#[from] InvalidHeader),
+    #[error("invalid argument `{0}`, expected KEY=VALUE")]
+    InvalidArgument(String),
+    #[error("empty or unbalanced stdio command")]
+    EmptyCommand,
+    #[error("failed to start `{command}`: {source}")]
+    Spawn {
+        command: String,
+        source: std::io::Error,
+    },
+    #[error("failed to connect: {0}")]
+    Connect(String),
+    #[error(transparent)]
+    Service(#[from] rmcp::ServiceError),
+    #[error("tool {0} returned an error")]
+    ToolFailed(String),
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+/// 录制回放自定义错误类型
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("failed to read recording {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to parse recording {path} line {line}: {message}")]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    #[error("{0} differences from the recording")]
+    Mismatch(usize),
+    #[error(transparent)]
+    State(#[from] Error),
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// 一致性检查自定义错误类型
+#[derive(Debug, thiserror::Error)]
+pub enum ConformanceError {
+    #[error(transparent)]
+    InvalidHeader(#[from] InvalidHeader),
+    #[error(transparent)]
+    Client(#[from] ClientError),
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("{0} conformance checks failed")]
+    Failed(usize),
+}
+
+/// 配置自定义错误类型
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to read config file {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to parse config file {path}: {message}")]
+    Parse { path: PathBuf, message: String },
+    #[error("unsupported config file format {0}, expected .toml, .yaml or .yml")]
+    UnsupportedFormat(PathBuf),
+    #[error("{field}: {message}")]
+    Invalid { field: String, message: String },
+    #[error("failed to serialize config: {message}")]
+    Serialize { message: String },
+}
@@ -138,6 +138,7 @@ where
 #[cfg(test)]
 mod tests {
     use serde::Deserialize;
+    use serde_json::json;
 
     use super::*;
 
@@ -146,10 +147,14 @@ mod tests {
         let url = "/dynamic/resource/42/axum";
         let pattern = "/dynamic/resource/{id}/{name}";
 
-        match Path::<BTreeMap<String, Value>>::extract(url, pattern) {
-            Ok(v) => println!("{v:?}"),
-            Err(e) => println!("Error: {}", e),
-        }
+        let params = Path::<BTreeMap<String, Value>>::extract(url, pattern).unwrap();
+        assert_eq!(
+            params,
+            BTreeMap::from([
+                ("id".to_string(), json!(42)),
+                ("name".to_string(), json!("axum")),
+            ])
+        );
     }
 
     #[test]
@@ -157,17 +162,19 @@ mod tests {
         let url = "/dynamic/resource/42/axum";
         let pattern = "/dynamic/resource/{id}/{name}";
 
-        #[allow(dead_code)]
-        #[derive(Debug, Deserialize)]
+        #[derive(Debug, PartialEq, Deserialize)]
         struct Test {
             id: i32,
             name: String,
         }
 
-        match Path::<Test>::extract(url, pattern) {
-            Ok(v) => println!("{v:?}"),
-            Err(e) => println!("Error: {}", e),
-        }
+        assert_eq!(
+            Path::<Test>::extract(url, pattern).unwrap(),
+            Test {
+                id: 42,
+                name: "axum".to_string()
+            }
+        );
     }
 
     #[test]
@@ -175,17 +182,15 @@ mod tests {
         let url = "/dynamic/resource/42/axum";
         let pattern = "/dynamic/resource/{id}/{name}";
 
-        // 测试提取为元组
-        match Path::<(i32, String)>::extract(url, pattern) {
-            Ok(v) => println!("Tuple: {:?}", v),
-            Err(e) => println!("Error: {}", e),
-        }
-
-        // 测试提取为元组
-        match Path::<(String, String)>::extract(url, pattern) {
-            Ok(v) => println!("String Tuple: {:?}", v),
-            Err(e) => println!("Error: {}", e),
-        }
+        // 元组按参数名排序
+        assert_eq!(
+            Path::<(i32, String)>::extract(url, pattern).unwrap(),
+            (42, "axum".to_string())
+        );
+        // 数字参数不能解析为字符串
+        assert!(Path::<(String, String)>::extract(url, pattern).is_err());
+        assert!(Path::<(i32, String)>::extract("/dynamic/resource/42", pattern).is_err());
+        assert!(Path::<(i32, String)>::extract("/static/resource/42/axum", pattern).is_err());
     }
 
     #[test]
@@ -193,38 +198,53 @@ mod tests {
         let url = "file:///documents/xxxx.text";
         let pattern = "file:///documents/{name}.text";
 
-        // 测试提取为元组
-        match Path::<(String,)>::extract(url, pattern) {
-            Ok(v) => println!("Tuple: {:?}", v),
-            Err(e) => println!("Error: {}", e),
-        }
+        assert_eq!(
+            Path::<(String,)>::extract(url, pattern).unwrap(),
+            ("xxxx".to_string(),)
+        );
     }
 
     #[test]
     fn test_extract_file_extension() {
-        // 测试文件扩展名格式
-        let test_cases = vec![
+        let test_cases = [
             (
                 "file:///documents/report.text",
                 "file:///documents/{name}.text",
+                Some(("name", json!("report"))),
             ),
             (
                 "file:///documents/user-data.json",
                 "file:///documents/{name}.json",
+                Some(("name", json!("user-data"))),
+            ),
+            (
+                "file:///images/photo-2023.jpg",
+                "file:///images/{name}.jpg",
+                Some(("name", json!("photo-2023"))),
             ),
-            ("file:///images/photo-2023.jpg", "file:///images/{name}.jpg"),
-            ("test://dynamic/resource/42", "test://dynamic/resource/{id}"),
             (
-                "file:///documents/asdas.text",
+                "test://dynamic/resource/42",
+                "test://dynamic/resource/{id}",
+                Some(("id", json!(42))),
+            ),
+            (
+                "file:///documents/report.json",
                 "file:///documents/{name}.text",
+                None,
             ),
         ];
 
-        for (url, pattern) in test_cases {
-            println!("Testing: {} with pattern {}", url, pattern);
-            match Path::<BTreeMap<String, Value>>::extract(url, pattern) {
-                Ok(params) => println!("Success: {:?}", params),
-                Err(e) => println!("Error: {}", e),
+        for (url, pattern, expected) in test_cases {
+            let result = Path::<BTreeMap<String, Value>>::extract(url, pattern);
+            match expected {
+                Some((key, value)) => {
+                    assert_eq!(
+                        result.unwrap(),
+                        BTreeMap::from([(key.to_string(), value)]),
+                        "{url}"
+                    )
+                }
+                None => assert!(result.is_err(), "{url}"),
             }
         }
     }
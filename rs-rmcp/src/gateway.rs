@@ -0,0 +1,1154 @@
+//! 下游 MCP 服务网关
+//!
+//! 按 `[[gateway.servers]]` 启动子进程 (stdio) 或连接 SSE / streamable HTTP 服务,
+//! 把下游的工具、资源与提示词加上命名空间后合并到本服务的列表中:
+//! - 工具与提示词: `<name><separator><原名称>`, 如 `py.add`
+//! - 资源与资源模板: `<name>+<原 URI>`, 如 `py+file:///notes.txt`
+//!
+//! 调用按命名空间转发给下游, 进度通知转发给发起请求的客户端, 客户端取消或请求超时时
+//! 向下游发送 `notifications/cancelled`。下游的列表变更转发给所有客户端, 资源更新只发给
+//! 订阅了该资源的会话, 日志只发给通过 `logging/setLevel` 设置了级别且级别允许的会话。
+//! 子进程退出或连接断开后按退避时间重启。
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    process::Stdio,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+use rmcp::{
+    ClientHandler, ErrorData as McpError, Peer, RoleClient, RoleServer, ServiceExt,
+    model::{
+        CallToolRequest, CallToolRequestParam, CallToolResult, CancelledNotificationParam,
+        ClientRequest, GetPromptRequest, GetPromptRequestParam, GetPromptResult,
+        LoggingMessageNotificationParam, Meta, NumberOrString, ProgressNotificationParam,
+        ProgressToken, Prompt, ReadResourceRequest, ReadResourceRequestParam, ReadResourceResult,
+        RequestId, Resource, ResourceContents, ResourceTemplate, ResourceUpdatedNotificationParam,
+        ServerResult, SubscribeRequest, SubscribeRequestParam, Tool, UnsubscribeRequest,
+        UnsubscribeRequestParam,
+    },
+    service::{
+        NotificationContext, PeerRequestOptions, RequestContext, RunningService, ServiceError,
+    },
+    transport::{SseClientTransport, StreamableHttpClientTransport},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::GatewayError, registry::RegistryChanges, reload, state::AppState};
+
+/// 资源 URI 的命名空间分隔符, `py+file:///a` 仍是合法的 URI
+const URI_SEPARATOR: char = '+';
+
+/// 网关配置, 对应配置文件的 `[gateway]` 表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GatewayConfig {
+    /// 工具与提示词的命名空间分隔符
+    pub separator: String,
+    /// 转发请求的超时 (秒), 0 表示不限制
+    pub request_timeout_secs: u64,
+    /// 下游退出后首次重启的等待时间 (秒), 连续失败时加倍
+    pub restart_delay_secs: u64,
+    /// 重启等待时间上限 (秒)
+    pub max_restart_delay_secs: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub servers: Vec<DownstreamConfig>,
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        Self {
+            separator: ".".to_string(),
+            request_timeout_secs: 300,
+            restart_delay_secs: 1,
+            max_restart_delay_secs: 60,
+            servers: Vec::new(),
+        }
+    }
+}
+
+/// 下游服务的传输方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownstreamTransport {
+    /// 启动子进程, 通过标准输入输出通信
+    #[default]
+    Stdio,
+    Sse,
+    /// streamable HTTP
+    Http,
+}
+
+/// 下游服务, 对应 `[[gateway.servers]]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DownstreamConfig {
+    /// 命名空间
+    pub name: String,
+    #[serde(default)]
+    pub transport: DownstreamTransport,
+    /// stdio: 启动的命令
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// stdio: 工作目录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// sse / http: 服务地址
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl GatewayConfig {
+    /// 校验下游配置, 返回出错的字段与原因
+    pub fn check(&self) -> Result<(), (String, String)> {
+        if self.separator.is_empty() {
+            return Err(("gateway.separator".into(), "must not be empty".into()));
+        }
+        if self.restart_delay_secs == 0 || self.max_restart_delay_secs < self.restart_delay_secs {
+            return Err((
+                "gateway.restart_delay_secs".into(),
+                "must be greater than 0 and at most max_restart_delay_secs".into(),
+            ));
+        }
+        for (i, server) in self.servers.iter().enumerate() {
+            let field = |name: &str| format!("gateway.servers[{i}].{name}");
+            let valid_name = !server.name.is_empty()
+                && server
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            if !valid_name {
+                return Err((
+                    field("name"),
+                    format!("`{}` must be letters, digits, `_` or `-`", server.name),
+                ));
+            }
+            if self.servers[..i].iter().any(|s| s.name == server.name) {
+                return Err((field("name"), format!("duplicate name `{}`", server.name)));
+            }
+            match server.transport {
+                DownstreamTransport::Stdio if server.command.is_none() => {
+                    return Err((field("command"), "required by the stdio transport".into()));
+                }
+                DownstreamTransport::Sse | DownstreamTransport::Http => {
+                    let url = server.url.as_deref().unwrap_or_default();
+                    if !url.starts_with("http://") {
+                        return Err((field("url"), format!("`{url}` must be an http:// URL")));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn request_timeout(&self) -> Option<Duration> {
+        (self.request_timeout_secs > 0).then(|| Duration::from_secs(self.request_timeout_secs))
+    }
+}
+
+/// 下游服务加上命名空间后的列表
+#[derive(Debug, Default)]
+struct Lists {
+    tools: Vec<Tool>,
+    resources: Vec<Resource>,
+    resource_templates: Vec<ResourceTemplate>,
+    prompts: Vec<Prompt>,
+}
+
+/// 一个下游服务
+#[derive(Debug)]
+pub struct Downstream {
+    config: DownstreamConfig,
+    separator: String,
+    peer: RwLock<Option<Peer<RoleClient>>>,
+    lists: RwLock<Lists>,
+    /// 转发给下游的进度令牌 -> 发起请求的客户端与其进度令牌
+    progress: Mutex<HashMap<ProgressToken, (Peer<RoleServer>, ProgressToken)>>,
+    /// 下一个转发给下游的进度令牌
+    next_progress_token: AtomicU32,
+}
+
+impl Downstream {
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.peer.read().unwrap().is_some()
+    }
+
+    fn prefixed(&self, name: &str) -> String {
+        format!("{}{}{name}", self.config.name, self.separator)
+    }
+
+    fn prefixed_uri(&self, uri: &str) -> String {
+        format!("{}{URI_SEPARATOR}{uri}", self.config.name)
+    }
+
+    /// 启动或连接下游服务并完成初始化
+    async fn connect(
+        self: &Arc<Self>,
+        state: &Arc<AppState>,
+    ) -> Result<
+        (
+            RunningService<RoleClient, DownstreamClient>,
+            Option<tokio::process::Child>,
+        ),
+        GatewayError,
+    > {
+        let client = DownstreamClient {
+            downstream: self.clone(),
+            state: state.clone(),
+        };
+        let config = &self.config;
+        let connect_error = |e: &dyn std::fmt::Display| GatewayError::Connect {
+            name: config.name.clone(),
+            message: e.to_string(),
+        };
+        let url = config.url.clone().unwrap_or_default();
+        match config.transport {
+            DownstreamTransport::Stdio => {
+                let command = config.command.as_deref().unwrap_or_default();
+                let mut command = tokio::process::Command::new(command);
+                command
+                    .args(&config.args)
+                    .envs(&config.env)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::inherit())
+                    .kill_on_drop(true);
+                if let Some(cwd) = &config.cwd {
+                    command.current_dir(cwd);
+                }
+                let mut child = command.spawn().map_err(|source| GatewayError::Spawn {
+                    name: config.name.clone(),
+                    source,
+                })?;
+                let (Some(stdout), Some(stdin)) = (child.stdout.take(), child.stdin.take()) else {
+                    return Err(connect_error(&"stdio is not piped"));
+                };
+                let service = client
+                    .serve((stdout, stdin))
+                    .await
+                    .map_err(|e| connect_error(&e))?;
+                Ok((service, Some(child)))
+            }
+            DownstreamTransport::Sse => {
+                let transport = SseClientTransport::start(url)
+                    .await
+                    .map_err(|e| connect_error(&e))?;
+                let service = client
+                    .serve(transport)
+                    .await
+                    .map_err(|e| connect_error(&e))?;
+                Ok((service, None))
+            }
+            DownstreamTransport::Http => {
+                let transport = StreamableHttpClientTransport::from_uri(url);
+                let service = client
+                    .serve(transport)
+                    .await
+                    .map_err(|e| connect_error(&e))?;
+                Ok((service, None))
+            }
+        }
+    }
+
+    /// 重新获取下游的列表, 下游未声明的能力视为空列表
+    async fn refresh(&self) -> RegistryChanges {
+        let Some(peer) = self.peer.read().unwrap().clone() else {
+            *self.lists.write().unwrap() = Lists::default();
+            return RegistryChanges {
+                tools: true,
+                resources: true,
+                prompts: true,
+            };
+        };
+        let capabilities = peer
+            .peer_info()
+            .map(|info| info.capabilities.clone())
+            .unwrap_or_default();
+        let warn = |list: &str, e: ServiceError| {
+            tracing::warn!(server = self.name(), "failed to list {list}: {e}");
+        };
+
+        let mut lists = Lists::default();
+        if capabilities.tools.is_some() {
+            match peer.list_all_tools().await {
+                Ok(tools) => {
+                    lists.tools = tools
+                        .into_iter()
+                        .map(|mut tool| {
+                            tool.name = self.prefixed(&tool.name).into();
+                            tool
+                        })
+                        .collect();
+                }
+                Err(e) => warn("tools", e),
+            }
+        }
+        if capabilities.resources.is_some() {
+            match peer.list_all_resources().await {
+                Ok(resources) => {
+                    lists.resources = resources
+                        .into_iter()
+                        .map(|mut resource| {
+                            resource.raw.uri = self.prefixed_uri(&resource.raw.uri);
+                            resource
+                        })
+                        .collect();
+                }
+                Err(e) => warn("resources", e),
+            }
+            match peer.list_all_resource_templates().await {
+                Ok(templates) => {
+                    lists.resource_templates = templates
+                        .into_iter()
+                        .map(|mut template| {
+                            template.raw.uri_template =
+                                self.prefixed_uri(&template.raw.uri_template);
+                            template
+                        })
+                        .collect();
+                }
+                Err(e) => warn("resource templates", e),
+            }
+        }
+        if capabilities.prompts.is_some() {
+            match peer.list_all_prompts().await {
+                Ok(prompts) => {
+                    lists.prompts = prompts
+                        .into_iter()
+                        .map(|mut prompt| {
+                            prompt.name = self.prefixed(&prompt.name);
+                            prompt
+                        })
+                        .collect();
+                }
+                Err(e) => warn("prompts", e),
+            }
+        }
+        tracing::info!(
+            server = self.name(),
+            tools = lists.tools.len(),
+            resources = lists.resources.len(),
+            prompts = lists.prompts.len(),
+            "downstream lists refreshed"
+        );
+        *self.lists.write().unwrap() = lists;
+        RegistryChanges {
+            tools: capabilities.tools.is_some(),
+            resources: capabilities.resources.is_some(),
+            prompts: capabilities.prompts.is_some(),
+        }
+    }
+
+    /// 转发请求, 客户端取消或超时时通知下游取消
+    async fn forward(
+        &self,
+        request: ClientRequest,
+        context: &RequestContext<RoleServer>,
+        timeout: Option<Duration>,
+    ) -> Result<ServerResult, McpError> {
+        let unavailable =
+            || McpError::internal_error(format!("server {} is unavailable", self.name()), None);
+        let peer = self.peer.read().unwrap().clone().ok_or_else(unavailable)?;
+        // 发送前登记进度令牌, 下游立即发出的进度通知也能转发给客户端
+        let progress_token = ProgressToken(NumberOrString::Number(
+            self.next_progress_token.fetch_add(1, Ordering::Relaxed),
+        ));
+        if let Some(token) = context.meta.get_progress_token() {
+            self.progress
+                .lock()
+                .unwrap()
+                .insert(progress_token.clone(), (context.peer.clone(), token));
+        }
+        let mut meta = Meta::new();
+        meta.set_progress_token(progress_token.clone());
+        let options = PeerRequestOptions {
+            timeout,
+            meta: Some(meta),
+        };
+        let handle = match peer.send_request_with_option(request, options).await {
+            Ok(handle) => handle,
+            Err(_) => {
+                self.progress.lock().unwrap().remove(&progress_token);
+                return Err(unavailable());
+            }
+        };
+        let mut in_flight = InFlight {
+            downstream: self,
+            peer,
+            id: handle.id.clone(),
+            progress_token,
+            done: false,
+        };
+
+        let result = handle.await_response().await;
+        in_flight.done = !matches!(result, Err(ServiceError::Timeout { .. }));
+        result.map_err(|e| match e {
+            ServiceError::McpError(error) => error,
+            ServiceError::TransportClosed => unavailable(),
+            e => McpError::internal_error(format!("server {}: {e}", self.name()), None),
+        })
+    }
+}
+
+/// 转发中的请求, 未收到响应就被丢弃 (客户端取消或超时) 时通知下游取消
+struct InFlight<'a> {
+    downstream: &'a Downstream,
+    peer: Peer<RoleClient>,
+    id: RequestId,
+    progress_token: ProgressToken,
+    done: bool,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.downstream
+            .progress
+            .lock()
+            .unwrap()
+            .remove(&self.progress_token);
+        if self.done {
+            return;
+        }
+        let peer = self.peer.clone();
+        let params = CancelledNotificationParam {
+            request_id: self.id.clone(),
+            reason: Some("cancelled by the gateway client".to_string()),
+        };
+        tokio::spawn(async move {
+            if let Err(e) = peer.notify_cancelled(params).await {
+                tracing::debug!("failed to cancel downstream request: {e}");
+            }
+        });
+    }
+}
+
+/// 网关, 持有所有下游服务
+#[derive(Debug)]
+pub struct Gateway {
+    config: GatewayConfig,
+    downstreams: Vec<Arc<Downstream>>,
+}
+
+impl Gateway {
+    pub fn new(config: &GatewayConfig) -> Self {
+        let downstreams = config
+            .servers
+            .iter()
+            .map(|server| {
+                Arc::new(Downstream {
+                    config: server.clone(),
+                    separator: config.separator.clone(),
+                    peer: RwLock::default(),
+                    lists: RwLock::default(),
+                    progress: Mutex::default(),
+                    next_progress_token: AtomicU32::default(),
+                })
+            })
+            .collect();
+        Self {
+            config: config.clone(),
+            downstreams,
+        }
+    }
+
+    pub fn downstreams(&self) -> &[Arc<Downstream>] {
+        &self.downstreams
+    }
+
+    pub fn tools(&self) -> Vec<Tool> {
+        self.collect(|lists| &lists.tools)
+    }
+
+    pub fn resources(&self) -> Vec<Resource> {
+        self.collect(|lists| &lists.resources)
+    }
+
+    pub fn resource_templates(&self) -> Vec<ResourceTemplate> {
+        self.collect(|lists| &lists.resource_templates)
+    }
+
+    pub fn prompts(&self) -> Vec<Prompt> {
+        self.collect(|lists| &lists.prompts)
+    }
+
+    fn collect<T: Clone>(&self, list: impl Fn(&Lists) -> &Vec<T>) -> Vec<T> {
+        self.downstreams
+            .iter()
+            .flat_map(|d| list(&d.lists.read().unwrap()).clone())
+            .collect()
+    }
+
+    /// 下游列出的工具, 返回下游与原名称
+    pub fn route_tool(&self, name: &str) -> Option<(&Arc<Downstream>, String)> {
+        self.route(name)
+            .filter(|(d, _)| d.lists.read().unwrap().tools.iter().any(|t| t.name == name))
+    }
+
+    /// 下游列出的提示词, 返回下游与原名称
+    pub fn route_prompt(&self, name: &str) -> Option<(&Arc<Downstream>, String)> {
+        self.route(name).filter(|(d, _)| {
+            d.lists
+                .read()
+                .unwrap()
+                .prompts
+                .iter()
+                .any(|p| p.name == name)
+        })
+    }
+
+    /// 按命名空间找到下游与原名称
+    fn route(&self, name: &str) -> Option<(&Arc<Downstream>, String)> {
+        self.downstreams.iter().find_map(|d| {
+            let rest = name.strip_prefix(d.name())?;
+            Some((d, rest.strip_prefix(&self.config.separator)?.to_string()))
+        })
+    }
+
+    /// 按命名空间找到下游与原 URI
+    pub fn route_uri(&self, uri: &str) -> Option<(&Arc<Downstream>, String)> {
+        self.downstreams.iter().find_map(|d| {
+            let rest = uri.strip_prefix(d.name())?;
+            Some((d, rest.strip_prefix(URI_SEPARATOR)?.to_string()))
+        })
+    }
+
+    pub async fn call_tool(
+        &self,
+        downstream: &Downstream,
+        mut request: CallToolRequestParam,
+        name: String,
+        context: &RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        request.name = name.into();
+        let request = ClientRequest::CallToolRequest(CallToolRequest::new(request));
+        match downstream
+            .forward(request, context, self.config.request_timeout())
+            .await?
+        {
+            ServerResult::CallToolResult(result) => Ok(result),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    pub async fn read_resource(
+        &self,
+        downstream: &Downstream,
+        uri: String,
+        context: &RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        let request = ClientRequest::ReadResourceRequest(ReadResourceRequest::new(
+            ReadResourceRequestParam { uri },
+        ));
+        match downstream
+            .forward(request, context, self.config.request_timeout())
+            .await?
+        {
+            ServerResult::ReadResourceResult(mut result) => {
+                for contents in &mut result.contents {
+                    let (ResourceContents::TextResourceContents { uri, .. }
+                    | ResourceContents::BlobResourceContents { uri, .. }) = contents;
+                    *uri = downstream.prefixed_uri(uri);
+                }
+                Ok(result)
+            }
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    /// 转发资源订阅或取消订阅
+    pub async fn subscribe(
+        &self,
+        downstream: &Downstream,
+        uri: String,
+        subscribe: bool,
+        context: &RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let request = match subscribe {
+            true => ClientRequest::SubscribeRequest(SubscribeRequest::new(SubscribeRequestParam {
+                uri,
+            })),
+            false => ClientRequest::UnsubscribeRequest(UnsubscribeRequest::new(
+                UnsubscribeRequestParam { uri },
+            )),
+        };
+        match downstream
+            .forward(request, context, self.config.request_timeout())
+            .await?
+        {
+            ServerResult::EmptyResult(_) => Ok(()),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    pub async fn get_prompt(
+        &self,
+        downstream: &Downstream,
+        mut request: GetPromptRequestParam,
+        name: String,
+        context: &RequestContext<RoleServer>,
+    ) -> Result<GetPromptResult, McpError> {
+        request.name = name;
+        let request = ClientRequest::GetPromptRequest(GetPromptRequest::new(request));
+        match downstream
+            .forward(request, context, self.config.request_timeout())
+            .await?
+        {
+            ServerResult::GetPromptResult(result) => Ok(result),
+            _ => Err(unexpected_response()),
+        }
+    }
+}
+
+fn unexpected_response() -> McpError {
+    McpError::internal_error("unexpected response from downstream server", None)
+}
+
+/// 启动所有下游服务, 各自在后台保持连接
+pub fn spawn(state: &Arc<AppState>) {
+    for downstream in state.gateway.downstreams() {
+        tokio::spawn(supervise(state.clone(), downstream.clone()));
+    }
+}
+
+/// 连接下游并在退出后重启
+async fn supervise(state: Arc<AppState>, downstream: Arc<Downstream>) {
+    let config = &state.config.gateway;
+    let initial_delay = Duration::from_secs(config.restart_delay_secs);
+    let max_delay = Duration::from_secs(config.max_restart_delay_secs);
+    let mut delay = initial_delay;
+    loop {
+        match downstream.connect(&state).await {
+            Ok((service, mut child)) => {
+                tracing::info!(server = downstream.name(), "downstream server connected");
+                delay = initial_delay;
+                *downstream.peer.write().unwrap() = Some(service.peer().clone());
+                let changes = downstream.refresh().await;
+                reload::notify(&state, &changes);
+
+                let reason = service.waiting().await;
+                *downstream.peer.write().unwrap() = None;
+                let changes = downstream.refresh().await;
+                reload::notify(&state, &changes);
+                let status = match &mut child {
+                    Some(child) => {
+                        let _ = child.start_kill();
+                        child.wait().await.ok()
+                    }
+                    None => None,
+                };
+                tracing::warn!(
+                    server = downstream.name(),
+                    ?reason,
+                    ?status,
+                    "downstream server disconnected, restarting in {}s",
+                    delay.as_secs()
+                );
+            }
+            Err(e) => {
+                tracing::error!("{e}, retrying in {}s", delay.as_secs());
+            }
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(max_delay);
+    }
+}
+
+/// 连接下游时使用的客户端, 把下游的通知转发给本服务的客户端
+#[derive(Debug, Clone)]
+struct DownstreamClient {
+    downstream: Arc<Downstream>,
+    state: Arc<AppState>,
+}
+
+impl ClientHandler for DownstreamClient {
+    async fn on_progress(
+        &self,
+        mut params: ProgressNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        let target = self
+            .downstream
+            .progress
+            .lock()
+            .unwrap()
+            .get(&params.progress_token)
+            .cloned();
+        if let Some((peer, token)) = target {
+            params.progress_token = token;
+            if let Err(e) = peer.notify_progress(params).await {
+                tracing::debug!("failed to forward progress: {e}");
+            }
+        }
+    }
+
+    async fn on_logging_message(
+        &self,
+        mut params: LoggingMessageNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        if !self.state.config.capabilities.logging {
+            return;
+        }
+        let name = self.downstream.name();
+        params.logger = Some(match params.logger {
+            Some(logger) => self.downstream.prefixed(&logger),
+            None => name.to_string(),
+        });
+        for peer in self.state.sessions.log_receivers(params.level) {
+            let _ = peer.notify_logging_message(params.clone()).await;
+        }
+    }
+
+    async fn on_resource_updated(
+        &self,
+        params: ResourceUpdatedNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        let params = ResourceUpdatedNotificationParam {
+            uri: self.downstream.prefixed_uri(&params.uri),
+        };
+        for peer in self.state.sessions.subscribers(&params.uri) {
+            let _ = peer.notify_resource_updated(params.clone()).await;
+        }
+    }
+
+    async fn on_tool_list_changed(&self, _context: NotificationContext<RoleClient>) {
+        self.refresh().await;
+    }
+
+    async fn on_resource_list_changed(&self, _context: NotificationContext<RoleClient>) {
+        self.refresh().await;
+    }
+
+    async fn on_prompt_list_changed(&self, _context: NotificationContext<RoleClient>) {
+        self.refresh().await;
+    }
+}
+
+impl DownstreamClient {
+    async fn refresh(&self) {
+        let changes = self.downstream.refresh().await;
+        reload::notify(&self.state, &changes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rmcp::{
+        ServerHandler,
+        model::{
+            ListResourcesResult, ListToolsResult, LoggingLevel, PaginatedRequestParam,
+            ServerCapabilities, ServerInfo, ServerNotification, SetLevelRequestParam,
+        },
+        transport::streamable_http_server::{
+            StreamableHttpServerConfig, StreamableHttpService, session::local::LocalSessionManager,
+        },
+    };
+    use tokio::sync::{Notify, mpsc};
+
+    use super::*;
+    use crate::{
+        audit::{self, AuditConfig, AuditQuery},
+        calculator,
+        config::Config,
+        metrics::SessionTransport,
+        testing::Harness,
+    };
+
+    #[test]
+    fn test_route_and_check() {
+        let server = |name: &str| DownstreamConfig {
+            name: name.to_string(),
+            transport: DownstreamTransport::Stdio,
+            command: Some("python".to_string()),
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            cwd: None,
+            url: None,
+        };
+        let config = GatewayConfig {
+            servers: vec![server("py"), server("py2")],
+            ..Default::default()
+        };
+        assert!(config.check().is_ok());
+        let gateway = Gateway::new(&config);
+
+        let (downstream, name) = gateway.route("py2.add").unwrap();
+        assert_eq!((downstream.name(), name.as_str()), ("py2", "add"));
+        let (downstream, name) = gateway.route("py.a.b").unwrap();
+        assert_eq!((downstream.name(), name.as_str()), ("py", "a.b"));
+        assert!(gateway.route("sum").is_none());
+        assert!(gateway.route("pyadd").is_none());
+        // 只路由下游列出的名称
+        assert!(gateway.route_tool("py.add").is_none());
+        let schema = Arc::new(serde_json::Map::new());
+        gateway.downstreams[0].lists.write().unwrap().tools =
+            vec![Tool::new("py.add", "add", schema)];
+        assert_eq!(gateway.route_tool("py.add").unwrap().1, "add");
+        assert!(gateway.route_tool("py.sub").is_none());
+        assert!(gateway.route_prompt("py.add").is_none());
+        let (downstream, uri) = gateway.route_uri("py+file:///a.txt").unwrap();
+        assert_eq!((downstream.name(), uri.as_str()), ("py", "file:///a.txt"));
+        assert!(gateway.route_uri("file:///a.txt").is_none());
+
+        let duplicate = GatewayConfig {
+            servers: vec![server("py"), server("py")],
+            ..Default::default()
+        };
+        assert_eq!(duplicate.check().unwrap_err().0, "gateway.servers[1].name");
+        let http = GatewayConfig {
+            servers: vec![DownstreamConfig {
+                transport: DownstreamTransport::Http,
+                ..server("go")
+            }],
+            ..Default::default()
+        };
+        assert_eq!(http.check().unwrap_err().0, "gateway.servers[0].url");
+    }
+
+    /// 发送一次进度后等待取消的下游服务
+    #[derive(Clone)]
+    struct WaitServer(Arc<Notify>);
+
+    impl ServerHandler for WaitServer {
+        fn get_info(&self) -> ServerInfo {
+            ServerInfo {
+                capabilities: ServerCapabilities::builder().enable_tools().build(),
+                ..Default::default()
+            }
+        }
+
+        async fn list_tools(
+            &self,
+            _request: Option<PaginatedRequestParam>,
+            _context: RequestContext<RoleServer>,
+        ) -> Result<ListToolsResult, McpError> {
+            let schema = Arc::new(serde_json::Map::new());
+            Ok(ListToolsResult::with_all_items(vec![Tool::new(
+                "wait",
+                "wait until cancelled",
+                schema,
+            )]))
+        }
+
+        async fn call_tool(
+            &self,
+            _request: CallToolRequestParam,
+            context: RequestContext<RoleServer>,
+        ) -> Result<CallToolResult, McpError> {
+            let token = context.meta.get_progress_token().expect("progress token");
+            let _ = context
+                .peer
+                .notify_progress(ProgressNotificationParam {
+                    progress_token: token,
+                    progress: 1.0,
+                    total: None,
+                    message: None,
+                })
+                .await;
+            context.ct.cancelled().await;
+            self.0.notify_one();
+            Err(McpError::internal_error("cancelled", None))
+        }
+    }
+
+    /// 把收到的进度通知发到通道
+    struct ProgressClient(mpsc::UnboundedSender<ProgressNotificationParam>);
+
+    impl ClientHandler for ProgressClient {
+        async fn on_progress(
+            &self,
+            params: ProgressNotificationParam,
+            _context: NotificationContext<RoleClient>,
+        ) {
+            let _ = self.0.send(params);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forward_progress_and_cancellation() {
+        let cancelled = Arc::new(Notify::new());
+        let downstream = WaitServer(cancelled.clone());
+        let service = StreamableHttpService::new(
+            move || Ok(downstream.clone()),
+            Arc::new(LocalSessionManager::default()),
+            StreamableHttpServerConfig::default(),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        let app = axum::Router::new().nest_service("/mcp", service);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config = Config {
+            gateway: GatewayConfig {
+                servers: vec![DownstreamConfig {
+                    name: "slow".to_string(),
+                    transport: DownstreamTransport::Http,
+                    command: None,
+                    args: Vec::new(),
+                    env: BTreeMap::new(),
+                    cwd: None,
+                    url: Some(url),
+                }],
+                ..Default::default()
+            },
+            audit: AuditConfig {
+                enabled: true,
+                path: std::env::temp_dir().join(format!(
+                    "rs-mcpr-gateway-audit-{}.jsonl",
+                    std::process::id()
+                )),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let _ = std::fs::remove_file(&config.audit.path);
+        let state = Arc::new(AppState::new(config).unwrap());
+        spawn(&state);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while state.gateway.tools().is_empty() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("downstream connected");
+        assert_eq!(state.gateway.tools()[0].name, "slow.wait");
+
+        // 客户端经由网关调用下游工具
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        let server = calculator::session_service(state.clone(), SessionTransport::Stdio);
+        tokio::spawn(async move {
+            let server = server.serve(tokio::io::split(server_io)).await.unwrap();
+            let _ = server.waiting().await;
+        });
+        let (tx, mut progress) = mpsc::unbounded_channel();
+        let client = ProgressClient(tx)
+            .serve(tokio::io::split(client_io))
+            .await
+            .unwrap();
+        let request = ClientRequest::CallToolRequest(CallToolRequest::new(CallToolRequestParam {
+            name: "slow.wait".into(),
+            arguments: None,
+        }));
+        let handle = client
+            .peer()
+            .send_request_with_option(request, PeerRequestOptions::no_options())
+            .await
+            .unwrap();
+
+        let params = tokio::time::timeout(Duration::from_secs(5), progress.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(params.progress_token, handle.progress_token);
+        assert_eq!(params.progress, 1.0);
+
+        // 下游没有列出的名称不转发
+        let error = client
+            .peer()
+            .call_tool(CallToolRequestParam {
+                name: "slow.missing".into(),
+                arguments: None,
+            })
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("tool not found"));
+
+        handle.cancel(None).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), cancelled.notified())
+            .await
+            .expect("downstream request cancelled");
+
+        // 被取消的调用同样写入审计日志
+        let filter = AuditQuery {
+            target: Some("slow.wait".to_string()),
+            ..Default::default()
+        };
+        let records = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let records = audit::query(&state.config.audit, &filter).unwrap();
+                if !records.is_empty() {
+                    break records;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("audit record written");
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].outcome, "cancelled");
+        assert_eq!(records[0].error_code, Some(-32800));
+        std::fs::remove_file(&state.config.audit.path).unwrap();
+    }
+
+    /// 调用工具时发送一条错误日志与 `file:///a` 的资源更新
+    #[derive(Clone)]
+    struct NotifyServer;
+
+    impl ServerHandler for NotifyServer {
+        fn get_info(&self) -> ServerInfo {
+            ServerInfo {
+                capabilities: ServerCapabilities::builder()
+                    .enable_tools()
+                    .enable_resources()
+                    .enable_resources_subscribe()
+                    .enable_logging()
+                    .build(),
+                ..Default::default()
+            }
+        }
+
+        async fn list_tools(
+            &self,
+            _request: Option<PaginatedRequestParam>,
+            _context: RequestContext<RoleServer>,
+        ) -> Result<ListToolsResult, McpError> {
+            let schema = Arc::new(serde_json::Map::new());
+            Ok(ListToolsResult::with_all_items(vec![Tool::new(
+                "notify",
+                "send notifications",
+                schema,
+            )]))
+        }
+
+        async fn list_resources(
+            &self,
+            _request: Option<PaginatedRequestParam>,
+            _context: RequestContext<RoleServer>,
+        ) -> Result<ListResourcesResult, McpError> {
+            Ok(ListResourcesResult::default())
+        }
+
+        async fn subscribe(
+            &self,
+            _request: SubscribeRequestParam,
+            _context: RequestContext<RoleServer>,
+        ) -> Result<(), McpError> {
+            Ok(())
+        }
+
+        async fn call_tool(
+            &self,
+            _request: CallToolRequestParam,
+            context: RequestContext<RoleServer>,
+        ) -> Result<CallToolResult, McpError> {
+            let _ = context
+                .peer
+                .notify_logging_message(LoggingMessageNotificationParam {
+                    level: LoggingLevel::Error,
+                    logger: None,
+                    data: serde_json::json!("boom"),
+                })
+                .await;
+            let _ = context
+                .peer
+                .notify_resource_updated(ResourceUpdatedNotificationParam {
+                    uri: "file:///a".to_string(),
+                })
+                .await;
+            Ok(CallToolResult::success(Vec::new()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forward_notifications_to_interested_sessions() {
+        let service = StreamableHttpService::new(
+            || Ok(NotifyServer),
+            Arc::new(LocalSessionManager::default()),
+            StreamableHttpServerConfig::default(),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        let app = axum::Router::new().nest_service("/mcp", service);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut config = Config {
+            gateway: GatewayConfig {
+                servers: vec![DownstreamConfig {
+                    name: "up".to_string(),
+                    transport: DownstreamTransport::Http,
+                    command: None,
+                    args: Vec::new(),
+                    env: BTreeMap::new(),
+                    cwd: None,
+                    url: Some(url),
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        config.capabilities.logging = true;
+        let state = Arc::new(AppState::new(config).unwrap());
+        spawn(&state);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while state.gateway.tools().is_empty() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("downstream connected");
+
+        let connect = || {
+            Harness::duplex(calculator::session_service(
+                state.clone(),
+                SessionTransport::Stdio,
+            ))
+        };
+        let mut interested = connect().await;
+        let mut other = connect().await;
+        let capabilities = &interested.server_info().capabilities;
+        assert_eq!(
+            capabilities.resources.as_ref().unwrap().subscribe,
+            Some(true)
+        );
+
+        interested
+            .set_level(SetLevelRequestParam {
+                level: LoggingLevel::Warning,
+            })
+            .await
+            .unwrap();
+        interested
+            .subscribe(SubscribeRequestParam {
+                uri: "up+file:///a".to_string(),
+            })
+            .await
+            .unwrap();
+        other.call_tool("up.notify", serde_json::Value::Null).await;
+
+        let mut notifications = Vec::new();
+        for _ in 0..2 {
+            notifications.push(match interested.notification().await {
+                ServerNotification::LoggingMessageNotification(n) => {
+                    format!("log {}", n.params.data)
+                }
+                ServerNotification::ResourceUpdatedNotification(n) => {
+                    format!("updated {}", n.params.uri)
+                }
+                other => panic!("unexpected {other:?}"),
+            });
+        }
+        notifications.sort();
+        assert_eq!(notifications, ["log \"boom\"", "updated up+file:///a"]);
+
+        // 未设置日志级别、未订阅的会话不接收
+        other
+            .assert_no_notification(Duration::from_millis(100))
+            .await;
+        interested.close().await;
+        other.close().await;
+    }
+}
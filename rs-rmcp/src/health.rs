@@ -0,0 +1,199 @@
+//! 健康检查与服务信息
+//!
+//! - `GET /healthz`: 进程存活
+//! - `GET /readyz`: 监听已绑定、注册表中的文件可读、审计存储可写, 开始优雅关闭后返回 503
+//! - `GET /info`: 版本、传输方式、能力与活跃会话数
+//!
+//! 这些路由不经过限流与 Origin 校验, 便于编排系统探测。
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse, routing::get};
+use rmcp::model::Implementation;
+use serde_json::{Map, Value, json};
+
+use crate::{config::Transport, metrics::SessionTransport, state::AppState, unix::UnixProtocol};
+
+pub const HEALTHZ_PATH: &str = "/healthz";
+pub const READYZ_PATH: &str = "/readyz";
+pub const INFO_PATH: &str = "/info";
+
+/// 服务生命周期
+#[derive(Debug, Default)]
+pub struct Health {
+    listening: AtomicBool,
+    shutting_down: AtomicBool,
+}
+
+impl Health {
+    /// 监听地址已绑定
+    pub fn set_listening(&self) {
+        self.listening.store(true, Ordering::Release);
+    }
+
+    /// 开始优雅关闭, 此后不再就绪
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::Release);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Acquire)
+    }
+}
+
+pub fn router(state: Arc<AppState>) -> axum::Router {
+    axum::Router::new()
+        .route(HEALTHZ_PATH, get(healthz))
+        .route(READYZ_PATH, get(readyz))
+        .route(INFO_PATH, get(info))
+        .with_state(state)
+}
+
+/// `GET /healthz`
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// 各项就绪检查的结果, 失败时为原因
+fn readiness(state: &AppState) -> Vec<(&'static str, Result<(), String>)> {
+    let health = &state.health;
+    let lifecycle = if health.is_shutting_down() {
+        Err("shutting down".to_string())
+    } else if !health.listening.load(Ordering::Acquire) {
+        Err("listener not bound".to_string())
+    } else {
+        Ok(())
+    };
+
+    let registry = state.registry();
+    let readme = registry
+        .resource_enabled("docs://readme")
+        .then_some(&registry.readme);
+    let registry = readme
+        .into_iter()
+        .chain(registry.files.values())
+        .try_for_each(|path| match path.is_file() {
+            true => Ok(()),
+            false => Err(format!("{} is not readable", path.display())),
+        });
+
+    let audit = state.audit.check().map_err(|e| e.to_string());
+
+    let disconnected: Vec<&str> = state
+        .gateway
+        .downstreams()
+        .iter()
+        .filter(|d| !d.is_connected())
+        .map(|d| d.name())
+        .collect();
+    let gateway = match disconnected.is_empty() {
+        true => Ok(()),
+        false => Err(format!("{} not connected", disconnected.join(", "))),
+    };
+
+    vec![
+        ("lifecycle", lifecycle),
+        ("registry", registry),
+        ("audit", audit),
+        ("gateway", gateway),
+    ]
+}
+
+/// `GET /readyz`
+async fn readyz(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let checks = readiness(&state);
+    let ready = checks.iter().all(|(_, result)| result.is_ok());
+    let checks: Map<String, Value> = checks
+        .into_iter()
+        .map(|(name, result)| {
+            let value = match result {
+                Ok(()) => json!("ok"),
+                Err(reason) => json!(reason),
+            };
+            (name.to_string(), value)
+        })
+        .collect();
+    let status = match ready {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(json!({ "ready": ready, "checks": checks })))
+}
+
+/// `GET /info`
+///
+/// `server_info` 与 initialize 响应一致, `build` 为本程序的包名与版本。
+async fn info(State(state): State<Arc<AppState>>) -> Json<Value> {
+    let config = &state.config;
+    let transports = match config.transport {
+        Transport::Stdio => vec![SessionTransport::Stdio],
+        Transport::Http => vec![SessionTransport::StreamableHttp, SessionTransport::Sse],
+        Transport::Websocket => vec![SessionTransport::Websocket],
+        Transport::Unix => match config.unix.protocol {
+            UnixProtocol::Http => vec![SessionTransport::StreamableHttp, SessionTransport::Sse],
+            UnixProtocol::Jsonrpc => vec![SessionTransport::Unix],
+        },
+    };
+    let mut endpoints = match config.transport {
+        Transport::Websocket => json!({ "websocket": config.http.ws_path }),
+        _ => json!({
+            "streamable_http": config.http.mcp_path,
+            "sse": config.http.sse_path,
+            "sse_post": config.http.post_path,
+        }),
+    };
+    if config.transport == Transport::Unix {
+        endpoints["unix_socket"] = json!(config.unix.path);
+    }
+    let sessions: Map<String, Value> = transports
+        .iter()
+        .map(|t| {
+            (
+                t.as_str().to_string(),
+                json!(state.metrics.active_sessions(*t)),
+            )
+        })
+        .collect();
+
+    Json(json!({
+        "server_info": Implementation::from_build_env(),
+        "build": {
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "transports": transports.iter().map(SessionTransport::as_str).collect::<Vec<_>>(),
+        "endpoints": endpoints,
+        "tls": config.tls.is_some(),
+        "capabilities": config.capabilities.to_capabilities(),
+        "sessions": sessions,
+        "shutting_down": state.health.is_shutting_down(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+
+    use super::*;
+
+    #[test]
+    fn test_readiness() {
+        let state = AppState::new(Config::default()).unwrap();
+        let failed = |state: &AppState| {
+            readiness(state)
+                .into_iter()
+                .filter(|(_, result)| result.is_err())
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(failed(&state), ["lifecycle"]);
+
+        state.health.set_listening();
+        assert!(failed(&state).is_empty());
+
+        state.health.begin_shutdown();
+        assert_eq!(failed(&state), ["lifecycle"]);
+    }
+}
@@ -0,0 +1,197 @@
+//! streamable HTTP 会话管理
+//!
+//! 在 rmcp 的 [`LocalSessionManager`] 之外:
+//! - 每个会话一个 [`EventLog`], 响应流由后台任务读取写入日志, 客户端断开后事件不丢失,
+//!   携带 `Last-Event-ID` 重连时续传
+//! - 启用 `cluster` 时把会话登记到共享存储, 见 [`crate::cluster`]
+//! - 启用 `record` 时录制会话收发的消息, 见 [`crate::recording`]
+//! - initialize 请求还没有 `Mcp-Session-Id`, 把分配的 [`SessionId`] 放入请求扩展,
+//!   会话登记在 initialize 时即可取得 ID, 只发送 initialize 的会话同样受过期检查
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use futures::{Stream, StreamExt};
+use rmcp::{
+    model::{ClientJsonRpcMessage, ServerJsonRpcMessage},
+    transport::{
+        common::server_side_http::ServerSseMessage,
+        streamable_http_server::{
+            SessionId, SessionManager,
+            session::local::{LocalSessionManager, LocalSessionManagerError, LocalSessionWorker},
+        },
+        worker::WorkerTransport,
+    },
+};
+
+use crate::{
+    cluster::Cluster,
+    config::Config,
+    error::{Error, HttpSessionError},
+    metrics::SessionTransport,
+    recording::{Recorded, Recorder},
+    resumable::{EventLog, EventLogConfig, EventStream, event_id, parse_event_id},
+};
+
+#[derive(Debug)]
+pub struct HttpSessionManager {
+    local: LocalSessionManager,
+    cluster: Option<Cluster>,
+    event_log: EventLogConfig,
+    logs: Mutex<HashMap<SessionId, Arc<EventLog<ServerSseMessage>>>>,
+    recorder: Recorder,
+}
+
+impl HttpSessionManager {
+    pub fn new(config: &Config, recorder: Recorder) -> Result<Self, Error> {
+        let cluster = match config.cluster.enabled {
+            true => Some(Cluster::new(&config.cluster)?),
+            false => None,
+        };
+        Ok(Self {
+            local: LocalSessionManager::default(),
+            cluster,
+            event_log: config.event_log.clone(),
+            logs: Mutex::default(),
+            recorder,
+        })
+    }
+
+    pub fn cluster(&self) -> Option<&Cluster> {
+        self.cluster.as_ref()
+    }
+
+    /// 会话是否在本副本
+    pub async fn is_local(&self, id: &str) -> bool {
+        self.local.sessions.read().await.contains_key(id)
+    }
+
+    /// 副本退出时从共享存储中移除本副本的会话
+    pub fn deregister(&self) {
+        if let Some(cluster) = &self.cluster
+            && let Err(e) = cluster.deregister()
+        {
+            tracing::error!("failed to deregister sessions: {e}");
+        }
+    }
+
+    fn log(&self, id: &SessionId) -> Result<Arc<EventLog<ServerSseMessage>>, HttpSessionError> {
+        self.logs
+            .lock()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| LocalSessionManagerError::SessionNotFound(id.clone()).into())
+    }
+
+    /// 后台读取 rmcp 的响应流写入日志, 返回日志的订阅
+    fn pump(
+        log: Arc<EventLog<ServerSseMessage>>,
+        events: impl Stream<Item = ServerSseMessage> + Send + 'static,
+    ) -> EventStream<ServerSseMessage> {
+        let stream = log.open_stream();
+        let subscription = log.subscribe(stream, None).expect("stream is open");
+        tokio::spawn(async move {
+            let mut events = std::pin::pin!(events);
+            loop {
+                tokio::select! {
+                    message = events.next() => match message {
+                        Some(message) => log.push(stream, |seq| ServerSseMessage {
+                            event_id: Some(event_id(stream, seq)),
+                            message: message.message,
+                        }),
+                        None => break,
+                    },
+                    _ = log.closed() => break,
+                }
+            }
+            log.finish(stream);
+        });
+        subscription
+    }
+}
+
+impl SessionManager for HttpSessionManager {
+    type Error = HttpSessionError;
+    type Transport = Recorded<WorkerTransport<LocalSessionWorker>>;
+
+    async fn create_session(&self) -> Result<(SessionId, Self::Transport), Self::Error> {
+        let (id, transport) = self.local.create_session().await?;
+        if let Some(cluster) = &self.cluster
+            && let Err(e) = cluster.register(&id)
+        {
+            self.local.close_session(&id).await?;
+            return Err(e.into());
+        }
+        self.logs
+            .lock()
+            .unwrap()
+            .insert(id.clone(), Arc::new(EventLog::new(&self.event_log)));
+        let transport = self
+            .recorder
+            .wrap(SessionTransport::StreamableHttp, &id, transport);
+        Ok((id, transport))
+    }
+
+    async fn initialize_session(
+        &self,
+        id: &SessionId,
+        mut message: ClientJsonRpcMessage,
+    ) -> Result<ServerJsonRpcMessage, Self::Error> {
+        message.insert_extension(id.clone());
+        Ok(self.local.initialize_session(id, message).await?)
+    }
+
+    async fn has_session(&self, id: &SessionId) -> Result<bool, Self::Error> {
+        Ok(self.local.has_session(id).await?)
+    }
+
+    async fn close_session(&self, id: &SessionId) -> Result<(), Self::Error> {
+        if let Some(log) = self.logs.lock().unwrap().remove(id) {
+            log.close();
+        }
+        if let Some(cluster) = &self.cluster {
+            cluster.unregister(id)?;
+        }
+        Ok(self.local.close_session(id).await?)
+    }
+
+    async fn create_stream(
+        &self,
+        id: &SessionId,
+        message: ClientJsonRpcMessage,
+    ) -> Result<impl Stream<Item = ServerSseMessage> + Send + Sync + 'static, Self::Error> {
+        let log = self.log(id)?;
+        let events = self.local.create_stream(id, message).await?;
+        Ok(Self::pump(log, events))
+    }
+
+    async fn accept_message(
+        &self,
+        id: &SessionId,
+        message: ClientJsonRpcMessage,
+    ) -> Result<(), Self::Error> {
+        Ok(self.local.accept_message(id, message).await?)
+    }
+
+    async fn create_standalone_stream(
+        &self,
+        id: &SessionId,
+    ) -> Result<impl Stream<Item = ServerSseMessage> + Send + Sync + 'static, Self::Error> {
+        let log = self.log(id)?;
+        let events = self.local.create_standalone_stream(id).await?;
+        Ok(Self::pump(log, events))
+    }
+
+    async fn resume(
+        &self,
+        id: &SessionId,
+        last_event_id: String,
+    ) -> Result<impl Stream<Item = ServerSseMessage> + Send + Sync + 'static, Self::Error> {
+        let log = self.log(id)?;
+        parse_event_id(&last_event_id)
+            .and_then(|(stream, seq)| log.subscribe(stream.parse().ok()?, Some(seq)))
+            .ok_or(HttpSessionError::EventUnavailable(last_event_id))
+    }
+}
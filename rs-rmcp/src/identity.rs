@@ -0,0 +1,28 @@
+//! 调用方身份
+
+use axum::http::request::Parts;
+use rmcp::{RoleServer, service::RequestContext};
+
+/// 调用方身份
+///
+/// 由传输层认证 (如 mTLS 客户端证书主题) 写入 HTTP 请求扩展,
+/// rmcp 会把 HTTP 请求的 [`Parts`] 透传到 MCP 请求上下文中。
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CallerIdentity(pub String);
+
+impl CallerIdentity {
+    /// 从 MCP 请求上下文中获取调用方身份
+    pub fn from_context(context: &RequestContext<RoleServer>) -> Option<&CallerIdentity> {
+        context
+            .extensions
+            .get::<Parts>()?
+            .extensions
+            .get::<CallerIdentity>()
+    }
+}
+
+impl std::fmt::Display for CallerIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
@@ -0,0 +1,236 @@
+//! 请求观测
+//!
+//! [`Instrumented`] 包装任意 MCP 服务, 在 JSON-RPC 层统一记录请求指标与会话数量,
+//! 为每个请求创建 span, 并在 [`crate::sessions`] 中登记会话。
+//!
+//! initialize 成功后在这里生成会话的 [`ClientProfile`] (含协商的协议版本), 放入后续请求与
+//! 通知的扩展, 并按画像降级响应。
+use std::{sync::Arc, time::Instant};
+
+use rmcp::{
+    ErrorData as McpError, RoleServer, Service,
+    model::{
+        ClientNotification, ClientRequest, ConstString, ErrorCode, Extensions, ServerInfo,
+        ServerResult,
+    },
+    service::{NotificationContext, RequestContext},
+    transport::streamable_http_server::SessionId,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, field::Empty};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::{
+    client_profile::ClientProfile,
+    identity::CallerIdentity,
+    metrics::{self, GaugeGuard, SessionTransport},
+    protocol,
+    sessions::SessionHandle,
+    state::AppState,
+    telemetry,
+};
+
+/// 请求被取消, 与 LSP 的 `RequestCancelled` 相同
+pub const REQUEST_CANCELLED: ErrorCode = ErrorCode(-32800);
+
+/// 带观测的 MCP 服务, 每个会话一个实例
+pub struct Instrumented<S> {
+    inner: S,
+    state: Arc<AppState>,
+    transport: SessionTransport,
+    session: SessionHandle,
+    _active: GaugeGuard,
+}
+
+impl<S> Instrumented<S> {
+    pub fn new(inner: S, state: Arc<AppState>, transport: SessionTransport) -> Self {
+        Self {
+            session: state.sessions.register(transport),
+            _active: state.metrics.session(transport),
+            inner,
+            state,
+            transport,
+        }
+    }
+
+    /// 管理接口强制关闭会话时取消, SSE 与 stdio 传输用作服务的取消令牌
+    pub fn close_token(&self) -> CancellationToken {
+        self.session.close_token()
+    }
+}
+
+impl<S: Service<RoleServer>> Service<RoleServer> for Instrumented<S> {
+    async fn handle_request(
+        &self,
+        request: ClientRequest,
+        mut context: RequestContext<RoleServer>,
+    ) -> Result<ServerResult, McpError> {
+        // 供审计等处理函数获取传输方式
+        context.extensions.insert(self.transport);
+        let method = method_name(&request);
+        let span = tracing::info_span!(
+            "mcp.request",
+            otel.name = method,
+            otel.kind = "server",
+            otel.status_code = Empty,
+            rpc.system = "jsonrpc",
+            rpc.method = method,
+            rpc.jsonrpc.request_id = %context.id,
+            rpc.jsonrpc.error_code = Empty,
+            mcp.transport = self.transport.as_str(),
+            mcp.session.id = Empty,
+            mcp.tool.name = Empty,
+        );
+        if let Some(parent) = telemetry::parent_context(&context.meta, &context.extensions) {
+            span.set_parent(parent);
+        }
+        let session_id = session_id(&context.extensions);
+        if let Some(session_id) = session_id {
+            span.record("mcp.session.id", session_id);
+        }
+        self.session.touch(
+            session_id,
+            CallerIdentity::from_context(&context).map(|id| id.0.as_str()),
+        );
+        let profile = self
+            .session
+            .profile()
+            .or_else(|| header_version(&context.extensions));
+        if let Some(profile) = &profile {
+            context.extensions.insert(profile.clone());
+        }
+        let mut subscription = None;
+        let mut log_level = None;
+        let mut initialize = None;
+        match &request {
+            ClientRequest::CallToolRequest(request) => {
+                span.record("mcp.tool.name", request.params.name.as_ref());
+            }
+            ClientRequest::InitializeRequest(request) => {
+                self.session.set_client(request.params.clone());
+                initialize = Some(request.params.clone());
+            }
+            ClientRequest::SubscribeRequest(request) => {
+                subscription = Some((true, request.params.uri.clone()));
+            }
+            ClientRequest::UnsubscribeRequest(request) => {
+                subscription = Some((false, request.params.uri.clone()));
+            }
+            ClientRequest::SetLevelRequest(request) => {
+                log_level = Some(request.params.level);
+            }
+            _ => {}
+        }
+
+        let start = Instant::now();
+        let ct = context.ct.clone();
+        let _in_flight = self.session.begin_request(&context.id, method, ct.clone());
+        // 客户端的 notifications/cancelled 或管理接口取消请求时立即返回
+        let mut result = tokio::select! {
+            result = self.inner.handle_request(request, context).instrument(span.clone()) => result,
+            _ = ct.cancelled() => Err(McpError::new(REQUEST_CANCELLED, "request cancelled", None)),
+        };
+        match (&mut result, initialize, &profile) {
+            (Ok(ServerResult::InitializeResult(result)), Some(request), _) => {
+                let version = result.protocol_version.clone();
+                let profile = ClientProfile::new(&request, version, &self.state.config.clients);
+                tracing::info!(
+                    client = %request.client_info.name,
+                    client_version = %request.client_info.version,
+                    protocol_version = %profile.negotiated.version,
+                    capabilities = ?request.capabilities,
+                    quirks = ?profile.quirks,
+                    "client initialized"
+                );
+                self.session.set_profile(profile);
+            }
+            (Ok(result), None, Some(profile)) => protocol::downgrade(result, profile),
+            _ => {}
+        }
+        if let (Ok(_), Some((subscribe, uri))) = (&result, subscription) {
+            match subscribe {
+                true => self.session.subscribe(&uri),
+                false => self.session.unsubscribe(&uri),
+            }
+        }
+        if let (Ok(_), Some(level)) = (&result, log_level) {
+            self.session.set_log_level(level);
+        }
+        self.state
+            .metrics
+            .observe_request(method, metrics::outcome(&result), start.elapsed());
+        if let Err(error) = &result {
+            span.record("rpc.jsonrpc.error_code", error.code.0);
+            span.record("otel.status_code", "ERROR");
+        }
+        result
+    }
+
+    async fn handle_notification(
+        &self,
+        notification: ClientNotification,
+        mut context: NotificationContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        self.session.touch(session_id(&context.extensions), None);
+        if let ClientNotification::InitializedNotification(_) = &notification {
+            self.session.set_peer(context.peer.clone());
+        }
+        if let Some(profile) = self.session.profile() {
+            context.extensions.insert(profile);
+        }
+        self.inner.handle_notification(notification, context).await
+    }
+
+    fn get_info(&self) -> ServerInfo {
+        self.inner.get_info()
+    }
+}
+
+/// 会话 ID: streamable HTTP 取 `Mcp-Session-Id` 头, initialize 请求取
+/// [`crate::http_sessions::HttpSessionManager`] 放入的 [`SessionId`], SSE 取 `sessionId` 查询参数
+pub fn session_id(extensions: &Extensions) -> Option<&str> {
+    if let Some(id) = extensions.get::<SessionId>() {
+        return Some(id);
+    }
+    let parts = extensions.get::<axum::http::request::Parts>()?;
+    if let Some(id) = parts.headers.get("mcp-session-id") {
+        return id.to_str().ok();
+    }
+    parts
+        .uri
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("sessionId="))
+}
+
+/// 无状态的 streamable HTTP 没有会话, 取 `MCP-Protocol-Version` 头
+fn header_version(extensions: &Extensions) -> Option<ClientProfile> {
+    let parts = extensions.get::<axum::http::request::Parts>()?;
+    let version = parts.headers.get("mcp-protocol-version")?.to_str().ok()?;
+    Some(ClientProfile::from_version(protocol::protocol_version(
+        version,
+    )))
+}
+
+fn method_of<M: ConstString>(_: &M) -> &'static str {
+    M::VALUE
+}
+
+/// JSON-RPC 方法名
+pub fn method_name(request: &ClientRequest) -> &'static str {
+    match request {
+        ClientRequest::PingRequest(r) => method_of(&r.method),
+        ClientRequest::InitializeRequest(r) => method_of(&r.method),
+        ClientRequest::CompleteRequest(r) => method_of(&r.method),
+        ClientRequest::SetLevelRequest(r) => method_of(&r.method),
+        ClientRequest::GetPromptRequest(r) => method_of(&r.method),
+        ClientRequest::ListPromptsRequest(r) => method_of(&r.method),
+        ClientRequest::ListResourcesRequest(r) => method_of(&r.method),
+        ClientRequest::ListResourceTemplatesRequest(r) => method_of(&r.method),
+        ClientRequest::ReadResourceRequest(r) => method_of(&r.method),
+        ClientRequest::SubscribeRequest(r) => method_of(&r.method),
+        ClientRequest::UnsubscribeRequest(r) => method_of(&r.method),
+        ClientRequest::CallToolRequest(r) => method_of(&r.method),
+        ClientRequest::ListToolsRequest(r) => method_of(&r.method),
+    }
+}
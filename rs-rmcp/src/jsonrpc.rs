@@ -0,0 +1,274 @@
+//! 未知方法与未知通知
+//!
+//! rmcp 0.5 把收到的消息直接解析为已知方法的枚举, 未知方法无法解析: 按行传输 (stdio、Unix 套接字)
+//! 关闭连接, streamable HTTP 与 SSE 以 HTTP 415/422 拒绝; 按行传输跳过未知通知后, 缓冲中的下一条
+//! 消息要等到新数据到达才处理。规范要求未知方法的请求返回 -32601, 未知通知直接忽略。
+//!
+//! 消息交给 rmcp 之前由 [`screen`] 按方法表筛选:
+//!
+//! - stdio 与 Unix 套接字: [`line_transport`] 按行读写, 代替 rmcp 的按行编解码
+//! - WebSocket: [`crate::websocket`] 逐帧以 [`decode`] 解析
+//! - streamable HTTP 与 SSE: [`screen_http`] 中间件对未知通知返回 202; 未知请求的错误在
+//!   streamable HTTP 上作为 JSON 响应返回, 在 SSE 上写入会话的 GET 响应流
+//!
+//! 批量请求原样交给 rmcp。
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    body::Body,
+    extract::{Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::{SinkExt, StreamExt, channel::mpsc};
+use rmcp::{
+    RoleServer,
+    model::{
+        CallToolRequestMethod, CancelledNotificationMethod, ClientJsonRpcMessage,
+        CompleteRequestMethod, ConstString, ErrorCode, GetPromptRequestMethod,
+        InitializeResultMethod, InitializedNotificationMethod, ListPromptsRequestMethod,
+        ListResourceTemplatesRequestMethod, ListResourcesRequestMethod, ListToolsRequestMethod,
+        PingRequestMethod, ProgressNotificationMethod, ReadResourceRequestMethod,
+        RootsListChangedNotificationMethod, ServerJsonRpcMessage, SetLevelRequestMethod,
+        SubscribeRequestMethod, UnsubscribeRequestMethod,
+    },
+    transport::{IntoTransport, Transport},
+};
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+use crate::{session_limits::error_body, state::AppState};
+
+/// 服务与连接之间的消息缓冲
+const CHANNEL_CAPACITY: usize = 16;
+
+/// rmcp 能解析的客户端请求
+const REQUESTS: [&str; 13] = [
+    InitializeResultMethod::VALUE,
+    PingRequestMethod::VALUE,
+    CompleteRequestMethod::VALUE,
+    SetLevelRequestMethod::VALUE,
+    GetPromptRequestMethod::VALUE,
+    ListPromptsRequestMethod::VALUE,
+    ListResourcesRequestMethod::VALUE,
+    ListResourceTemplatesRequestMethod::VALUE,
+    ReadResourceRequestMethod::VALUE,
+    SubscribeRequestMethod::VALUE,
+    UnsubscribeRequestMethod::VALUE,
+    CallToolRequestMethod::VALUE,
+    ListToolsRequestMethod::VALUE,
+];
+
+/// rmcp 能解析的客户端通知
+const NOTIFICATIONS: [&str; 4] = [
+    CancelledNotificationMethod::VALUE,
+    ProgressNotificationMethod::VALUE,
+    InitializedNotificationMethod::VALUE,
+    RootsListChangedNotificationMethod::VALUE,
+];
+
+/// 筛选结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum Screened {
+    /// 已知方法、响应或批量请求, 交给 rmcp
+    Known,
+    /// 未知方法的请求, 附需要直接回复的错误
+    UnknownRequest(Value),
+    /// 未知通知, 丢弃
+    UnknownNotification,
+}
+
+/// 按方法表筛选一条消息
+pub fn screen(message: &Value) -> Screened {
+    let Some(method) = message.get("method").and_then(Value::as_str) else {
+        return Screened::Known;
+    };
+    match message.get("id") {
+        Some(id) if !REQUESTS.contains(&method) => Screened::UnknownRequest(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {
+                "code": ErrorCode::METHOD_NOT_FOUND.0,
+                "message": format!("method not found: {method}"),
+            },
+        })),
+        None if !NOTIFICATIONS.contains(&method) => Screened::UnknownNotification,
+        _ => Screened::Known,
+    }
+}
+
+/// 解析一条按行或逐帧收到的消息
+///
+/// 无法交给 rmcp 时返回需要直接回复的错误, 未知通知为 `None`。
+pub fn decode(text: &str) -> Result<ClientJsonRpcMessage, Option<Value>> {
+    let parse_error = |e: serde_json::Error| {
+        Some(error_body(ErrorCode::PARSE_ERROR, format!("parse error: {e}")).0)
+    };
+    let message: Value = serde_json::from_str(text).map_err(parse_error)?;
+    match screen(&message) {
+        Screened::Known => serde_json::from_value(message).map_err(parse_error),
+        Screened::UnknownRequest(reply) => Err(Some(reply)),
+        Screened::UnknownNotification => Err(None),
+    }
+}
+
+/// 按行收发 JSON-RPC 的服务端传输
+///
+/// 读取端结束后继续写出服务端尚未发送的消息, 直到服务结束。
+pub fn line_transport<R, W>(
+    read: R,
+    write: W,
+) -> impl Transport<RoleServer, Error = mpsc::SendError> + 'static
+where
+    R: AsyncRead + Send + Unpin + 'static,
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    let (inbound_tx, inbound_rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let (outbound_tx, outbound_rx) = mpsc::channel(CHANNEL_CAPACITY);
+    tokio::spawn(pump_lines(read, write, inbound_tx, outbound_rx));
+    IntoTransport::<RoleServer, _, _>::into_transport((outbound_tx, inbound_rx))
+}
+
+async fn pump_lines<R, W>(
+    read: R,
+    mut write: W,
+    inbound: mpsc::Sender<ClientJsonRpcMessage>,
+    mut outbound: mpsc::Receiver<ServerJsonRpcMessage>,
+) where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = BufReader::new(read).lines();
+    let mut inbound = Some(inbound);
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line(), if inbound.is_some() => line,
+            message = outbound.next() => {
+                let Some(message) = message else {
+                    break;
+                };
+                match serde_json::to_value(&message) {
+                    Ok(message) if write_line(&mut write, &message).await.is_ok() => continue,
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::error!("failed to serialize message: {e}");
+                        continue;
+                    }
+                }
+            }
+        };
+        let line = match line {
+            Ok(Some(line)) => line,
+            Ok(None) => {
+                inbound = None;
+                continue;
+            }
+            Err(e) => {
+                tracing::debug!("failed to read line: {e}");
+                inbound = None;
+                continue;
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match decode(line) {
+            Ok(message) => {
+                let sender = inbound.as_mut().expect("reading");
+                if sender.send(message).await.is_err() {
+                    break;
+                }
+            }
+            Err(Some(reply)) => {
+                if write_line(&mut write, &reply).await.is_err() {
+                    break;
+                }
+            }
+            Err(None) => tracing::debug!("ignoring unknown notification: {line}"),
+        }
+    }
+}
+
+async fn write_line<W: AsyncWrite + Unpin>(write: &mut W, message: &Value) -> std::io::Result<()> {
+    let mut line = message.to_string().into_bytes();
+    line.push(b'\n');
+    write.write_all(&line).await?;
+    write.flush().await
+}
+
+/// streamable HTTP 与 SSE 中间件: 在 rmcp 解析之前处理未知方法与未知通知
+pub async fn screen_http(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    if req.method() != Method::POST {
+        return next.run(req).await;
+    }
+    let (parts, body) = req.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let screened = serde_json::from_slice(&bytes)
+        .map(|message| screen(&message))
+        .unwrap_or(Screened::Known);
+    let req = Request::from_parts(parts, Body::from(bytes));
+    match screened {
+        Screened::Known => next.run(req).await,
+        Screened::UnknownNotification => StatusCode::ACCEPTED.into_response(),
+        // SSE 的响应只能经 GET 响应流发送
+        Screened::UnknownRequest(reply) => match sse_session_id(&req) {
+            Some(id) if state.sessions.reply_sse(id, &reply) => {
+                StatusCode::ACCEPTED.into_response()
+            }
+            Some(_) => StatusCode::NOT_FOUND.into_response(),
+            None => Json(reply).into_response(),
+        },
+    }
+}
+
+/// SSE 的 POST 端点以 `sessionId` 查询参数标识会话
+fn sse_session_id(req: &Request) -> Option<&str> {
+    req.uri()
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("sessionId="))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_screen() {
+        let request = |method: &str| json!({ "jsonrpc": "2.0", "id": 7, "method": method });
+        let notification = |method: &str| json!({ "jsonrpc": "2.0", "method": method });
+        assert_eq!(screen(&request("tools/call")), Screened::Known);
+        assert_eq!(
+            screen(&notification("notifications/initialized")),
+            Screened::Known
+        );
+        assert_eq!(
+            screen(&json!({ "jsonrpc": "2.0", "id": 7, "result": {} })),
+            Screened::Known
+        );
+        assert_eq!(screen(&json!([request("nope")])), Screened::Known);
+        assert_eq!(
+            screen(&notification("notifications/nope")),
+            Screened::UnknownNotification
+        );
+        let Screened::UnknownRequest(reply) = screen(&request("nope/nope")) else {
+            panic!("expected an error reply");
+        };
+        assert_eq!(reply["id"], 7);
+        assert_eq!(reply["error"]["code"], -32601);
+
+        assert!(decode("{").unwrap_err().unwrap()["error"]["code"] == -32700);
+        assert!(
+            decode(&notification("notifications/nope").to_string())
+                .unwrap_err()
+                .is_none()
+        );
+        assert!(decode(&request("ping").to_string()).is_ok());
+    }
+}
@@ -0,0 +1,424 @@
+//! 日志
+//!
+//! - 级别使用 `EnvFilter` 语法, `RUST_LOG` 优先于配置
+//! - 输出格式: compact / pretty / json
+//! - 输出位置: stderr (默认, 不干扰 stdio 传输) / stdout / 按时间轮转的文件
+//! - 请求内的日志携带 `mcp.request` span 的请求 ID 与会话 ID
+//! - 按字段名脱敏, 如令牌、工具参数
+use std::{
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    EnvFilter, Layer, fmt::MakeWriter, fmt::format::FmtSpan, layer::SubscriberExt,
+    util::SubscriberInitExt,
+};
+
+use crate::{error::Error, telemetry};
+
+/// 脱敏后的取值
+const REDACTED: &str = "[REDACTED]";
+/// 终端输出时字段名与分隔符之间的颜色控制序列
+const ANSI: &str = r"(?:\x1b\[[0-9;]*m)*";
+
+/// 日志格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Compact,
+    Pretty,
+    Json,
+}
+
+/// 日志输出位置
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutput {
+    #[default]
+    Stderr,
+    Stdout,
+    File,
+}
+
+/// 日志文件轮转周期
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+/// 日志配置, 对应配置文件的 `[log]` 表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// `EnvFilter` 指令, 如 `info` 或 `info,rmcp=warn`, 设置 `RUST_LOG` 时被覆盖
+    pub level: String,
+    pub format: LogFormat,
+    pub output: LogOutput,
+    pub file: LogFileConfig,
+    /// 需要脱敏的字段名, 不区分大小写, 字段名包含其中任一项即脱敏
+    pub redact: Vec<String>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "debug".to_string(),
+            format: LogFormat::default(),
+            output: LogOutput::default(),
+            file: LogFileConfig::default(),
+            redact: [
+                "authorization",
+                "cookie",
+                "token",
+                "password",
+                "secret",
+                "arguments",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+/// 日志文件配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogFileConfig {
+    pub directory: PathBuf,
+    /// 文件名前缀, 轮转时追加日期
+    pub prefix: String,
+    pub rotation: LogRotation,
+    /// 保留的文件数, 0 表示不清理
+    pub max_files: usize,
+}
+
+impl Default for LogFileConfig {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("logs"),
+            prefix: "rs-mcpr.log".to_string(),
+            rotation: LogRotation::default(),
+            max_files: 7,
+        }
+    }
+}
+
+impl LogConfig {
+    /// 日志过滤器, `RUST_LOG` 优先, 无效时回退到配置
+    pub fn filter(&self) -> EnvFilter {
+        std::env::var(EnvFilter::DEFAULT_ENV)
+            .ok()
+            .filter(|directives| !directives.is_empty())
+            .and_then(|directives| EnvFilter::try_new(directives).ok())
+            // 配置中的级别已在校验时检查
+            .unwrap_or_else(|| EnvFilter::try_new(&self.level).unwrap_or_else(|_| "debug".into()))
+    }
+}
+
+/// 初始化日志, 返回值需保持到进程退出, 释放时刷新文件缓冲
+pub fn init(
+    config: &LogConfig,
+    tracer_provider: Option<&SdkTracerProvider>,
+) -> Result<Option<WorkerGuard>, Error> {
+    let redactor = Arc::new(Redactor::new(&config.redact));
+    let (writer, guard) = match config.output {
+        LogOutput::Stderr => (BoxMakeWriter::new(io::stderr), None),
+        LogOutput::Stdout => (BoxMakeWriter::new(io::stdout), None),
+        LogOutput::File => {
+            let mut builder = RollingFileAppender::builder()
+                .rotation(config.file.rotation.into())
+                .filename_prefix(&config.file.prefix);
+            if config.file.max_files > 0 {
+                builder = builder.max_log_files(config.file.max_files);
+            }
+            // 清理旧文件前目录需已存在
+            std::fs::create_dir_all(&config.file.directory)?;
+            let appender = builder.build(&config.file.directory)?;
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (BoxMakeWriter::new(writer), Some(guard))
+        }
+    };
+    let ansi = config.format != LogFormat::Json
+        && match config.output {
+            LogOutput::Stderr => io::stderr().is_terminal(),
+            LogOutput::Stdout => io::stdout().is_terminal(),
+            LogOutput::File => false,
+        };
+    let writer = RedactingMakeWriter {
+        inner: writer,
+        redactor,
+    };
+
+    let fmt_layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi)
+        .with_file(true)
+        .with_line_number(true)
+        .with_target(false)
+        .log_internal_errors(true)
+        .with_span_events(FmtSpan::CLOSE);
+    let fmt_layer = match config.format {
+        LogFormat::Compact => fmt_layer.compact().boxed(),
+        LogFormat::Pretty => fmt_layer.pretty().boxed(),
+        LogFormat::Json => fmt_layer
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    };
+
+    tracing_subscriber::registry()
+        .with(config.filter())
+        .with(fmt_layer)
+        .with(tracer_provider.map(telemetry::layer))
+        .init();
+    Ok(guard)
+}
+
+type BoxMakeWriter = tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+/// 写入前对整行日志脱敏
+struct RedactingMakeWriter {
+    inner: BoxMakeWriter,
+    redactor: Arc<Redactor>,
+}
+
+impl<'a> MakeWriter<'a> for RedactingMakeWriter {
+    type Writer = RedactingWriter<Box<dyn Write + 'a>>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+            redactor: self.redactor.clone(),
+        }
+    }
+}
+
+/// fmt 层每条日志只调用一次 `write`, 因此可以按整行处理
+struct RedactingWriter<W> {
+    inner: W,
+    redactor: Arc<Redactor>,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match std::str::from_utf8(buf) {
+            Ok(line) => self
+                .inner
+                .write_all(self.redactor.redact(line).as_bytes())?,
+            Err(_) => self.inner.write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 按字段名脱敏
+///
+/// 同时识别 `key=value`、JSON `"key":value` 以及 `Debug` 输出的 `key: value`,
+/// 取值可以是带引号的字符串或 `{...}` / `[...]` / `Some(...)` 等嵌套结构。
+#[derive(Debug)]
+pub struct Redactor {
+    /// 匹配字段名及其后的分隔符, 取值从匹配结束处开始
+    key: Option<Regex>,
+}
+
+impl Redactor {
+    pub fn new(fields: &[String]) -> Self {
+        let alternatives: Vec<String> = fields
+            .iter()
+            .filter(|f| !f.is_empty())
+            .map(|f| regex::escape(f))
+            .collect();
+        if alternatives.is_empty() {
+            return Self { key: None };
+        }
+        let pattern = format!(
+            r#"(?i)[\w.-]*(?:{})[\w.-]*{ANSI}(?:\\*")?{ANSI}\s*[:=]{ANSI}\s*"#,
+            alternatives.join("|")
+        );
+        Self {
+            key: Some(Regex::new(&pattern).expect("escaped field names form a valid regex")),
+        }
+    }
+
+    pub fn redact(&self, line: &str) -> String {
+        let Some(key) = &self.key else {
+            return line.to_string();
+        };
+        let mut output = String::with_capacity(line.len());
+        let mut rest = 0;
+        let mut search = 0;
+        while let Some(m) = key.find_at(line, search) {
+            let end = value_end(line, m.end());
+            if end == m.end() {
+                search = m.end();
+                continue;
+            }
+            output.push_str(&line[rest..m.end()]);
+            output.push_str(&redacted_value(&line[m.end()..end]));
+            rest = end;
+            search = end;
+        }
+        output.push_str(&line[rest..]);
+        output
+    }
+}
+
+/// 保留取值的引号风格
+fn redacted_value(value: &str) -> String {
+    match escaped_quote(value) {
+        Some(quote) => format!("{quote}{REDACTED}{quote}"),
+        None => REDACTED.to_string(),
+    }
+}
+
+/// 开头的引号及其转义前缀, 如 `"`、`\"`、`\\\"` (多层转义)
+fn escaped_quote(value: &str) -> Option<&str> {
+    let backslashes = value.bytes().take_while(|&b| b == b'\\').count();
+    (value.as_bytes().get(backslashes) == Some(&b'"')).then(|| &value[..=backslashes])
+}
+
+/// 从 `start` 开始扫描一个取值, 返回结束位置
+fn value_end(line: &str, start: usize) -> usize {
+    let bytes = line.as_bytes();
+    let mut i = start;
+    // 嵌在字符串中的字符串, 如 JSON 日志中的 `\"..\"`, 以相同的转义引号结束
+    if let Some(quote) = escaped_quote(&line[i..])
+        && quote.len() > 1
+    {
+        return line[i + quote.len()..]
+            .find(quote)
+            .map_or(line.len(), |n| i + quote.len() + n + quote.len());
+    }
+    if bytes.get(i) == Some(&b'"') {
+        i += 1;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'"' => return i + 1,
+                _ => i += 1,
+            }
+        }
+        return line.len();
+    }
+
+    // 嵌套结构按括号配对, 如 `Some({"a": Number(1)})`
+    // JSON 格式的日志中内层引号均转义为 `\"`, 未转义的 `"` 表示外层字符串结束
+    let json = line.trim_start().starts_with('{');
+    let mut depth = 0usize;
+    let mut in_string = false;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => {
+                if json && bytes.get(i + 1) == Some(&b'"') {
+                    in_string = !in_string;
+                }
+                i += 2;
+                continue;
+            }
+            b'"' if json => return i,
+            b'"' => in_string = !in_string,
+            _ if in_string => {}
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' if depth == 0 => return i,
+            b')' | b']' | b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            b',' | b' ' | b'\n' if depth == 0 => return i,
+            _ => {}
+        }
+        i += 1;
+    }
+    line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor() -> Redactor {
+        Redactor::new(&LogConfig::default().redact)
+    }
+
+    #[test]
+    fn test_redact_text() {
+        let r = redactor();
+        assert_eq!(
+            r.redact("initialize caller=None authorization=\"Bearer abc\" user=bob"),
+            "initialize caller=None authorization=\"[REDACTED]\" user=bob"
+        );
+        assert_eq!(
+            r.redact(r#"headers={"authorization": "Bearer abc", "host": "localhost"}"#),
+            r#"headers={"authorization": "[REDACTED]", "host": "localhost"}"#
+        );
+        assert_eq!(
+            r.redact(r#"CallToolRequestParam { name: "sum", arguments: Some({"a": Number(1), "b": Object {"c": String("})")}}) }"#),
+            r#"CallToolRequestParam { name: "sum", arguments: [REDACTED] }"#
+        );
+        assert_eq!(
+            r.redact("access_token=abc123 next"),
+            "access_token=[REDACTED] next"
+        );
+        assert_eq!(Redactor::new(&[]).redact("token=abc"), "token=abc");
+        assert_eq!(
+            r.redact("\x1b[3mtoken\x1b[0m\x1b[2m=\x1b[0mabc done"),
+            "\x1b[3mtoken\x1b[0m\x1b[2m=\x1b[0m[REDACTED] done"
+        );
+    }
+
+    #[test]
+    fn test_redact_json() {
+        let r = redactor();
+        let line = r#"{"level":"INFO","fields":{"message":"call","api_token":"abc","request":"Request { arguments: Some({\"a\": Number(1)}) }"},"span":{"mcp.session.id":"s1"}}"#;
+        let redacted = r.redact(line);
+        let value: serde_json::Value = serde_json::from_str(&redacted).unwrap();
+        assert_eq!(value["fields"]["api_token"], REDACTED);
+        assert_eq!(
+            value["fields"]["request"],
+            "Request { arguments: [REDACTED] }"
+        );
+        assert_eq!(value["span"]["mcp.session.id"], "s1");
+
+        // 工具结果中多层转义的 JSON
+        let line =
+            r#"{"fields":{"result":"Text { text: \"{\\\"token\\\":\\\"abc\\\",\\\"x\\\":1}\" }"}}"#;
+        let value: serde_json::Value = serde_json::from_str(&r.redact(line)).unwrap();
+        assert_eq!(
+            value["fields"]["result"],
+            r#"Text { text: "{\"token\":\"[REDACTED]\",\"x\":1}" }"#
+        );
+    }
+}
@@ -1,119 +1,475 @@
-use clap::{Parser, ValueEnum};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+use clap::{Parser, Subcommand};
 use log::{error, info};
 use rmcp::{
     ServiceExt,
     transport::{
         sse_server::{SseServer, SseServerConfig},
         stdio,
-        streamable_http_server::{StreamableHttpService, session::local::LocalSessionManager},
+        streamable_http_server::{StreamableHttpServerConfig, StreamableHttpService},
     },
 };
-use tracing_subscriber::fmt::format::FmtSpan;
+use serde_json::json;
 
+mod admin;
+use admin::{AdminClient, SessionsCommand};
+mod audit;
+use audit::AuditQuery;
+mod bridge;
+use bridge::BridgeCommand;
+mod client_profile;
+mod cluster;
+mod config;
+use config::{Config, Transport};
+mod conformance;
+use conformance::ConformanceArgs;
+mod cors;
+use cors::{OriginConfig, OriginPolicy};
 mod error;
 mod extract;
+mod gateway;
+mod health;
+mod http_sessions;
+mod identity;
+mod instrument;
+mod jsonrpc;
+mod logging;
+use logging::{LogFormat, LogOutput};
+mod metrics;
+use metrics::SessionTransport;
+mod protocol;
+mod ratelimit;
+use ratelimit::RateLimitConfig;
+mod recording;
+mod registry;
+mod reload;
+mod resumable;
+mod session_limits;
+mod sessions;
+mod state;
+use state::AppState;
+mod telemetry;
+use telemetry::TraceExporter;
+#[cfg(test)]
+mod testing;
+mod tls;
+use tls::{TlsListener, TlsPeer};
+mod unix;
+use unix::UnixProtocol;
+mod websocket;
 
 mod calculator;
-use calculator::Calculator;
-
-#[derive(Debug, Clone, ValueEnum)]
-enum Transport {
-    Stdio,
-    Http,
-}
+mod client;
+mod repl;
+use client::ClientArgs;
+mod replay;
+use replay::ReplayArgs;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Transport type to use (stdio or http)
-    #[arg(short, long, value_enum, default_value = "http")]
-    transport: Transport,
+    /// Config file (TOML or YAML), defaults to rs-mcpr.toml / rs-mcpr.yaml in
+    /// the current directory, ~/.config/rs-mcpr/ or /etc/rs-mcpr/
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Transport type to use (stdio, http, websocket or unix) [default: http]
+    #[arg(short, long, value_enum)]
+    transport: Option<Transport>,
+
+    /// Server address for HTTP transport (format: host:port) [default: 127.0.0.1:8000]
+    #[arg(short, long)]
+    address: Option<String>,
+
+    /// Log filter directives, e.g. `info` or `info,rmcp=warn`; RUST_LOG takes
+    /// precedence [default: debug]
+    #[arg(long)]
+    log_level: Option<String>,
+
+    /// Log format (compact, pretty or json) [default: compact]
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
+
+    /// Log output (stderr, stdout or file) [default: stderr]
+    #[arg(long, value_enum)]
+    log_output: Option<LogOutput>,
+
+    /// PEM certificate chain, enables HTTPS for HTTP transport
+    #[arg(long)]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for the certificate
+    #[arg(long)]
+    tls_key: Option<PathBuf>,
+
+    /// PEM CA bundle used to verify client certificates (mTLS)
+    #[arg(long)]
+    tls_client_ca: Option<PathBuf>,
+
+    /// Separate admin listener (host:port) serving /metrics instead of the main server
+    #[arg(long)]
+    admin_address: Option<String>,
+
+    /// Trace exporter (none, otlp or file) [default: none]
+    #[arg(long, value_enum)]
+    trace_exporter: Option<TraceExporter>,
+
+    /// OTLP/HTTP traces endpoint [default: http://localhost:4318/v1/traces]
+    #[arg(long)]
+    otlp_endpoint: Option<String>,
+
+    /// JSON Lines file written by the file trace exporter [default: traces.jsonl]
+    #[arg(long)]
+    trace_file: Option<PathBuf>,
+
+    /// Audit log file, enables auditing of tool calls, resource reads and
+    /// prompt renders [default: audit.jsonl, disabled]
+    #[arg(long)]
+    audit_file: Option<PathBuf>,
+
+    /// Recording file, enables recording of every JSON-RPC message
+    /// [default: recording.jsonl, disabled]
+    #[arg(long)]
+    record_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    rate_limit: RateLimitConfig,
+
+    #[command(flatten)]
+    origin: OriginConfig,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generate a development CA, server and client certificates
+    GenCert {
+        /// Output directory
+        #[arg(short, long, default_value = "certs")]
+        out_dir: PathBuf,
+
+        /// Subject alternative names of the server certificate
+        #[arg(long = "san", default_values = ["localhost", "127.0.0.1"])]
+        sans: Vec<String>,
+
+        /// Common name of the client certificate
+        #[arg(long, default_value = "dev-client")]
+        client_cn: String,
+    },
+    /// Configuration tools
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Administrative tools
+    Admin {
+        #[command(subcommand)]
+        command: AdminCommand,
+    },
+    /// Connect to an MCP server over stdio, SSE or streamable HTTP
+    Client(ClientArgs),
+    /// Bridge between the stdio and HTTP/SSE transports
+    Bridge {
+        #[command(subcommand)]
+        command: BridgeCommand,
+    },
+    /// Replay a recorded session against the server and diff the responses
+    Replay(ReplayArgs),
+    /// Check an MCP server against the protocol specification
+    Conformance(ConformanceArgs),
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Validate and print the effective merged configuration
+    Check,
+}
+
+#[derive(Subcommand, Debug)]
+enum AdminCommand {
+    /// Query the audit log, oldest first
+    Audit {
+        #[command(flatten)]
+        query: AuditQuery,
+
+        /// Print records as JSON Lines
+        #[arg(long)]
+        json: bool,
+    },
+    /// Inspect and manage sessions through the admin API (requires admin.token)
+    Sessions {
+        /// Admin API base URL [default: http://<admin.address>, or
+        /// http(s)://<address> depending on tls]
+        #[arg(long)]
+        url: Option<String>,
+
+        #[command(subcommand)]
+        command: SessionsCommand,
+    },
+}
 
-    /// Server address for HTTP transport (format: host:port)
-    #[arg(short, long, default_value = "127.0.0.1:8000")]
-    address: String,
+impl Args {
+    /// 命令行显式指定的配置项, 覆盖配置文件与环境变量
+    fn overrides(&self) -> serde_json::Value {
+        let mut value = json!({
+            "rate_limit": self.rate_limit,
+            "origin": self.origin,
+        });
+        if let Some(transport) = self.transport {
+            value["transport"] = json!(transport);
+        }
+        if let Some(address) = &self.address {
+            value["address"] = json!(address);
+        }
+        if let Some(address) = &self.admin_address {
+            value["admin"]["address"] = json!(address);
+        }
+        if let Some(exporter) = self.trace_exporter {
+            value["telemetry"]["exporter"] = json!(exporter);
+        }
+        if let Some(endpoint) = &self.otlp_endpoint {
+            value["telemetry"]["otlp_endpoint"] = json!(endpoint);
+        }
+        if let Some(file) = &self.trace_file {
+            value["telemetry"]["file"] = json!(file);
+        }
+        if let Some(file) = &self.audit_file {
+            value["audit"]["enabled"] = json!(true);
+            value["audit"]["path"] = json!(file);
+        }
+        if let Some(file) = &self.record_file {
+            value["record"]["enabled"] = json!(true);
+            value["record"]["path"] = json!(file);
+        }
+        if let Some(level) = &self.log_level {
+            value["log"]["level"] = json!(level);
+        }
+        if let Some(format) = self.log_format {
+            value["log"]["format"] = json!(format);
+        }
+        if let Some(output) = self.log_output {
+            value["log"]["output"] = json!(output);
+        }
+        for (key, path) in [
+            ("cert", &self.tls_cert),
+            ("key", &self.tls_key),
+            ("client_ca", &self.tls_client_ca),
+        ] {
+            if let Some(path) = path {
+                value["tls"][key] = json!(path);
+            }
+        }
+        value
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    init_log();
-
     // Parse command line arguments using clap
     let args = Args::parse();
+
+    if let Some(Command::GenCert {
+        out_dir,
+        sans,
+        client_cn,
+    }) = &args.command
+    {
+        tls::generate_dev_certificates(out_dir, sans.clone(), client_cn)?;
+        println!("development certificates written to {}", out_dir.display());
+        return Ok(());
+    }
+
+    if let Some(Command::Config {
+        command: ConfigCommand::Check,
+    }) = &args.command
+    {
+        return config_check(&args);
+    }
+
+    if let Some(Command::Admin {
+        command: AdminCommand::Audit { query, json },
+    }) = &args.command
+    {
+        return audit_query(&args, query, *json);
+    }
+
+    if let Some(Command::Admin {
+        command: AdminCommand::Sessions { url, command },
+    }) = &args.command
+    {
+        let (config, _) = Config::load(args.config.as_deref(), args.overrides())?;
+        AdminClient::new(&config, url.as_deref())?
+            .run(command)
+            .await?;
+        return Ok(());
+    }
+
+    if let Some(Command::Client(client_args)) = &args.command {
+        let (config, config_path) = Config::load(args.config.as_deref(), args.overrides())?;
+        let _log_guard = logging::init(&config.log, None)?;
+        client::run(&config, config_path.as_deref(), client_args).await?;
+        return Ok(());
+    }
+
+    if let Some(Command::Bridge { command }) = &args.command {
+        let (config, _) = Config::load(args.config.as_deref(), args.overrides())?;
+        let _log_guard = logging::init(&config.log, None)?;
+        bridge::run(&config, command).await?;
+        return Ok(());
+    }
+
+    if let Some(Command::Conformance(conformance_args)) = &args.command {
+        let (config, config_path) = Config::load(args.config.as_deref(), args.overrides())?;
+        let _log_guard = logging::init(&config.log, None)?;
+        conformance::run(&config, config_path.as_deref(), conformance_args).await?;
+        return Ok(());
+    }
+
+    if let Some(Command::Replay(replay_args)) = &args.command {
+        let (config, _) = Config::load(args.config.as_deref(), args.overrides())?;
+        let _log_guard = logging::init(&config.log, None)?;
+        replay::run(config, replay_args).await?;
+        return Ok(());
+    }
+
+    let (config, config_path) = Config::load(args.config.as_deref(), args.overrides())?;
+    let tracer_provider = telemetry::init(&config.telemetry)?;
+    let _log_guard = logging::init(&config.log, tracer_provider.as_ref())?;
     info!(
-        "args transport: {:?}, address: {}",
-        args.transport, args.address
+        "config file: {:?}, transport: {:?}, address: {}",
+        config_path, config.transport, config.address
     );
 
-    match args.transport {
-        Transport::Stdio => {
-            stdio_server().await?;
-            return Ok(());
+    let state = Arc::new(AppState::new(config)?);
+    reload::spawn(state.clone(), config_path, args.overrides());
+    gateway::spawn(&state);
+    if let Some(address) = state.config.admin.address.clone() {
+        tokio::spawn(start_admin_server(state.clone(), address));
+    }
+    let result = match state.config.transport {
+        Transport::Stdio => stdio_server(state).await,
+        Transport::Http | Transport::Websocket => start_http_server(state).await,
+        Transport::Unix => match state.config.unix.protocol {
+            UnixProtocol::Http => start_http_server(state).await,
+            UnixProtocol::Jsonrpc => start_unix_jsonrpc_server(state).await,
+        },
+    };
+
+    // 刷新未导出的 span
+    if let Some(provider) = tracer_provider
+        && let Err(e) = provider.shutdown()
+    {
+        error!("failed to shut down tracer provider: {}", e);
+    }
+    result
+}
+
+/// Validates and prints the effective configuration
+fn config_check(args: &Args) -> anyhow::Result<()> {
+    match Config::load(args.config.as_deref(), args.overrides()) {
+        Ok((config, config_path)) => {
+            match &config_path {
+                Some(path) => println!("# config file: {}", path.display()),
+                None => println!("# config file: none"),
+            }
+            print!("{}", config.to_toml()?);
+            Ok(())
         }
-        Transport::Http => {
-            start_http_server(&args.address).await?;
+        Err(e) => {
+            eprintln!("invalid configuration: {e}");
+            std::process::exit(1);
         }
     }
-
-    Ok(())
 }
 
-/// Initializes a logger.
-fn init_log() {
-    tracing_subscriber::fmt()
-        .compact()
-        .with_ansi(true)
-        .with_max_level(tracing::Level::DEBUG)
-        .with_level(true)
-        .with_file(true)
-        .with_line_number(true)
-        .with_target(false)
-        .log_internal_errors(true)
-        .with_span_events(FmtSpan::CLOSE)
-        .init();
+/// Prints audit records matching the query
+fn audit_query(args: &Args, query: &AuditQuery, json: bool) -> anyhow::Result<()> {
+    let (config, _) = Config::load(args.config.as_deref(), args.overrides())?;
+    for record in audit::query(&config.audit, query)? {
+        if json {
+            println!("{}", serde_json::to_string(&record)?);
+            continue;
+        }
+        println!(
+            "{} {} {} {} session={} identity={} transport={} latency={:.1}ms{}",
+            record
+                .timestamp
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            record.method,
+            record.target,
+            record.outcome,
+            record.session.as_deref().unwrap_or("-"),
+            record.identity.as_deref().unwrap_or("-"),
+            record.transport.as_deref().unwrap_or("-"),
+            record.latency_ms,
+            record
+                .error_code
+                .map(|code| format!(" error_code={code}"))
+                .unwrap_or_default(),
+        );
+    }
+    Ok(())
 }
 
 /// Starts TCP server to communicate with standard input/output
-async fn stdio_server() -> anyhow::Result<()> {
+async fn stdio_server(state: Arc<AppState>) -> anyhow::Result<()> {
     // Create an instance of our Calculator router
-    let service = Calculator::new().serve(stdio()).await.inspect_err(|e| {
-        tracing::error!("stdio serving error: {:?}", e);
-    })?;
+    let service = calculator::session_service(state.clone(), SessionTransport::Stdio);
+    let ct = service.close_token();
+    let (stdin, stdout) = stdio();
+    let transport = state.recorder.record(
+        SessionTransport::Stdio,
+        jsonrpc::line_transport(stdin, stdout),
+    );
+    let service = service
+        .serve_with_ct(transport, ct)
+        .await
+        .inspect_err(|e| {
+            tracing::error!("stdio serving error: {:?}", e);
+        })?;
 
     service.waiting().await?;
 
     Ok(())
 }
 
-/// Starts SSE and HTTP servers
-async fn start_http_server(address: &str) -> anyhow::Result<()> {
-    let http_service = StreamableHttpService::new(
-        || Ok(Calculator::new()),
-        LocalSessionManager::default().into(),
-        Default::default(),
-    );
+/// Starts the HTTP server with the streamable HTTP and SSE transports, or the
+/// WebSocket transport
+async fn start_http_server(state: Arc<AppState>) -> anyhow::Result<()> {
+    let config = &state.config;
+    let address = config.address.as_str();
 
-    let sse_config = SseServerConfig {
-        bind: address.parse()?,
-        sse_path: "/sse".to_string(),
-        post_path: "/message".to_string(),
-        ct: tokio_util::sync::CancellationToken::new(),
-        sse_keep_alive: None,
+    let mut app = match config.transport {
+        Transport::Websocket => websocket::router(state.clone()),
+        _ => http_transport_router(state.clone())?,
     };
-
-    // Create SSE server
-    let (sse_server, sse_router) = SseServer::new(sse_config);
-    // Start SSE server with Calculator service
-    sse_server.with_service(Calculator::new);
-    // // Register token validation middleware for SSE
-    // let sse_cancel_token = sse_server.config.ct.clone();
-    // // Handle Ctrl+C
-    // let sse_cancel_token2 = sse_server.config.ct.clone();
-
-    // Create HTTP router with request logging middleware
-    let app = axum::Router::new()
-        .nest_service("/mcp", http_service)
-        .merge(sse_router)
+    session_limits::spawn_sweeper(state.clone());
+    if config.admin.address.is_none() {
+        app = app.merge(admin_router(state.clone()));
+    }
+    let app = app
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            metrics::track_sse_streams,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            ratelimit::http_rate_limit,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            cluster::authenticate,
+        ))
+        .layer(axum::middleware::from_fn(tls::propagate_identity))
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::new(OriginPolicy::new(&config.origin, address)),
+            cors::validate_origin,
+        ))
+        // 健康检查不经过上面的中间件
+        .merge(health::router(state.clone()))
         .with_state(());
 
     // SSE Handle signals for graceful shutdown
@@ -128,19 +484,215 @@ async fn start_http_server(address: &str) -> anyhow::Result<()> {
     // });
 
     // Start HTTP server
-    info!("MCP Server started on {}", address);
-    let listener = tokio::net::TcpListener::bind(address).await?;
-    let server = axum::serve(listener, app).with_graceful_shutdown(async move {
-        // sse_cancel_token2.cancelled().await;
-        tokio::signal::ctrl_c().await.unwrap();
-        info!("Server is shutting down");
-    });
+    let result = match config.tls.clone() {
+        #[cfg(unix)]
+        _ if config.transport == Transport::Unix => {
+            let (listener, _socket) = unix::bind(&config.unix)?;
+            info!("MCP Server started on {}", config.unix.path.display());
+            state.health.set_listening();
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown_signal(state.clone()))
+                .await
+        }
+        Some(tls) => {
+            info!("MCP Server started on https://{}", address);
+            let listener = TlsListener::bind(address, tls).await?;
+            state.health.set_listening();
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<TlsPeer>(),
+            )
+            .with_graceful_shutdown(shutdown_signal(state.clone()))
+            .await
+        }
+        None => {
+            info!("MCP Server started on {}", address);
+            let listener = tokio::net::TcpListener::bind(address).await?;
+            state.health.set_listening();
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown_signal(state.clone()))
+            .await
+        }
+    };
 
     // start HTTP and SSE servers
-    if let Err(e) = server.await {
+    if let Err(e) = result {
         error!("Server error: {}", e);
     }
 
     info!("Server has been shut down");
     Ok(())
 }
+
+/// Starts newline-delimited JSON-RPC on a Unix domain socket
+#[cfg(unix)]
+async fn start_unix_jsonrpc_server(state: Arc<AppState>) -> anyhow::Result<()> {
+    let (listener, _socket) = unix::bind(&state.config.unix)?;
+    info!("MCP Server started on {}", state.config.unix.path.display());
+    state.health.set_listening();
+    unix::serve_jsonrpc(state.clone(), listener, shutdown_signal(state.clone())).await;
+    info!("Server has been shut down");
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn start_unix_jsonrpc_server(_state: Arc<AppState>) -> anyhow::Result<()> {
+    anyhow::bail!("the unix transport is only supported on Unix platforms")
+}
+
+/// Streamable HTTP and SSE routes
+fn http_transport_router(state: Arc<AppState>) -> anyhow::Result<axum::Router> {
+    let config = &state.config;
+    let address = config.address.as_str();
+
+    let http_state = state.clone();
+    let http_service = StreamableHttpService::new(
+        move || {
+            Ok(calculator::session_service(
+                http_state.clone(),
+                SessionTransport::StreamableHttp,
+            ))
+        },
+        state.http_sessions.clone(),
+        StreamableHttpServerConfig {
+            sse_keep_alive: config.http.sse_keep_alive(),
+            stateful_mode: config.http.stateful_mode,
+        },
+    );
+
+    let sse_config = SseServerConfig {
+        bind: address.parse()?,
+        sse_path: config.http.sse_path.clone(),
+        post_path: config.http.post_path.clone(),
+        ct: tokio_util::sync::CancellationToken::new(),
+        sse_keep_alive: config.http.sse_keep_alive(),
+    };
+
+    // Create SSE server
+    let (mut sse_server, sse_router) = SseServer::new(sse_config);
+    // Start SSE server with Calculator service, each session can be closed by the admin API
+    let sse_state = state.clone();
+    tokio::spawn(async move {
+        while let Some(transport) = sse_server.next_transport().await {
+            let service = calculator::session_service(sse_state.clone(), SessionTransport::Sse);
+            let ct = service.close_token();
+            let transport = sse_state.recorder.record(SessionTransport::Sse, transport);
+            tokio::spawn(async move {
+                match service.serve_with_ct(transport, ct).await {
+                    Ok(server) => {
+                        let _ = server.waiting().await;
+                    }
+                    Err(e) => error!("sse serving error: {:?}", e),
+                }
+            });
+        }
+    });
+    // // Register token validation middleware for SSE
+    // let sse_cancel_token = sse_server.config.ct.clone();
+    // // Handle Ctrl+C
+    // let sse_cancel_token2 = sse_server.config.ct.clone();
+
+    // Create HTTP router with request logging middleware
+    let sse_router = sse_router
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            jsonrpc::screen_http,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            resumable::resumable_sse_stream,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            sessions::closable_sse_stream,
+        ));
+    let mcp_router = axum::Router::new()
+        .nest_service(&config.http.mcp_path, http_service)
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            jsonrpc::screen_http,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            session_limits::enforce,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            cluster::forward,
+        ));
+    Ok(mcp_router.merge(sse_router))
+}
+
+/// /metrics and the session admin API, each only when enabled
+fn admin_router(state: Arc<AppState>) -> axum::Router {
+    let mut app = axum::Router::new();
+    if state.config.metrics.enabled {
+        app = app.route(
+            &state.config.metrics.path,
+            axum::routing::get(metrics::metrics_handler),
+        );
+    }
+    let mut app = app.with_state(state.clone());
+    if let Some(token) = &state.config.admin.token {
+        app = app.merge(admin::router(state.clone(), token));
+    }
+    app
+}
+
+/// Starts the admin server on a separate address
+async fn start_admin_server(state: Arc<AppState>, address: String) {
+    let app = admin_router(state.clone());
+
+    let result = match tokio::net::TcpListener::bind(&address).await {
+        Ok(listener) => {
+            info!("Admin server started on {}", address);
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown_signal(state.clone()))
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        error!("Admin server error: {}", e);
+    }
+}
+
+/// Waits for Ctrl+C or SIGTERM, then reports not ready for the drain period
+/// before the listener stops accepting connections
+async fn shutdown_signal(state: Arc<AppState>) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    state.health.begin_shutdown();
+    let drain = state.config.http.shutdown_drain_secs;
+    if drain > 0 {
+        info!("Server is draining for {}s", drain);
+        tokio::time::sleep(std::time::Duration::from_secs(drain)).await;
+    }
+    state.http_sessions.deregister();
+    info!("Server is shutting down");
+}
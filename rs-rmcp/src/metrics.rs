@@ -0,0 +1,296 @@
+//! Prometheus 指标
+//!
+//! 标签取值均有上限: 工具名、提示词名只使用已注册的名称, 资源读取使用模板或静态 URI,
+//! 其它取值统一记为 `unknown`。
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{StatusCode, header::CONTENT_TYPE},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use rmcp::ErrorData as McpError;
+use serde::{Deserialize, Serialize};
+
+use crate::state::AppState;
+
+/// 超出取值范围的标签
+pub const UNKNOWN: &str = "unknown";
+
+/// 指标配置, 对应配置文件的 `[metrics]` 表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    /// 指标路径
+    pub path: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: "/metrics".to_string(),
+        }
+    }
+}
+
+/// 会话使用的传输方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionTransport {
+    Stdio,
+    Sse,
+    StreamableHttp,
+    Websocket,
+    Unix,
+}
+
+impl SessionTransport {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionTransport::Stdio => "stdio",
+            SessionTransport::Sse => "sse",
+            SessionTransport::StreamableHttp => "streamable_http",
+            SessionTransport::Websocket => "websocket",
+            SessionTransport::Unix => "unix",
+        }
+    }
+}
+
+/// 服务指标
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    tool_calls: IntCounterVec,
+    tool_calls_in_flight: IntGaugeVec,
+    resource_reads: IntCounterVec,
+    prompt_renders: IntCounterVec,
+    active_sessions: IntGaugeVec,
+    sse_streams: IntGauge,
+    rate_limited: IntCounterVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            counter
+        };
+        let gauge = |name: &str, help: &str, labels: &[&str]| {
+            let gauge = IntGaugeVec::new(Opts::new(name, help), labels).unwrap();
+            registry.register(Box::new(gauge.clone())).unwrap();
+            gauge
+        };
+
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "mcp_request_duration_seconds",
+                "JSON-RPC request latency in seconds",
+            ),
+            &["method"],
+        )
+        .unwrap();
+        registry
+            .register(Box::new(request_duration.clone()))
+            .unwrap();
+        let sse_streams = IntGauge::new("mcp_sse_streams", "Open SSE streams").unwrap();
+        registry.register(Box::new(sse_streams.clone())).unwrap();
+
+        Self {
+            requests: counter(
+                "mcp_requests_total",
+                "JSON-RPC requests by method and outcome",
+                &["method", "outcome"],
+            ),
+            request_duration,
+            tool_calls: counter(
+                "mcp_tool_calls_total",
+                "Tool calls by tool name and outcome",
+                &["tool", "outcome"],
+            ),
+            tool_calls_in_flight: gauge(
+                "mcp_tool_calls_in_flight",
+                "Tool calls currently executing",
+                &["tool"],
+            ),
+            resource_reads: counter(
+                "mcp_resource_reads_total",
+                "resources/read requests by template and outcome",
+                &["template", "outcome"],
+            ),
+            prompt_renders: counter(
+                "mcp_prompt_renders_total",
+                "prompts/get requests by prompt name and outcome",
+                &["prompt", "outcome"],
+            ),
+            active_sessions: gauge(
+                "mcp_active_sessions",
+                "Active MCP sessions by transport",
+                &["transport"],
+            ),
+            sse_streams,
+            rate_limited: counter(
+                "mcp_rate_limited_total",
+                "Requests rejected by rate limits by scope",
+                &["scope"],
+            ),
+            registry,
+        }
+    }
+
+    pub fn observe_request(&self, method: &str, outcome: &str, elapsed: Duration) {
+        self.requests.with_label_values(&[method, outcome]).inc();
+        self.request_duration
+            .with_label_values(&[method])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_tool_call(&self, tool: &str, outcome: &str) {
+        self.tool_calls.with_label_values(&[tool, outcome]).inc();
+    }
+
+    /// 记录执行中的工具调用, 返回值释放时计数减一
+    pub fn tool_call_in_flight(&self, tool: &str) -> GaugeGuard {
+        GaugeGuard::new(self.tool_calls_in_flight.with_label_values(&[tool]))
+    }
+
+    pub fn observe_resource_read(&self, template: &str, outcome: &str) {
+        self.resource_reads
+            .with_label_values(&[template, outcome])
+            .inc();
+    }
+
+    pub fn observe_prompt_render(&self, prompt: &str, outcome: &str) {
+        self.prompt_renders
+            .with_label_values(&[prompt, outcome])
+            .inc();
+    }
+
+    /// 记录活跃会话, 返回值释放时计数减一
+    pub fn session(&self, transport: SessionTransport) -> GaugeGuard {
+        GaugeGuard::new(
+            self.active_sessions
+                .with_label_values(&[transport.as_str()]),
+        )
+    }
+
+    /// 当前活跃会话数
+    pub fn active_sessions(&self, transport: SessionTransport) -> i64 {
+        self.active_sessions
+            .with_label_values(&[transport.as_str()])
+            .get()
+    }
+
+    pub fn observe_rate_limited(&self, scope: &str) {
+        self.rate_limited.with_label_values(&[scope]).inc();
+    }
+
+    /// 以 Prometheus 文本格式输出
+    pub fn encode(&self) -> Result<String, prometheus::Error> {
+        TextEncoder::new().encode_to_string(&self.registry.gather())
+    }
+}
+
+/// 计数守卫, 创建时加一, 释放时减一
+#[derive(Debug)]
+pub struct GaugeGuard(IntGauge);
+
+impl GaugeGuard {
+    fn new(gauge: IntGauge) -> Self {
+        gauge.inc();
+        Self(gauge)
+    }
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// 请求结果标签
+pub fn outcome<T>(result: &Result<T, McpError>) -> &'static str {
+    match result {
+        Ok(_) => "ok",
+        Err(_) => "error",
+    }
+}
+
+/// `GET /metrics`
+pub async fn metrics_handler(State(state): State<Arc<AppState>>) -> Response {
+    match state.metrics.encode() {
+        Ok(body) => ([(CONTENT_TYPE, TextEncoder::new().format_type())], body).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// 统计 SSE 流数量, 响应体释放时视为流关闭
+pub async fn track_sse_streams(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(req).await;
+    let is_sse = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if !is_sse {
+        return response;
+    }
+
+    let guard = GaugeGuard::new(state.metrics.sse_streams.clone());
+    let (parts, body) = response.into_parts();
+    let stream = body.into_data_stream().inspect(move |_| {
+        let _ = &guard;
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let metrics = Metrics::new();
+        metrics.observe_request("tools/call", "ok", Duration::from_millis(5));
+        metrics.observe_tool_call("sum", "ok");
+        metrics.observe_resource_read("test://dynamic/resource/{id}", "ok");
+        let session = metrics.session(SessionTransport::Sse);
+        {
+            let _in_flight = metrics.tool_call_in_flight("sum");
+            let text = metrics.encode().unwrap();
+            assert!(text.contains(r#"mcp_tool_calls_in_flight{tool="sum"} 1"#));
+        }
+
+        let text = metrics.encode().unwrap();
+        assert!(text.contains(r#"mcp_requests_total{method="tools/call",outcome="ok"} 1"#));
+        assert!(text.contains(r#"mcp_tool_calls_total{outcome="ok",tool="sum"} 1"#));
+        assert!(text.contains(r#"template="test://dynamic/resource/{id}""#));
+        assert!(text.contains(r#"mcp_active_sessions{transport="sse"} 1"#));
+        assert!(text.contains(r#"mcp_tool_calls_in_flight{tool="sum"} 0"#));
+
+        drop(session);
+        let text = metrics.encode().unwrap();
+        assert!(text.contains(r#"mcp_active_sessions{transport="sse"} 0"#));
+    }
+}
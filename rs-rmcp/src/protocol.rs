@@ -0,0 +1,255 @@
+//! 协议版本协商
+//!
+//! 服务同时支持多个 MCP 规范版本 (`[protocol] versions`)。initialize 时 [`negotiate`]
+//! 按客户端请求的版本选择: 支持则原样返回; 比支持的最新版本更新则返回最新版本;
+//! 更旧或格式错误的版本以 -32602 拒绝, `data` 中列出支持的版本。
+//!
+//! 协商结果与客户端能力是会话的 [`ClientProfile`] 的一部分; 响应返回前由 [`downgrade`]
+//! 移除旧版本客户端不认识的字段。
+use rmcp::{
+    ErrorData as McpError,
+    model::{ClientCapabilities, Content, ProtocolVersion, ServerResult},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::client_profile::ClientProfile;
+
+/// 已知的规范版本, 从旧到新
+pub const KNOWN_VERSIONS: [&str; 3] = ["2024-11-05", "2025-03-26", "2025-06-18"];
+
+/// 协议配置, 对应配置文件的 `[protocol]` 表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProtocolConfig {
+    /// 支持的协议版本
+    pub versions: Vec<String>,
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        Self {
+            versions: KNOWN_VERSIONS.map(String::from).to_vec(),
+        }
+    }
+}
+
+impl ProtocolConfig {
+    /// 校验支持的版本, 返回出错原因
+    pub fn check(&self) -> Result<(), String> {
+        if self.versions.is_empty() {
+            return Err("must not be empty".to_string());
+        }
+        match self
+            .versions
+            .iter()
+            .find(|version| !KNOWN_VERSIONS.contains(&version.as_str()))
+        {
+            Some(version) => Err(format!(
+                "unknown version `{version}`, expected one of {KNOWN_VERSIONS:?}"
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn latest(&self) -> &str {
+        self.versions.iter().max().map_or(KNOWN_VERSIONS[0], |v| v)
+    }
+}
+
+/// 随规范版本引入、响应中需要按版本降级的特性
+///
+/// 2025-06-18 的 elicitation 与 `resource_link` 内容不在其中: rmcp 0.5 既不能发起
+/// `elicitation/create`, 也不能表示 `resource_link`, 服务端不会向任何版本的客户端发送它们。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// 工具的 `annotations`
+    ToolAnnotations,
+    /// 工具的 `outputSchema` 与结果中的 `structuredContent`
+    StructuredOutput,
+}
+
+impl Feature {
+    /// 引入该特性的规范版本
+    pub fn since(self) -> &'static str {
+        match self {
+            Feature::ToolAnnotations => "2025-03-26",
+            Feature::StructuredOutput => "2025-06-18",
+        }
+    }
+}
+
+/// 会话协商的协议版本与客户端能力
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Negotiated {
+    pub version: ProtocolVersion,
+    pub capabilities: ClientCapabilities,
+}
+
+impl Negotiated {
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.version.to_string().as_str() >= feature.since()
+    }
+}
+
+/// `YYYY-MM-DD` 格式的版本
+fn is_date(version: &str) -> bool {
+    let bytes = version.as_bytes();
+    bytes.len() == 10
+        && bytes.iter().enumerate().all(|(i, b)| match i {
+            4 | 7 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+/// 构造任意版本, rmcp 只为部分版本提供常量
+pub fn protocol_version(version: &str) -> ProtocolVersion {
+    serde_json::from_value(Value::String(version.to_string())).expect("string version")
+}
+
+/// 为客户端请求的版本选择响应的版本
+pub fn negotiate(
+    config: &ProtocolConfig,
+    requested: &ProtocolVersion,
+) -> Result<ProtocolVersion, McpError> {
+    let requested = requested.to_string();
+    let latest = config.latest();
+    if config.versions.contains(&requested) {
+        return Ok(protocol_version(&requested));
+    }
+    if is_date(&requested) && requested.as_str() > latest {
+        return Ok(protocol_version(latest));
+    }
+    Err(McpError::invalid_params(
+        "Unsupported protocol version",
+        Some(json!({ "supported": config.versions, "requested": requested })),
+    ))
+}
+
+/// 移除客户端不支持的字段
+pub fn downgrade(result: &mut ServerResult, profile: &ClientProfile) {
+    let annotations = profile.supports(Feature::ToolAnnotations);
+    let structured = profile.supports(Feature::StructuredOutput);
+    match result {
+        ServerResult::ListToolsResult(list) => {
+            for tool in &mut list.tools {
+                if !annotations {
+                    tool.annotations = None;
+                }
+                if !structured {
+                    tool.output_schema = None;
+                }
+            }
+        }
+        // 旧版本只认识 content, 结构化结果转为文本
+        ServerResult::CallToolResult(call) if !structured => {
+            if let Some(value) = call.structured_content.take()
+                && call.content.as_ref().is_none_or(Vec::is_empty)
+            {
+                call.content = Some(vec![Content::text(value.to_string())]);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rmcp::model::{CallToolResult, ListToolsResult, Tool, ToolAnnotations};
+
+    use super::*;
+    use crate::{
+        config::Config,
+        state::AppState,
+        testing::{Fixture, Harness},
+    };
+
+    #[test]
+    fn test_negotiate() {
+        let config = ProtocolConfig::default();
+        for version in KNOWN_VERSIONS {
+            let negotiated = negotiate(&config, &protocol_version(version)).unwrap();
+            assert_eq!(negotiated.to_string(), version);
+        }
+        let newer = negotiate(&config, &protocol_version("2099-01-01")).unwrap();
+        assert_eq!(newer.to_string(), "2025-06-18");
+
+        for version in ["2024-01-01", "1.0.0", ""] {
+            let error = negotiate(&config, &protocol_version(version)).unwrap_err();
+            assert_eq!(error.code.0, -32602);
+            assert_eq!(error.data.unwrap()["requested"], version);
+        }
+
+        let config = ProtocolConfig {
+            versions: vec!["2025-03-26".to_string()],
+        };
+        assert!(negotiate(&config, &ProtocolVersion::V_2024_11_05).is_err());
+        let newer = negotiate(&config, &protocol_version("2025-06-18")).unwrap();
+        assert_eq!(newer, ProtocolVersion::V_2025_03_26);
+
+        assert!(ProtocolConfig { versions: vec![] }.check().is_err());
+        let unknown = ProtocolConfig {
+            versions: vec!["2025-01-01".to_string()],
+        };
+        assert!(unknown.check().is_err());
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let negotiated = |version| ClientProfile::from_version(protocol_version(version));
+        let mut tool = Tool::new("t", "", serde_json::Map::new());
+        tool.annotations = Some(ToolAnnotations::new());
+        tool.output_schema = Some(Default::default());
+        let tools = ServerResult::ListToolsResult(ListToolsResult::with_all_items(vec![tool]));
+
+        let mut result = tools.clone();
+        downgrade(&mut result, &negotiated("2024-11-05"));
+        let ServerResult::ListToolsResult(list) = &result else {
+            unreachable!()
+        };
+        assert!(list.tools[0].annotations.is_none() && list.tools[0].output_schema.is_none());
+
+        let mut result = tools.clone();
+        downgrade(&mut result, &negotiated("2025-03-26"));
+        let ServerResult::ListToolsResult(list) = &result else {
+            unreachable!()
+        };
+        assert!(list.tools[0].annotations.is_some() && list.tools[0].output_schema.is_none());
+
+        let mut result = tools.clone();
+        downgrade(&mut result, &negotiated("2025-06-18"));
+        assert_eq!(json!(result), json!(tools));
+
+        let structured =
+            ServerResult::CallToolResult(CallToolResult::structured(json!({ "a": 1 })));
+        let mut result = structured.clone();
+        downgrade(&mut result, &negotiated("2025-03-26"));
+        let ServerResult::CallToolResult(call) = &result else {
+            unreachable!()
+        };
+        assert!(call.structured_content.is_none());
+        assert_eq!(call.content.as_ref().unwrap().len(), 1);
+
+        let mut result = structured.clone();
+        downgrade(&mut result, &negotiated("2025-06-18"));
+        assert_eq!(json!(result), json!(structured));
+    }
+
+    #[tokio::test]
+    async fn test_session_negotiated() {
+        let state = Arc::new(AppState::new(Config::default()).unwrap());
+        let harness = Harness::calculator(Fixture::Stdio, state.clone()).await;
+        assert_eq!(
+            harness.server_info().protocol_version,
+            ProtocolVersion::V_2025_03_26
+        );
+        let sessions = state.sessions.list();
+        let negotiated = sessions[0].profile().unwrap().negotiated;
+        assert_eq!(negotiated.version, ProtocolVersion::V_2025_03_26);
+        assert!(negotiated.supports(Feature::ToolAnnotations));
+        assert!(!negotiated.supports(Feature::StructuredOutput));
+        harness.close().await;
+    }
+}
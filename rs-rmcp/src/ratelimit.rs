@@ -0,0 +1,571 @@
+//! 限流与并发配额
+//!
+//! - 令牌桶限流: 按会话、调用方身份、工具
+//! - 每个工具的最大并发调用数
+//! - 传输层按调用方限流, 超限返回 HTTP 429
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderValue, StatusCode, header::RETRY_AFTER},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rmcp::{ErrorData as McpError, model::ErrorCode};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::json;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{identity::CallerIdentity, state::AppState, tls::TlsPeer};
+
+/// 超出限流时的 JSON-RPC 错误码 (实现自定义的服务端错误区间)
+pub const RATE_LIMITED: ErrorCode = ErrorCode(-32029);
+
+/// 限流桶数量超过该值时清理已回满的桶
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+/// 速率, 格式为 `<次数>/<s|m|h>`, 如 `20/s`, 突发容量等于次数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (count, unit) = s
+            .split_once('/')
+            .ok_or_else(|| format!("invalid rate `{s}`, expected <count>/<s|m|h>"))?;
+        let burst: u32 = count
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid rate count `{count}`"))?;
+        if burst == 0 {
+            return Err("rate count must be greater than 0".to_string());
+        }
+        let period = match unit.trim() {
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return Err(format!("invalid rate unit `{unit}`, expected s, m or h")),
+        };
+        Ok(Self {
+            burst,
+            per_second: burst as f64 / period,
+        })
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let period = (self.burst as f64 / self.per_second).round();
+        let unit = if period >= 3600.0 {
+            "h"
+        } else if period >= 60.0 {
+            "m"
+        } else {
+            "s"
+        };
+        write!(f, "{}/{unit}", self.burst)
+    }
+}
+
+impl Serialize for Rate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// 配置文件中单工具限流以表的形式书写: `tool_rates = { sum = "5/s" }`
+mod tool_rates_map {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Rate;
+
+    pub fn serialize<S: Serializer>(
+        rates: &[(String, Rate)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(rates.iter().map(|(tool, rate)| (tool, rate)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(String, Rate)>, D::Error> {
+        Ok(BTreeMap::<String, Rate>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+/// 解析 `<tool>=<rate>` 形式的单工具限流
+fn parse_tool_rate(s: &str) -> Result<(String, Rate), String> {
+    let (tool, rate) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid tool rate `{s}`, expected <tool>=<rate>"))?;
+    Ok((tool.to_string(), rate.parse()?))
+}
+
+/// 令牌桶
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: Rate,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: Rate) -> Self {
+        Self {
+            rate,
+            tokens: rate.burst as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate.per_second).min(self.rate.burst as f64);
+        self.updated = now;
+    }
+
+    /// 尝试消耗一个令牌, 失败时返回需要等待的时间
+    pub fn try_acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64(
+            (1.0 - self.tokens) / self.rate.per_second,
+        ))
+    }
+
+    /// 退还 [`TokenBucket::try_acquire`] 消耗的令牌
+    fn refund(&mut self) {
+        self.tokens = (self.tokens + 1.0).min(self.rate.burst as f64);
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.rate.burst as f64
+    }
+}
+
+/// 限流配置, 同时作为命令行参数与配置文件的 `[rate_limit]` 表
+#[derive(Debug, Clone, Default, clap::Args, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Token bucket rate per session for tool calls, e.g. 20/s
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_rate: Option<Rate>,
+
+    /// Token bucket rate per caller identity for tool calls
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity_rate: Option<Rate>,
+
+    /// Default token bucket rate per tool, shared by all sessions
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_rate: Option<Rate>,
+
+    /// Rate for a single tool, overrides --tool-rate, e.g. sum=5/s
+    #[arg(long = "tool-rate-override", value_parser = parse_tool_rate)]
+    #[serde(with = "tool_rates_map", skip_serializing_if = "Vec::is_empty")]
+    pub tool_rates: Vec<(String, Rate)>,
+
+    /// Maximum concurrent calls per tool
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_calls: Option<usize>,
+
+    /// HTTP request rate per caller (identity or client IP), answered with 429
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_rate: Option<Rate>,
+}
+
+impl RateLimitConfig {
+    fn tool_rate(&self, tool: &str) -> Option<Rate> {
+        self.tool_rates
+            .iter()
+            .find(|(name, _)| name == tool)
+            .map(|(_, rate)| *rate)
+            .or(self.tool_rate)
+    }
+}
+
+/// 超限信息
+#[derive(Debug)]
+pub struct Exceeded {
+    pub scope: &'static str,
+    pub key: String,
+    pub retry_after: Option<Duration>,
+}
+
+impl Exceeded {
+    fn retry_after_secs(&self) -> Option<u64> {
+        self.retry_after
+            .map(|d| d.as_secs() + u64::from(d.subsec_nanos() > 0))
+    }
+}
+
+impl From<Exceeded> for McpError {
+    fn from(e: Exceeded) -> Self {
+        McpError::new(
+            RATE_LIMITED,
+            format!("rate limit exceeded: {}", e.scope),
+            Some(json!({
+                "scope": e.scope,
+                "key": e.key,
+                "retryAfterMs": e.retry_after.map(|d| d.as_millis() as u64),
+            })),
+        )
+    }
+}
+
+impl IntoResponse for Exceeded {
+    fn into_response(self) -> Response {
+        let retry_after = self.retry_after_secs().unwrap_or(1);
+        let mut response = (
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("rate limit exceeded: {}", self.scope),
+        )
+            .into_response();
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        response
+    }
+}
+
+/// 按键分组的令牌桶
+#[derive(Debug, Default)]
+struct Buckets(Mutex<HashMap<String, TokenBucket>>);
+
+impl Buckets {
+    fn try_acquire(&self, key: &str, rate: Rate) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.0.lock().unwrap();
+        if buckets.len() > MAX_IDLE_BUCKETS {
+            buckets.retain(|_, bucket| !bucket.is_full(now));
+        }
+        buckets
+            .entry(key.to_string())
+            .or_insert_with(|| TokenBucket::new(rate))
+            .try_acquire(now)
+    }
+
+    fn refund(&self, key: &str) {
+        if let Some(bucket) = self.0.lock().unwrap().get_mut(key) {
+            bucket.refund();
+        }
+    }
+}
+
+/// 一次工具调用已消耗的令牌, 之后的检查拒绝调用时退还
+#[derive(Default)]
+struct Taken<'a> {
+    session: Option<&'a Mutex<TokenBucket>>,
+    identity: Option<&'a str>,
+    tool: Option<&'a str>,
+}
+
+/// 会话级配额, 每个会话一份
+#[derive(Debug, Default)]
+pub struct SessionQuota(Option<Mutex<TokenBucket>>);
+
+/// 限流器, 在所有会话间共享
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    identities: Buckets,
+    tools: Buckets,
+    http: Buckets,
+    concurrency: Mutex<HashMap<String, Arc<Semaphore>>>,
+    rejected: AtomicU64,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// 为新会话创建配额
+    pub fn session_quota(&self) -> SessionQuota {
+        SessionQuota(
+            self.config
+                .session_rate
+                .map(|rate| Mutex::new(TokenBucket::new(rate))),
+        )
+    }
+
+    fn reject(&self, exceeded: Exceeded) -> Exceeded {
+        let rejected = self.rejected.fetch_add(1, Ordering::Relaxed) + 1;
+        tracing::warn!(
+            scope = exceeded.scope,
+            key = %exceeded.key,
+            rejected,
+            "rate limit exceeded"
+        );
+        exceeded
+    }
+
+    /// 检查工具调用配额, 成功时返回并发许可, 许可在调用结束后释放
+    ///
+    /// 任一检查拒绝调用时退还已消耗的令牌, 被拒绝的调用不占用配额。
+    pub fn check_tool_call(
+        &self,
+        session: &SessionQuota,
+        session_id: &str,
+        identity: Option<&CallerIdentity>,
+        tool: &str,
+    ) -> Result<Option<OwnedSemaphorePermit>, Exceeded> {
+        let mut taken = Taken::default();
+        let result = self
+            .take_tokens(session, session_id, identity, tool, &mut taken)
+            .and_then(|()| self.concurrency_permit(tool));
+        if result.is_err() {
+            if let Some(bucket) = taken.session {
+                bucket.lock().unwrap().refund();
+            }
+            if let Some(identity) = taken.identity {
+                self.identities.refund(identity);
+            }
+            if let Some(tool) = taken.tool {
+                self.tools.refund(tool);
+            }
+        }
+        result
+    }
+
+    /// 依次消耗会话、调用方身份与工具的令牌, 记录到 `taken`
+    fn take_tokens<'a>(
+        &self,
+        session: &'a SessionQuota,
+        session_id: &str,
+        identity: Option<&'a CallerIdentity>,
+        tool: &'a str,
+        taken: &mut Taken<'a>,
+    ) -> Result<(), Exceeded> {
+        let exceeded = |scope, key: &str, retry_after| {
+            self.reject(Exceeded {
+                scope,
+                key: key.to_string(),
+                retry_after: Some(retry_after),
+            })
+        };
+
+        if let Some(bucket) = &session.0 {
+            bucket
+                .lock()
+                .unwrap()
+                .try_acquire(Instant::now())
+                .map_err(|d| exceeded("session", session_id, d))?;
+            taken.session = Some(bucket);
+        }
+        if let (Some(rate), Some(identity)) = (self.config.identity_rate, identity) {
+            self.identities
+                .try_acquire(&identity.0, rate)
+                .map_err(|d| exceeded("identity", &identity.0, d))?;
+            taken.identity = Some(&identity.0);
+        }
+        if let Some(rate) = self.config.tool_rate(tool) {
+            self.tools
+                .try_acquire(tool, rate)
+                .map_err(|d| exceeded("tool", tool, d))?;
+            taken.tool = Some(tool);
+        }
+        Ok(())
+    }
+
+    fn concurrency_permit(&self, tool: &str) -> Result<Option<OwnedSemaphorePermit>, Exceeded> {
+        let Some(max) = self.config.max_concurrent_calls else {
+            return Ok(None);
+        };
+        let semaphore = {
+            let mut concurrency = self.concurrency.lock().unwrap();
+            // 没有调用在执行的信号量只被这里持有, 移除后与新建的等价
+            concurrency.retain(|_, semaphore| Arc::strong_count(semaphore) > 1);
+            concurrency
+                .entry(tool.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(max)))
+                .clone()
+        };
+        semaphore.try_acquire_owned().map(Some).map_err(|_| {
+            self.reject(Exceeded {
+                scope: "concurrency",
+                key: tool.to_string(),
+                retry_after: None,
+            })
+        })
+    }
+
+    /// 检查 HTTP 请求配额
+    pub fn check_http(&self, key: &str) -> Result<(), Exceeded> {
+        let Some(rate) = self.config.http_rate else {
+            return Ok(());
+        };
+        self.http.try_acquire(key, rate).map_err(|d| {
+            self.reject(Exceeded {
+                scope: "http",
+                key: key.to_string(),
+                retry_after: Some(d),
+            })
+        })
+    }
+}
+
+/// HTTP 限流中间件, 以调用方身份或客户端 IP 作为限流键
+pub async fn http_rate_limit(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let extensions = req.extensions();
+    let key = if let Some(identity) = extensions.get::<CallerIdentity>() {
+        identity.0.clone()
+    } else if let Some(ConnectInfo(peer)) = extensions.get::<ConnectInfo<TlsPeer>>() {
+        peer.remote_addr.ip().to_string()
+    } else if let Some(ConnectInfo(addr)) = extensions.get::<ConnectInfo<std::net::SocketAddr>>() {
+        addr.ip().to_string()
+    } else {
+        "unknown".to_string()
+    };
+
+    if let Err(exceeded) = state.limiter.check_http(&key) {
+        state.metrics.observe_rate_limited(exceeded.scope);
+        return exceeded.into_response();
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        let rate: Rate = "120/m".parse().unwrap();
+        assert_eq!(rate.burst, 120);
+        assert_eq!(rate.per_second, 2.0);
+
+        assert_eq!(rate.to_string(), "120/m");
+
+        assert!("0/s".parse::<Rate>().is_err());
+        assert!("10/d".parse::<Rate>().is_err());
+        assert!("10".parse::<Rate>().is_err());
+        assert_eq!(
+            parse_tool_rate("sum=5/s").unwrap(),
+            ("sum".to_string(), "5/s".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket::new("2/s".parse().unwrap());
+        let now = bucket.updated;
+
+        assert!(bucket.try_acquire(now).is_ok());
+        assert!(bucket.try_acquire(now).is_ok());
+        let retry_after = bucket.try_acquire(now).unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(500));
+
+        assert!(bucket.try_acquire(now + retry_after).is_ok());
+    }
+
+    #[test]
+    fn test_tool_call_limits() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            tool_rate: Some("100/s".parse().unwrap()),
+            tool_rates: vec![("sum".to_string(), "1/m".parse().unwrap())],
+            max_concurrent_calls: Some(1),
+            ..Default::default()
+        });
+        let session = limiter.session_quota();
+
+        let permit = limiter
+            .check_tool_call(&session, "s1", None, "echo")
+            .unwrap();
+        let err = limiter
+            .check_tool_call(&session, "s1", None, "echo")
+            .unwrap_err();
+        assert_eq!(err.scope, "concurrency");
+        drop(permit);
+        assert!(
+            limiter
+                .check_tool_call(&session, "s1", None, "echo")
+                .is_ok()
+        );
+
+        assert!(limiter.check_tool_call(&session, "s1", None, "sum").is_ok());
+        let err = limiter
+            .check_tool_call(&session, "s1", None, "sum")
+            .unwrap_err();
+        assert_eq!(err.scope, "tool");
+        assert!(err.retry_after.unwrap() > Duration::from_secs(59));
+        // 没有执行中调用的信号量被清理
+        assert_eq!(limiter.concurrency.lock().unwrap().len(), 1);
+
+        let limiter = RateLimiter::new(RateLimitConfig {
+            session_rate: Some("1/m".parse().unwrap()),
+            ..Default::default()
+        });
+        let session = limiter.session_quota();
+        assert!(limiter.check_tool_call(&session, "s1", None, "sum").is_ok());
+        let err = limiter
+            .check_tool_call(&session, "s1", None, "sum")
+            .unwrap_err();
+        assert_eq!((err.scope, err.key.as_str()), ("session", "s1"));
+
+        // 工具配额拒绝的调用不消耗会话配额
+        let limiter = RateLimiter::new(RateLimitConfig {
+            session_rate: Some("2/m".parse().unwrap()),
+            tool_rates: vec![("sum".to_string(), "1/m".parse().unwrap())],
+            ..Default::default()
+        });
+        let session = limiter.session_quota();
+        assert!(limiter.check_tool_call(&session, "s1", None, "sum").is_ok());
+        for _ in 0..3 {
+            let err = limiter
+                .check_tool_call(&session, "s1", None, "sum")
+                .unwrap_err();
+            assert_eq!(err.scope, "tool");
+        }
+        assert!(
+            limiter
+                .check_tool_call(&session, "s1", None, "echo")
+                .is_ok()
+        );
+        let err = limiter
+            .check_tool_call(&session, "s1", None, "echo")
+            .unwrap_err();
+        assert_eq!(err.scope, "session");
+    }
+}
@@ -0,0 +1,235 @@
+//! 消息录制
+//!
+//! 启用 `[record]` 后, 每个会话在传输层收发的 JSON-RPC 消息逐条追加到 JSON Lines 文件,
+//! 记录时间、会话、传输方式、方向以及距会话开始的毫秒数。录制文件可由
+//! `rs-mcpr replay` 回放, 见 [`crate::replay`]。
+//!
+//! 无状态模式的 streamable HTTP 请求不经过会话管理器, 不会被录制。
+use std::{
+    fs::File,
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use chrono::{DateTime, Utc};
+use rmcp::{
+    RoleServer,
+    transport::{IntoTransport, Transport, common::server_side_http::session_id},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{audit, error::Error, metrics::SessionTransport};
+
+/// 录制配置, 对应配置文件的 `[record]` 表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordConfig {
+    pub enabled: bool,
+    /// 录制文件路径
+    pub path: PathBuf,
+}
+
+impl Default for RecordConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: PathBuf::from("recording.jsonl"),
+        }
+    }
+}
+
+/// 消息方向, 以服务端为准
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// 客户端发往服务端
+    In,
+    /// 服务端发往客户端
+    Out,
+}
+
+/// 一条录制记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    pub timestamp: DateTime<Utc>,
+    pub session: String,
+    pub transport: String,
+    pub direction: Direction,
+    /// 距会话开始的毫秒数
+    pub elapsed_ms: f64,
+    pub message: Value,
+}
+
+/// 录制文件, 所有会话共用; 未启用时包装的传输只转发消息
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    file: Option<Arc<Mutex<File>>>,
+}
+
+impl Recorder {
+    pub fn new(config: &RecordConfig) -> Result<Self, Error> {
+        let file = match config.enabled {
+            true => Some(Arc::new(Mutex::new(audit::open_append(&config.path)?))),
+            false => None,
+        };
+        Ok(Self { file })
+    }
+
+    /// 包装会话的传输, `session` 为录制中的会话标识
+    pub fn wrap<T: Transport<RoleServer>>(
+        &self,
+        transport: SessionTransport,
+        session: &str,
+        inner: T,
+    ) -> Recorded<T> {
+        Recorded {
+            inner,
+            session: self.file.clone().map(|file| {
+                Arc::new(RecordingSession {
+                    file,
+                    session: session.to_string(),
+                    transport,
+                    start: Instant::now(),
+                })
+            }),
+        }
+    }
+
+    /// 包装没有会话 ID 的传输, 生成一个随机 ID
+    pub fn record<E, A>(
+        &self,
+        transport: SessionTransport,
+        inner: impl IntoTransport<RoleServer, E, A>,
+    ) -> Recorded<impl Transport<RoleServer, Error = E> + 'static>
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        self.wrap(transport, &session_id(), inner.into_transport())
+    }
+}
+
+#[derive(Debug)]
+struct RecordingSession {
+    file: Arc<Mutex<File>>,
+    session: String,
+    transport: SessionTransport,
+    start: Instant,
+}
+
+impl RecordingSession {
+    fn write(&self, direction: Direction, message: &impl Serialize) {
+        let record = serde_json::to_value(message).map(|message| RecordedMessage {
+            timestamp: Utc::now(),
+            session: self.session.clone(),
+            transport: self.transport.as_str().to_string(),
+            direction,
+            elapsed_ms: self.start.elapsed().as_micros() as f64 / 1000.0,
+            message,
+        });
+        let result = record
+            .and_then(|record| serde_json::to_vec(&record))
+            .map_err(std::io::Error::from)
+            .and_then(|mut line| {
+                line.push(b'\n');
+                self.file.lock().unwrap().write_all(&line)
+            });
+        if let Err(e) = result {
+            tracing::error!(session = %self.session, "failed to record message: {e}");
+        }
+    }
+}
+
+/// 录制收发消息的传输
+pub struct Recorded<T> {
+    inner: T,
+    session: Option<Arc<RecordingSession>>,
+}
+
+impl<T: Transport<RoleServer>> Transport<RoleServer> for Recorded<T> {
+    type Error = T::Error;
+
+    fn send(
+        &mut self,
+        item: rmcp::service::TxJsonRpcMessage<RoleServer>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        if let Some(session) = &self.session {
+            session.write(Direction::Out, &item);
+        }
+        self.inner.send(item)
+    }
+
+    async fn receive(&mut self) -> Option<rmcp::service::RxJsonRpcMessage<RoleServer>> {
+        let item = self.inner.receive().await;
+        if let (Some(item), Some(session)) = (&item, &self.session) {
+            session.write(Direction::In, item);
+        }
+        item
+    }
+
+    fn close(&mut self) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.inner.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rmcp::{ServiceExt, model::CallToolRequestParam, object};
+
+    use super::*;
+    use crate::{calculator, config::Config, replay, state::AppState};
+
+    #[tokio::test]
+    async fn test_record_session() {
+        let dir = std::env::temp_dir().join(format!("rs-mcpr-record-{}", std::process::id()));
+        let path = dir.join("recording.jsonl");
+        let recorder = Recorder::new(&RecordConfig {
+            enabled: true,
+            path: path.clone(),
+        })
+        .unwrap();
+
+        let state = Arc::new(AppState::new(Config::default()).unwrap());
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        let server = calculator::session_service(state, SessionTransport::Stdio);
+        let transport = recorder.record(SessionTransport::Stdio, tokio::io::split(server_io));
+        tokio::spawn(async move {
+            let server = server.serve(transport).await.unwrap();
+            let _ = server.waiting().await;
+        });
+        let client = ().serve(tokio::io::split(client_io)).await.unwrap();
+        client
+            .call_tool(CallToolRequestParam {
+                name: "sum".into(),
+                arguments: Some(object!({ "a": 1, "b": 2 })),
+            })
+            .await
+            .unwrap();
+        client.cancel().await.unwrap();
+
+        let records = replay::load(&path).unwrap();
+        let directions: Vec<_> = records.iter().map(|r| r.direction).collect();
+        // initialize, initialized 通知, tools/call
+        assert_eq!(
+            directions,
+            [
+                Direction::In,
+                Direction::Out,
+                Direction::In,
+                Direction::In,
+                Direction::Out
+            ]
+        );
+        assert!(records.iter().all(|r| r.session == records[0].session));
+        assert!(records.iter().all(|r| r.transport == "stdio"));
+        assert_eq!(records[3].message["method"], "tools/call");
+        assert_eq!(records[4].message["id"], records[3].message["id"]);
+        assert!(records[4].elapsed_ms >= records[0].elapsed_ms);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+//! 工具 / 资源 / 提示词注册表
+//!
+//! 注册表由配置生成, 配置热加载时整体替换。每个请求开始时取一份快照,
+//! 执行中的请求始终使用旧快照完成。
+use std::{collections::BTreeMap, path::PathBuf};
+
+use rmcp::model::{
+    AnnotateAble, Prompt, PromptArgument, RawResource, RawResourceTemplate, Resource,
+    ResourceTemplate,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// 注册表配置, 对应配置文件的 `[registry]` 表
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegistryConfig {
+    /// 启用的工具, 为空表示全部启用
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<String>,
+    /// 禁用的资源 URI 或资源模板
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub disabled_resources: Vec<String>,
+    /// 禁用的提示词
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub disabled_prompts: Vec<String>,
+    /// 额外暴露的文件资源
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileResource>,
+}
+
+/// 文件资源
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileResource {
+    pub uri: String,
+    pub path: PathBuf,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+/// 注册表快照
+#[derive(Debug, Clone, PartialEq)]
+pub struct Registry {
+    /// 启用的工具, `None` 表示全部启用
+    pub tools: Option<Vec<String>>,
+    pub resources: Vec<Resource>,
+    pub resource_templates: Vec<ResourceTemplate>,
+    pub prompts: Vec<Prompt>,
+    /// 禁用的提示词, 同样作用于下游提示词
+    pub disabled_prompts: Vec<String>,
+    /// 文件资源: URI -> 路径
+    pub files: BTreeMap<String, PathBuf>,
+    /// `docs://readme` 资源对应的文件
+    pub readme: PathBuf,
+}
+
+/// 两份注册表之间发生变化的列表
+#[derive(Debug, Default, PartialEq)]
+pub struct RegistryChanges {
+    pub tools: bool,
+    pub resources: bool,
+    pub prompts: bool,
+}
+
+impl Registry {
+    pub fn new(config: &Config) -> Self {
+        let registry = &config.registry;
+        let resource_enabled = |uri: &str| !registry.disabled_resources.iter().any(|d| d == uri);
+
+        let mut resources: Vec<RawResource> = builtin_resources()
+            .into_iter()
+            .filter(|r| resource_enabled(&r.uri))
+            .collect();
+        resources.extend(registry.files.iter().map(|file| RawResource {
+            uri: file.uri.clone(),
+            name: file.name.clone(),
+            description: file.description.clone(),
+            mime_type: file.mime_type.clone(),
+            size: None,
+        }));
+
+        Self {
+            tools: (!registry.tools.is_empty()).then(|| registry.tools.clone()),
+            resources: resources.into_iter().map(|r| r.no_annotation()).collect(),
+            resource_templates: builtin_resource_templates()
+                .into_iter()
+                .filter(|t| resource_enabled(&t.uri_template))
+                .map(|t| t.no_annotation())
+                .collect(),
+            prompts: builtin_prompts()
+                .into_iter()
+                .filter(|p| !registry.disabled_prompts.contains(&p.name))
+                .collect(),
+            disabled_prompts: registry.disabled_prompts.clone(),
+            files: registry
+                .files
+                .iter()
+                .map(|file| (file.uri.clone(), file.path.clone()))
+                .collect(),
+            readme: config.paths.readme.clone(),
+        }
+    }
+
+    pub fn tool_enabled(&self, name: &str) -> bool {
+        self.tools
+            .as_ref()
+            .is_none_or(|tools| tools.iter().any(|t| t == name))
+    }
+
+    pub fn resource_enabled(&self, uri: &str) -> bool {
+        self.resources.iter().any(|r| r.uri == uri)
+    }
+
+    pub fn resource_template_enabled(&self, uri_template: &str) -> bool {
+        self.resource_templates
+            .iter()
+            .any(|t| t.uri_template == uri_template)
+    }
+
+    /// 提示词未被禁用, 本地与下游提示词都适用
+    pub fn prompt_enabled(&self, name: &str) -> bool {
+        !self.disabled_prompts.iter().any(|p| p == name)
+    }
+
+    /// 与旧注册表比较, 返回发生变化的列表
+    pub fn changes(&self, old: &Registry) -> RegistryChanges {
+        RegistryChanges {
+            tools: self.tools != old.tools,
+            resources: self.resources != old.resources
+                || self.resource_templates != old.resource_templates
+                || self.files != old.files,
+            prompts: self.prompts != old.prompts || self.disabled_prompts != old.disabled_prompts,
+        }
+    }
+}
+
+/// 静态资源
+fn builtin_resources() -> Vec<RawResource> {
+    vec![
+        RawResource {
+            uri: "docs://readme".to_string(),
+            name: "Project README".to_string(),
+            description: Some("The project's README file".to_string()),
+            mime_type: Some("text/markdown".to_string()),
+            size: None,
+        },
+        RawResource {
+            uri: "file:///documents/report.pdf".to_string(),
+            name: "Project README".to_string(),
+            description: Some("The project's README file".to_string()),
+            mime_type: Some("text/plain".to_string()),
+            size: None,
+        },
+    ]
+}
+
+/// 动态资源模板
+fn builtin_resource_templates() -> Vec<RawResourceTemplate> {
+    vec![
+        RawResourceTemplate {
+            uri_template: "test://dynamic/resource/{id}".to_string(),
+            name: "Dynamic Resource".to_string(),
+            description: Some("A dynamic resource template. This resource contains the URI parameter `{id}` in its name".to_string()),
+            mime_type: Some("text/plain".to_string()),
+        },
+        RawResourceTemplate {
+            uri_template: "file:///documents/{name}.text".to_string(),
+            name: "read file".to_string(),
+            description: Some("".to_string()),
+            mime_type: Some("text/plain".to_string()),
+        },
+    ]
+}
+
+/// 提示词
+fn builtin_prompts() -> Vec<Prompt> {
+    vec![Prompt::new(
+        "code_review".to_string(),
+        Some("Code review assistance".to_string()),
+        Some(vec![PromptArgument {
+            name: "pr_number".to_string(),
+            description: Some("pr_number is required".to_string()),
+            required: Some(true),
+        }]),
+    )]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_changes() {
+        let mut config = Config::default();
+        let old = Registry::new(&config);
+        assert!(old.tool_enabled("sum"));
+        assert!(old.resource_enabled("docs://readme"));
+
+        config.registry.tools = vec!["sum".to_string()];
+        config.registry.disabled_prompts = vec!["code_review".to_string()];
+        let new = Registry::new(&config);
+        assert!(new.tool_enabled("sum"));
+        assert!(!new.tool_enabled("echo"));
+        assert!(!new.prompt_enabled("code_review"));
+        assert!(new.prompt_enabled("py.code_review"));
+        assert_eq!(
+            new.changes(&old),
+            RegistryChanges {
+                tools: true,
+                resources: false,
+                prompts: true,
+            }
+        );
+
+        config.registry.disabled_resources = vec!["test://dynamic/resource/{id}".to_string()];
+        let newer = Registry::new(&config);
+        assert!(!newer.resource_template_enabled("test://dynamic/resource/{id}"));
+        assert!(newer.changes(&new).resources);
+    }
+}
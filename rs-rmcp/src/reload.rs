@@ -0,0 +1,190 @@
+//! 配置热加载
+//!
+//! 配置文件变化或收到 SIGHUP 时重新加载配置, 原子替换注册表,
+//! 并向已连接的客户端推送 `notifications/*/list_changed`。
+//! 监听地址、TLS、限流等其它配置只在启动时生效, 变化时仅记录警告。
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use serde_json::Value;
+
+use crate::{
+    config::Config,
+    error::Error,
+    registry::{Registry, RegistryChanges},
+    state::AppState,
+};
+
+/// 配置文件修改时间的轮询间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 启动后台热加载任务
+///
+/// `overrides` 为命令行参数覆盖值, 重新加载时同样生效。
+pub fn spawn(state: Arc<AppState>, config_path: Option<PathBuf>, overrides: Value) {
+    tokio::spawn(async move {
+        if let Err(e) = watch(&state, config_path.as_deref(), &overrides).await {
+            tracing::error!("config watcher stopped: {e}");
+        }
+    });
+}
+
+async fn watch(
+    state: &AppState,
+    config_path: Option<&Path>,
+    overrides: &Value,
+) -> Result<(), Error> {
+    #[cfg(unix)]
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+    let mut modified = config_path.and_then(modified_time);
+
+    loop {
+        #[cfg(unix)]
+        let hangup = hangup.recv();
+        #[cfg(not(unix))]
+        let hangup = std::future::pending::<Option<()>>();
+
+        tokio::select! {
+            _ = hangup => {
+                tracing::info!("received SIGHUP, reloading config");
+            }
+            _ = tokio::time::sleep(POLL_INTERVAL) => {
+                let current = config_path.and_then(modified_time);
+                if current == modified {
+                    continue;
+                }
+                modified = current;
+                tracing::info!(path = ?config_path, "config file changed, reloading");
+            }
+        }
+
+        match reload(state, config_path, overrides.clone()) {
+            Ok(changes) => tracing::info!(?changes, "config reloaded"),
+            // 保留旧注册表, 等待下一次修改
+            Err(e) => tracing::error!("failed to reload config: {e}"),
+        }
+    }
+}
+
+/// 重新加载配置并替换注册表, 返回发生变化的列表
+pub fn reload(
+    state: &AppState,
+    config_path: Option<&Path>,
+    overrides: Value,
+) -> Result<RegistryChanges, Error> {
+    let (config, _) = Config::load(config_path, overrides)?;
+    warn_restart_required(&state.config, &config)?;
+
+    let changes = state.replace_registry(Registry::new(&config));
+    notify(state, &changes);
+    Ok(changes)
+}
+
+/// 注册表以外的配置需要重启才能生效
+fn warn_restart_required(current: &Config, new: &Config) -> Result<(), Error> {
+    let (Value::Object(current), Value::Object(new)) =
+        (serde_json::to_value(current)?, serde_json::to_value(new)?)
+    else {
+        return Ok(());
+    };
+    let fields: Vec<&String> = new
+        .iter()
+        .filter(|(field, value)| *field != "registry" && current.get(*field) != Some(value))
+        .map(|(field, _)| field)
+        .collect();
+    if !fields.is_empty() {
+        tracing::warn!(?fields, "changed settings take effect after restart");
+    }
+    Ok(())
+}
+
+/// 向仍然连接的客户端推送列表变更通知
+///
+/// 每个客户端在单独的任务中发送, 慢或卡住的客户端不会拖延其他客户端与调用方。
+pub fn notify(state: &AppState, changes: &RegistryChanges) {
+    let capabilities = &state.config.capabilities;
+    let tools = changes.tools && capabilities.tools && capabilities.tool_list_changed;
+    let prompts = changes.prompts && capabilities.prompts && capabilities.prompt_list_changed;
+    let resources =
+        changes.resources && capabilities.resources && capabilities.resource_list_changed;
+    if !(tools || prompts || resources) {
+        return;
+    }
+
+    for peer in state.sessions.list_changed_peers() {
+        tokio::spawn(async move {
+            let result = async {
+                if tools {
+                    peer.notify_tool_list_changed().await?;
+                }
+                if prompts {
+                    peer.notify_prompt_list_changed().await?;
+                }
+                if resources {
+                    peer.notify_resource_list_changed().await?;
+                }
+                Ok::<_, rmcp::service::ServiceError>(())
+            }
+            .await;
+            if let Err(e) = result {
+                tracing::warn!("failed to send list_changed notification: {e}");
+            }
+        });
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reload_swaps_registry() {
+        let dir = std::env::temp_dir().join(format!("rs-mcpr-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rs-mcpr.toml");
+        std::fs::write(&path, "").unwrap();
+
+        let (config, _) = Config::load(Some(&path), json!({})).unwrap();
+        let state = AppState::new(config).unwrap();
+        let before = state.registry();
+        assert!(before.prompt_enabled("code_review"));
+
+        std::fs::write(
+            &path,
+            "[registry]\ndisabled_prompts = [\"code_review\"]\n\n\
+             [[registry.files]]\nuri = \"file:///notes\"\npath = \"notes.md\"\nname = \"notes\"\n",
+        )
+        .unwrap();
+        let changes = reload(&state, Some(&path), json!({})).unwrap();
+        assert_eq!(
+            changes,
+            RegistryChanges {
+                tools: false,
+                resources: true,
+                prompts: true,
+            }
+        );
+
+        // 旧快照不受影响
+        assert!(before.prompt_enabled("code_review"));
+        let after = state.registry();
+        assert!(!after.prompt_enabled("code_review"));
+        assert!(after.resource_enabled("file:///notes"));
+
+        // 配置无效时保留旧注册表
+        std::fs::write(&path, "[registry]\nunknown = 1\n").unwrap();
+        assert!(reload(&state, Some(&path), json!({})).is_err());
+        assert!(!state.registry().prompt_enabled("code_review"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
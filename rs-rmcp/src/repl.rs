@@ -0,0 +1,791 @@
+//! 交互式客户端
+//!
+//! `client repl` 连接任意 MCP 服务后进入交互式命令行:
+//! - Tab 补全命令、工具名、资源 URI、提示词名以及工具与提示词的参数名
+//! - `call <tool>` / `prompt <name>` 不带参数时按 `inputSchema` 或提示词参数逐个询问
+//! - `history`、`info` (会话信息) 与 `notifications` (收到的通知)
+//!
+//! `client --local repl` 以 stdio 传输启动本程序, 不需要网络即可测试 Calculator 服务。
+//! 标准输入是终端时 (Unix) 使用内置的行编辑器, 支持方向键、历史记录与 Tab 补全;
+//! 否则按行读取, 便于脚本驱动。
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use rmcp::model::{
+    JsonObject, Prompt, ServerNotification, SubscribeRequestParam, Tool, UnsubscribeRequestParam,
+};
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::{
+    client::{self, ClientCommand, McpClient, Target},
+    error::ClientError,
+};
+
+/// 命令与帮助
+const COMMANDS: &[(&str, &str)] = &[
+    ("tools", "list tools"),
+    (
+        "call",
+        "call <tool> [KEY=VALUE...], asks for arguments when none are given",
+    ),
+    ("resources", "list resources and resource templates"),
+    ("read", "read <uri>"),
+    ("subscribe", "subscribe <uri>"),
+    ("unsubscribe", "unsubscribe <uri>"),
+    ("prompts", "list prompts"),
+    (
+        "prompt",
+        "prompt <name> [KEY=VALUE...], asks for arguments when none are given",
+    ),
+    (
+        "notifications",
+        "show received notifications, `notifications clear` drops them",
+    ),
+    ("info", "show server and session info"),
+    ("history", "show command history"),
+    ("json", "json on|off, print results as JSON"),
+    ("refresh", "reload tool, resource and prompt names"),
+    ("help", "show this help"),
+    ("quit", "leave the shell"),
+];
+
+/// 用于补全的工具、资源与提示词
+#[derive(Debug, Default)]
+struct Catalog {
+    tools: Vec<Tool>,
+    resources: Vec<String>,
+    prompts: Vec<Prompt>,
+}
+
+impl Catalog {
+    /// 只获取服务声明的能力, 获取失败时视为空列表
+    async fn load(client: &McpClient) -> Self {
+        let capabilities = client
+            .peer_info()
+            .map(|info| info.capabilities.clone())
+            .unwrap_or_default();
+        let mut catalog = Self::default();
+        if capabilities.tools.is_some() {
+            catalog.tools = client.list_all_tools().await.unwrap_or_default();
+        }
+        if capabilities.resources.is_some() {
+            catalog.resources = client
+                .list_all_resources()
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .map(|r| r.uri.clone())
+                .collect();
+        }
+        if capabilities.prompts.is_some() {
+            catalog.prompts = client.list_all_prompts().await.unwrap_or_default();
+        }
+        catalog
+    }
+
+    fn tool(&self, name: &str) -> Option<&Tool> {
+        self.tools.iter().find(|t| t.name == name)
+    }
+
+    fn prompt(&self, name: &str) -> Option<&Prompt> {
+        self.prompts.iter().find(|p| p.name == name)
+    }
+
+    /// 补全光标前的文本, 返回被补全单词的字节起始位置与候选
+    fn complete(&self, line: &str) -> (usize, Vec<String>) {
+        let start = line.rfind(' ').map_or(0, |i| i + 1);
+        let word = &line[start..];
+        let words: Vec<&str> = line[..start].split_whitespace().collect();
+        // 已填写的参数不再补全
+        let unused = |names: Vec<String>, given: &[&str]| -> Vec<String> {
+            names
+                .into_iter()
+                .filter(|n| {
+                    !given
+                        .iter()
+                        .any(|g| g.split('=').next() == Some(n.as_str()))
+                })
+                .map(|n| format!("{n}="))
+                .collect()
+        };
+        let candidates: Vec<String> = match words.as_slice() {
+            [] => COMMANDS.iter().map(|(c, _)| c.to_string()).collect(),
+            ["call"] => self.tools.iter().map(|t| t.name.to_string()).collect(),
+            ["call", tool, given @ ..] => self
+                .tool(tool)
+                .map(|t| unused(properties(t).map(|(n, _)| n.clone()).collect(), given))
+                .unwrap_or_default(),
+            ["read" | "subscribe" | "unsubscribe"] => self.resources.clone(),
+            ["prompt"] => self.prompts.iter().map(|p| p.name.clone()).collect(),
+            ["prompt", prompt, given @ ..] => self
+                .prompt(prompt)
+                .map(|p| {
+                    let names = p.arguments.iter().flatten().map(|a| a.name.clone());
+                    unused(names.collect(), given)
+                })
+                .unwrap_or_default(),
+            ["json"] => vec!["on".into(), "off".into()],
+            ["notifications"] => vec!["clear".into()],
+            _ => Vec::new(),
+        };
+        let candidates = candidates
+            .into_iter()
+            .filter(|c| c.starts_with(word))
+            .collect();
+        (start, candidates)
+    }
+}
+
+fn properties(tool: &Tool) -> impl Iterator<Item = (&String, &Value)> {
+    tool.input_schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+}
+
+/// 按 schema 的 `type` 解析输入, 字符串原样保留, 其他类型按 JSON 解析
+fn parse_value(input: &str, schema: &Value) -> Result<Value, String> {
+    let Some(kind) = schema.get("type").and_then(Value::as_str) else {
+        return Ok(serde_json::from_str(input).unwrap_or_else(|_| Value::String(input.into())));
+    };
+    if kind == "string" {
+        return Ok(Value::String(input.to_string()));
+    }
+    let value: Value = serde_json::from_str(input).map_err(|e| format!("expected {kind}: {e}"))?;
+    let matches = match kind {
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "null" => value.is_null(),
+        _ => true,
+    };
+    if !matches {
+        return Err(format!("expected {kind}"));
+    }
+    Ok(value)
+}
+
+/// 读取输入行, 终端上使用行编辑器
+struct Editor {
+    history: Vec<String>,
+    terminal: bool,
+}
+
+impl Editor {
+    fn new() -> Self {
+        Self {
+            history: Vec::new(),
+            terminal: io::stdin().is_terminal() && io::stdout().is_terminal(),
+        }
+    }
+
+    /// 输入结束时返回 `None`, 在终端上按 Ctrl+C 返回空行
+    fn read_line(
+        &mut self,
+        prompt: &str,
+        complete: &dyn Fn(&str) -> (usize, Vec<String>),
+    ) -> io::Result<Option<String>> {
+        #[cfg(unix)]
+        if self.terminal {
+            return tokio::task::block_in_place(|| {
+                terminal::read_line(prompt, &self.history, complete)
+            });
+        }
+        if self.terminal {
+            print!("{prompt}");
+            io::stdout().flush()?;
+        }
+        let mut line = String::new();
+        let read = tokio::task::block_in_place(|| io::stdin().lock().read_line(&mut line))?;
+        Ok((read > 0).then(|| line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+
+    fn add_history(&mut self, line: &str) {
+        if self.history.last().map(String::as_str) != Some(line) {
+            self.history.push(line.to_string());
+        }
+    }
+}
+
+struct Repl {
+    client: McpClient,
+    target: Target,
+    notifications: mpsc::UnboundedReceiver<ServerNotification>,
+    received: Vec<ServerNotification>,
+    shown: usize,
+    catalog: Catalog,
+    editor: Editor,
+    json: bool,
+}
+
+/// 执行 `client repl`
+pub async fn run(
+    client: McpClient,
+    notifications: mpsc::UnboundedReceiver<ServerNotification>,
+    target: &Target,
+    json: bool,
+) -> Result<(), ClientError> {
+    let catalog = Catalog::load(&client).await;
+    let mut repl = Repl {
+        client,
+        target: target.clone(),
+        notifications,
+        received: Vec::new(),
+        shown: 0,
+        catalog,
+        editor: Editor::new(),
+        json,
+    };
+    if repl.editor.terminal {
+        repl.info();
+        println!("type `help` for commands, Tab to complete");
+    }
+    loop {
+        repl.poll_notifications().await;
+        let catalog = &repl.catalog;
+        let Some(line) = repl.editor.read_line("mcp> ", &|l| catalog.complete(l))? else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        repl.editor.add_history(line);
+        let Some(words) = shlex::split(line) else {
+            println!("error: unbalanced quotes");
+            continue;
+        };
+        if matches!(words[0].as_str(), "quit" | "exit") {
+            break;
+        }
+        // 命令执行中按 Ctrl+C 放弃等待
+        tokio::select! {
+            result = repl.dispatch(&words) => {
+                if let Err(e) = result {
+                    println!("error: {e}");
+                }
+            }
+            _ = tokio::signal::ctrl_c() => println!("interrupted"),
+        }
+    }
+    repl.client.close().await;
+    Ok(())
+}
+
+impl Repl {
+    /// 收取新通知, 列表变化时刷新补全数据
+    async fn poll_notifications(&mut self) {
+        let mut changed = false;
+        while let Ok(notification) = self.notifications.try_recv() {
+            changed |= matches!(
+                notification,
+                ServerNotification::ToolListChangedNotification(_)
+                    | ServerNotification::ResourceListChangedNotification(_)
+                    | ServerNotification::PromptListChangedNotification(_)
+            );
+            self.received.push(notification);
+        }
+        if changed {
+            self.catalog = Catalog::load(&self.client).await;
+        }
+        let new = self.received.len() - self.shown;
+        if new > 0 {
+            println!("({new} new notifications, type `notifications` to show)");
+        }
+    }
+
+    async fn dispatch(&mut self, words: &[String]) -> Result<(), ClientError> {
+        let arg = |i: usize| words.get(i).cloned();
+        let usage = |command: &str| {
+            let help = COMMANDS
+                .iter()
+                .find(|(c, _)| *c == command)
+                .map(|(_, h)| *h);
+            println!("usage: {}", help.unwrap_or(command));
+        };
+        let json = self.json;
+        match words[0].as_str() {
+            "tools" => client::execute(&self.client, &ClientCommand::Tools, json).await?,
+            "resources" => client::execute(&self.client, &ClientCommand::Resources, json).await?,
+            "prompts" => client::execute(&self.client, &ClientCommand::Prompts, json).await?,
+            "read" => match arg(1) {
+                Some(uri) => {
+                    client::execute(&self.client, &ClientCommand::Read { uri }, json).await?
+                }
+                None => usage("read"),
+            },
+            "call" => {
+                let Some(name) = arg(1) else {
+                    usage("call");
+                    return Ok(());
+                };
+                let arguments = if words.len() > 2 {
+                    client::parse_arguments(&words[2..], true)?
+                } else {
+                    let tool = self.catalog.tool(&name).cloned();
+                    match tool {
+                        Some(tool) => match self.ask_tool_arguments(&tool)? {
+                            Some(arguments) => arguments,
+                            None => return Ok(()),
+                        },
+                        None => JsonObject::new(),
+                    }
+                };
+                client::call(&self.client, &name, arguments, json).await?;
+            }
+            "prompt" => {
+                let Some(name) = arg(1) else {
+                    usage("prompt");
+                    return Ok(());
+                };
+                let arguments = if words.len() > 2 {
+                    client::parse_arguments(&words[2..], false)?
+                } else {
+                    let prompt = self.catalog.prompt(&name).cloned();
+                    match prompt {
+                        Some(prompt) => match self.ask_prompt_arguments(&prompt)? {
+                            Some(arguments) => arguments,
+                            None => return Ok(()),
+                        },
+                        None => JsonObject::new(),
+                    }
+                };
+                client::render_prompt(&self.client, &name, arguments, json).await?;
+            }
+            "subscribe" => match arg(1) {
+                Some(uri) => {
+                    self.client
+                        .subscribe(SubscribeRequestParam { uri: uri.clone() })
+                        .await?;
+                    println!("subscribed to {uri}");
+                }
+                None => usage("subscribe"),
+            },
+            "unsubscribe" => match arg(1) {
+                Some(uri) => {
+                    self.client
+                        .unsubscribe(UnsubscribeRequestParam { uri: uri.clone() })
+                        .await?;
+                    println!("unsubscribed from {uri}");
+                }
+                None => usage("unsubscribe"),
+            },
+            "notifications" => {
+                if arg(1).as_deref() == Some("clear") {
+                    self.received.clear();
+                } else {
+                    for notification in &self.received {
+                        println!("{}", client::format_notification(notification, json));
+                    }
+                }
+                self.shown = self.received.len();
+            }
+            "info" => self.info(),
+            "history" => {
+                for (i, line) in self.editor.history.iter().enumerate() {
+                    println!("{:>4}  {line}", i + 1);
+                }
+            }
+            "json" => match arg(1).as_deref() {
+                Some("on") => self.json = true,
+                Some("off") => self.json = false,
+                _ => println!("json output is {}", if json { "on" } else { "off" }),
+            },
+            "refresh" => self.catalog = Catalog::load(&self.client).await,
+            "help" => {
+                for (command, help) in COMMANDS {
+                    println!("{command:<14} {help}");
+                }
+            }
+            command => println!("unknown command `{command}`, type `help` for commands"),
+        }
+        Ok(())
+    }
+
+    fn info(&self) {
+        let target = match &self.target {
+            Target::Stdio(command) => format!("stdio `{}`", command.join(" ")),
+            Target::Sse(url) => format!("sse {url}"),
+            Target::Http(url) => format!("streamable http {url}"),
+        };
+        println!("connected to {target}");
+        if let Some(info) = self.client.peer_info() {
+            println!(
+                "server {} {}, protocol {}",
+                info.server_info.name, info.server_info.version, info.protocol_version
+            );
+            let capabilities = serde_json::to_string(&info.capabilities).unwrap_or_default();
+            println!("capabilities {capabilities}");
+            if let Some(instructions) = &info.instructions {
+                println!("instructions {instructions}");
+            }
+        }
+        println!(
+            "{} tools, {} resources, {} prompts, {} notifications received",
+            self.catalog.tools.len(),
+            self.catalog.resources.len(),
+            self.catalog.prompts.len(),
+            self.received.len()
+        );
+    }
+
+    /// 按 `inputSchema` 逐个询问参数, 必填项先问; 放弃输入时返回 `None`
+    fn ask_tool_arguments(&mut self, tool: &Tool) -> Result<Option<JsonObject>, ClientError> {
+        let required: Vec<&str> = tool
+            .input_schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        let mut fields: Vec<(&String, &Value, bool)> = properties(tool)
+            .map(|(name, schema)| (name, schema, required.contains(&name.as_str())))
+            .collect();
+        fields.sort_by_key(|(_, _, required)| !required);
+
+        let mut arguments = JsonObject::new();
+        for (name, schema, required) in fields {
+            let kind = schema.get("type").and_then(Value::as_str).unwrap_or("any");
+            if let Some(description) = schema.get("description").and_then(Value::as_str) {
+                println!("  {name}: {description}");
+            }
+            let prompt = format!(
+                "  {name} ({kind}{}): ",
+                if required { ", required" } else { "" }
+            );
+            loop {
+                let Some(input) = self.editor.read_line(&prompt, &|_| (0, Vec::new()))? else {
+                    return Ok(None);
+                };
+                if input.is_empty() {
+                    if required {
+                        println!("  {name} is required");
+                        continue;
+                    }
+                    break;
+                }
+                match parse_value(&input, schema) {
+                    Ok(value) => {
+                        arguments.insert(name.clone(), value);
+                        break;
+                    }
+                    Err(e) => println!("  {e}"),
+                }
+            }
+        }
+        Ok(Some(arguments))
+    }
+
+    /// 逐个询问提示词参数, 放弃输入时返回 `None`
+    fn ask_prompt_arguments(&mut self, prompt: &Prompt) -> Result<Option<JsonObject>, ClientError> {
+        let mut arguments = JsonObject::new();
+        for argument in prompt.arguments.iter().flatten() {
+            let required = argument.required == Some(true);
+            if let Some(description) = &argument.description {
+                println!("  {}: {description}", argument.name);
+            }
+            let label = format!(
+                "  {}{}: ",
+                argument.name,
+                if required { " (required)" } else { "" }
+            );
+            loop {
+                let Some(input) = self.editor.read_line(&label, &|_| (0, Vec::new()))? else {
+                    return Ok(None);
+                };
+                if input.is_empty() && required {
+                    println!("  {} is required", argument.name);
+                    continue;
+                }
+                if !input.is_empty() {
+                    arguments.insert(argument.name.clone(), Value::String(input));
+                }
+                break;
+            }
+        }
+        Ok(Some(arguments))
+    }
+}
+
+/// 基于 termios 的行编辑器
+#[cfg(unix)]
+mod terminal {
+    use std::io::{self, Read, Write};
+
+    /// 关闭回显与行缓冲, 释放时恢复
+    struct RawMode(libc::termios);
+
+    impl RawMode {
+        fn enable() -> io::Result<Self> {
+            // SAFETY: termios 是普通的 C 结构体, 由 tcgetattr 填充
+            let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let original = termios;
+            // 保留输出处理, `\n` 仍然换行到行首
+            termios.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+            termios.c_iflag &= !(libc::IXON | libc::ICRNL);
+            termios.c_cc[libc::VMIN] = 1;
+            termios.c_cc[libc::VTIME] = 0;
+            if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self(original))
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0) };
+        }
+    }
+
+    fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        Ok((input.read(&mut byte)? == 1).then_some(byte[0]))
+    }
+
+    /// 终端显示宽度, 东亚宽字符与 emoji 占两列
+    fn width(c: char) -> usize {
+        match c as u32 {
+            0x1100..=0x115F
+            | 0x2E80..=0xA4CF
+            | 0xAC00..=0xD7A3
+            | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6
+            | 0x1F300..=0x1F64F
+            | 0x1F900..=0x1F9FF
+            | 0x20000..=0x3FFFD => 2,
+            _ => 1,
+        }
+    }
+
+    fn redraw(out: &mut impl Write, prompt: &str, line: &[char], cursor: usize) -> io::Result<()> {
+        let text: String = line.iter().collect();
+        write!(out, "\r\x1b[K{prompt}{text}")?;
+        let back: usize = line[cursor..].iter().map(|c| width(*c)).sum();
+        if back > 0 {
+            write!(out, "\x1b[{back}D")?;
+        }
+        out.flush()
+    }
+
+    fn common_prefix(candidates: &[String]) -> String {
+        let mut prefix: Vec<char> = candidates[0].chars().collect();
+        for candidate in &candidates[1..] {
+            let common = prefix
+                .iter()
+                .zip(candidate.chars())
+                .take_while(|(a, b)| **a == *b)
+                .count();
+            prefix.truncate(common);
+        }
+        prefix.into_iter().collect()
+    }
+
+    pub fn read_line(
+        prompt: &str,
+        history: &[String],
+        complete: &dyn Fn(&str) -> (usize, Vec<String>),
+    ) -> io::Result<Option<String>> {
+        let _raw = RawMode::enable()?;
+        let mut input = io::stdin().lock();
+        let mut out = io::stdout().lock();
+        let mut line: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        // 浏览历史时保存正在编辑的行
+        let mut browsing = history.len();
+        let mut draft: Vec<char> = Vec::new();
+        let mut last_tab = false;
+        redraw(&mut out, prompt, &line, cursor)?;
+
+        loop {
+            let Some(byte) = read_byte(&mut input)? else {
+                return Ok(None);
+            };
+            let mut tab = false;
+            match byte {
+                b'\r' | b'\n' => {
+                    writeln!(out)?;
+                    return Ok(Some(line.into_iter().collect()));
+                }
+                // Ctrl+C 放弃当前行
+                0x03 => {
+                    writeln!(out, "^C")?;
+                    return Ok(Some(String::new()));
+                }
+                0x04 if line.is_empty() => {
+                    writeln!(out)?;
+                    return Ok(None);
+                }
+                0x04 if cursor < line.len() => {
+                    line.remove(cursor);
+                }
+                0x7f | 0x08 if cursor > 0 => {
+                    cursor -= 1;
+                    line.remove(cursor);
+                }
+                0x01 => cursor = 0,
+                0x05 => cursor = line.len(),
+                0x15 => {
+                    line.drain(..cursor);
+                    cursor = 0;
+                }
+                0x0b => line.truncate(cursor),
+                0x0c => write!(out, "\x1b[2J\x1b[H")?,
+                b'\t' => {
+                    tab = true;
+                    let before: String = line[..cursor].iter().collect();
+                    let (start, candidates) = complete(&before);
+                    let start = before[..start].chars().count();
+                    let replace = |line: &mut Vec<char>, cursor: &mut usize, text: &str| {
+                        line.splice(start..*cursor, text.chars());
+                        *cursor = start + text.chars().count();
+                    };
+                    match candidates.as_slice() {
+                        [] => write!(out, "\x07")?,
+                        [only] => {
+                            let suffix = if only.ends_with('=') { "" } else { " " };
+                            replace(&mut line, &mut cursor, &format!("{only}{suffix}"));
+                        }
+                        many => {
+                            let common = common_prefix(many);
+                            if common.chars().count() > cursor - start {
+                                replace(&mut line, &mut cursor, &common);
+                            } else if last_tab {
+                                writeln!(out, "\n{}", many.join("  "))?;
+                            } else {
+                                write!(out, "\x07")?;
+                            }
+                        }
+                    }
+                }
+                0x1b => {
+                    let Some(b'[' | b'O') = read_byte(&mut input)? else {
+                        continue;
+                    };
+                    let mut code = read_byte(&mut input)?.unwrap_or_default();
+                    let mut number = None;
+                    while code.is_ascii_digit() {
+                        number = Some(code);
+                        code = read_byte(&mut input)?.unwrap_or_default();
+                    }
+                    match (number, code) {
+                        (None, b'A') if browsing > 0 => {
+                            if browsing == history.len() {
+                                draft = line.clone();
+                            }
+                            browsing -= 1;
+                            line = history[browsing].chars().collect();
+                            cursor = line.len();
+                        }
+                        (None, b'B') if browsing < history.len() => {
+                            browsing += 1;
+                            line = match history.get(browsing) {
+                                Some(entry) => entry.chars().collect(),
+                                None => draft.clone(),
+                            };
+                            cursor = line.len();
+                        }
+                        (None, b'C') => cursor = (cursor + 1).min(line.len()),
+                        (None, b'D') => cursor = cursor.saturating_sub(1),
+                        (None, b'H') | (Some(b'1' | b'7'), b'~') => cursor = 0,
+                        (None, b'F') | (Some(b'4' | b'8'), b'~') => cursor = line.len(),
+                        (Some(b'3'), b'~') if cursor < line.len() => {
+                            line.remove(cursor);
+                        }
+                        _ => {}
+                    }
+                }
+                byte if byte >= 0x20 => {
+                    // 读取 UTF-8 字符的后续字节
+                    let len = match byte {
+                        0xF0.. => 4,
+                        0xE0.. => 3,
+                        0xC0.. => 2,
+                        _ => 1,
+                    };
+                    let mut bytes = vec![byte];
+                    for _ in 1..len {
+                        bytes.extend(read_byte(&mut input)?);
+                    }
+                    if let Ok(text) = std::str::from_utf8(&bytes) {
+                        for c in text.chars() {
+                            line.insert(cursor, c);
+                            cursor += 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            last_tab = tab;
+            redraw(&mut out, prompt, &line, cursor)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn catalog() -> Catalog {
+        let schema = json!({
+            "type": "object",
+            "properties": {"a": {"type": "integer"}, "b": {"type": "integer"}},
+            "required": ["a", "b"],
+        });
+        let schema = Arc::new(schema.as_object().unwrap().clone());
+        Catalog {
+            tools: vec![
+                Tool::new("sum", "sum", schema.clone()),
+                Tool::new("sub2", "sub", schema),
+            ],
+            resources: vec!["docs://readme".into(), "file:///a.txt".into()],
+            prompts: vec![Prompt::new("code_review", None::<String>, None)],
+        }
+    }
+
+    #[test]
+    fn test_complete_and_parse_value() {
+        let catalog = catalog();
+        assert_eq!(catalog.complete("to"), (0, vec!["tools".to_string()]));
+        assert_eq!(
+            catalog.complete("call s"),
+            (5, vec!["sum".to_string(), "sub2".to_string()])
+        );
+        assert_eq!(
+            catalog.complete("call sum a=1 "),
+            (13, vec!["b=".to_string()])
+        );
+        assert_eq!(
+            catalog.complete("read do"),
+            (5, vec!["docs://readme".to_string()])
+        );
+        assert_eq!(
+            catalog.complete("prompt "),
+            (7, vec!["code_review".to_string()])
+        );
+        assert!(catalog.complete("call nope ").1.is_empty());
+
+        let integer = json!({"type": "integer"});
+        assert_eq!(parse_value("3", &integer), Ok(json!(3)));
+        assert!(parse_value("3.5", &integer).is_err());
+        assert!(parse_value("x", &integer).is_err());
+        assert_eq!(
+            parse_value("42", &json!({"type": "string"})),
+            Ok(json!("42"))
+        );
+        assert_eq!(parse_value("hi", &json!({})), Ok(json!("hi")));
+    }
+}
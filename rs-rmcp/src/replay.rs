@@ -0,0 +1,431 @@
+//! 录制回放
+//!
+//! 把录制文件中客户端发出的消息按原顺序送入进程内的 [`crate::calculator::Calculator`],
+//! 并把实际响应与录制的响应逐字段比较。每遇到一条录制的响应, 先等待同一请求 ID 的实际响应
+//! 再继续发送, 取消等依赖先后顺序的消息与录制时一致。服务端主动发出的通知与请求不参与比较。
+//!
+//! `testdata/golden` 下的录制作为服务端的回归测试, 行为有意变化时用 `--update` 更新。
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use rmcp::{
+    RoleClient, ServiceExt,
+    model::ClientJsonRpcMessage,
+    transport::{Transport, async_rw::AsyncRwTransport},
+};
+use serde_json::Value;
+
+use crate::{
+    calculator,
+    config::Config,
+    error::ReplayError,
+    metrics::SessionTransport,
+    recording::{Direction, RecordedMessage},
+    state::AppState,
+};
+
+/// 回放参数
+#[derive(clap::Args, Debug)]
+pub struct ReplayArgs {
+    /// Recording written by the `[record]` layer (JSON Lines)
+    pub file: PathBuf,
+
+    /// Only replay this session
+    #[arg(long)]
+    pub session: Option<String>,
+
+    /// JSON pointer ignored when comparing responses, e.g. /result/serverInfo/version
+    #[arg(long = "ignore", value_name = "POINTER")]
+    pub ignore: Vec<String>,
+
+    /// Seconds to wait for each response
+    #[arg(long, default_value_t = 5)]
+    pub timeout_secs: u64,
+
+    /// Rewrite the recorded responses with the actual ones instead of failing
+    #[arg(long)]
+    pub update: bool,
+}
+
+/// 一处响应差异, `pointer` 为 JSON Pointer, 缺失的一侧为 `None`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub session: String,
+    pub id: String,
+    pub pointer: String,
+    pub expected: Option<Value>,
+    pub actual: Option<Value>,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: &Option<Value>| match value {
+            Some(value) => value.to_string(),
+            None => "<missing>".to_string(),
+        };
+        write!(
+            f,
+            "session {} request {} {}: expected {}, got {}",
+            self.session,
+            self.id,
+            match self.pointer.as_str() {
+                "" => "response",
+                pointer => pointer,
+            },
+            show(&self.expected),
+            show(&self.actual),
+        )
+    }
+}
+
+/// 单个会话的回放结果
+#[derive(Debug, Default)]
+pub struct SessionReplay {
+    /// 比较过的响应数
+    pub responses: usize,
+    pub differences: Vec<Difference>,
+    /// 录制中响应的下标与实际响应, 用于 `--update`
+    pub actual: Vec<(usize, Value)>,
+}
+
+/// 读取录制文件
+pub fn load(path: &Path) -> Result<Vec<RecordedMessage>, ReplayError> {
+    let file = File::open(path).map_err(|source| ReplayError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut records = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|e| ReplayError::Parse {
+            path: path.to_path_buf(),
+            line: number + 1,
+            message: e.to_string(),
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// 按会话分组, 保持会话首次出现的顺序, 返回每个会话的记录下标
+pub fn sessions(records: &[RecordedMessage]) -> Vec<(String, Vec<usize>)> {
+    let mut sessions: Vec<(String, Vec<usize>)> = Vec::new();
+    for (index, record) in records.iter().enumerate() {
+        match sessions.iter_mut().find(|(id, _)| *id == record.session) {
+            Some((_, indexes)) => indexes.push(index),
+            None => sessions.push((record.session.clone(), vec![index])),
+        }
+    }
+    sessions
+}
+
+/// 响应的请求 ID, 请求与通知返回 `None`
+fn response_id(message: &Value) -> Option<String> {
+    let id = message.get("id")?;
+    (message.get("result").is_some() || message.get("error").is_some()).then(|| id.to_string())
+}
+
+/// 在一个新的会话中回放录制的客户端消息
+pub async fn replay_session(
+    state: Arc<AppState>,
+    records: &[RecordedMessage],
+    indexes: &[usize],
+    ignore: &[String],
+    timeout: Duration,
+) -> Result<SessionReplay, ReplayError> {
+    let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+    let service = calculator::session_service(state, SessionTransport::Stdio);
+    let ct = service.close_token();
+    let server = tokio::spawn(async move {
+        match service.serve_with_ct(tokio::io::split(server_io), ct).await {
+            Ok(server) => {
+                let _ = server.waiting().await;
+            }
+            Err(e) => tracing::error!("replay serving error: {:?}", e),
+        }
+    });
+    let (read, write) = tokio::io::split(client_io);
+    let mut client = AsyncRwTransport::<RoleClient, _, _>::new_client(read, write);
+
+    let mut replay = SessionReplay::default();
+    let mut received: HashMap<String, Value> = HashMap::new();
+    for &index in indexes {
+        let record = &records[index];
+        match record.direction {
+            Direction::In => {
+                let message: ClientJsonRpcMessage = serde_json::from_value(record.message.clone())?;
+                client.send(message).await?;
+            }
+            Direction::Out => {
+                let Some(id) = response_id(&record.message) else {
+                    continue;
+                };
+                replay.responses += 1;
+                let actual = wait_response(&mut client, &mut received, &id, timeout).await?;
+                let mut differences = Vec::new();
+                compare(
+                    &record.message,
+                    actual.as_ref(),
+                    &mut String::new(),
+                    ignore,
+                    &mut differences,
+                );
+                replay.differences.extend(differences.into_iter().map(
+                    |(pointer, expected, actual)| Difference {
+                        session: record.session.clone(),
+                        id: id.clone(),
+                        pointer,
+                        expected,
+                        actual,
+                    },
+                ));
+                if let Some(actual) = actual {
+                    replay.actual.push((index, actual));
+                }
+            }
+        }
+    }
+    client.close().await?;
+    server.abort();
+    Ok(replay)
+}
+
+/// 等待指定 ID 的响应, 先到的其它响应暂存起来
+async fn wait_response(
+    client: &mut AsyncRwTransport<
+        RoleClient,
+        tokio::io::ReadHalf<tokio::io::DuplexStream>,
+        tokio::io::WriteHalf<tokio::io::DuplexStream>,
+    >,
+    received: &mut HashMap<String, Value>,
+    id: &str,
+    timeout: Duration,
+) -> Result<Option<Value>, ReplayError> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        if let Some(message) = received.remove(id) {
+            return Ok(Some(message));
+        }
+        let Ok(Some(message)) = tokio::time::timeout_at(deadline, client.receive()).await else {
+            return Ok(None);
+        };
+        let message = serde_json::to_value(message)?;
+        if let Some(id) = response_id(&message) {
+            received.insert(id, message);
+        }
+    }
+}
+
+/// 逐字段比较, 记录差异的 JSON Pointer
+fn compare(
+    expected: &Value,
+    actual: Option<&Value>,
+    pointer: &mut String,
+    ignore: &[String],
+    differences: &mut Vec<(String, Option<Value>, Option<Value>)>,
+) {
+    if ignore.iter().any(|ignored| ignored == pointer) {
+        return;
+    }
+    let Some(actual) = actual else {
+        differences.push((pointer.clone(), Some(expected.clone()), None));
+        return;
+    };
+    let mut child = |key: &str, expected: Option<&Value>, actual: Option<&Value>| {
+        let len = pointer.len();
+        pointer.push('/');
+        pointer.push_str(&key.replace('~', "~0").replace('/', "~1"));
+        match expected {
+            Some(expected) => compare(expected, actual, pointer, ignore, differences),
+            None if !ignore.iter().any(|ignored| ignored == pointer) => {
+                differences.push((pointer.clone(), None, actual.cloned()));
+            }
+            None => {}
+        }
+        pointer.truncate(len);
+    };
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            let mut keys: Vec<&String> = expected.keys().chain(actual.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                child(key, expected.get(key), actual.get(key));
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            for index in 0..expected.len().max(actual.len()) {
+                child(&index.to_string(), expected.get(index), actual.get(index));
+            }
+        }
+        (expected, actual) if expected != actual => {
+            differences.push((
+                pointer.clone(),
+                Some(expected.clone()),
+                Some(actual.clone()),
+            ));
+        }
+        _ => {}
+    }
+}
+
+/// `rs-mcpr replay`
+pub async fn run(mut config: Config, args: &ReplayArgs) -> Result<(), ReplayError> {
+    // 回放产生的消息不再写入录制文件
+    config.record.enabled = false;
+    let state = Arc::new(AppState::new(config)?);
+    let mut records = load(&args.file)?;
+    let timeout = Duration::from_secs(args.timeout_secs);
+
+    let mut replayed = 0;
+    let mut responses = 0;
+    let mut differences = Vec::new();
+    let mut updates = Vec::new();
+    for (session, indexes) in sessions(&records) {
+        if args.session.as_ref().is_some_and(|only| *only != session) {
+            continue;
+        }
+        let replay =
+            replay_session(state.clone(), &records, &indexes, &args.ignore, timeout).await?;
+        replayed += 1;
+        responses += replay.responses;
+        for difference in &replay.differences {
+            println!("{difference}");
+        }
+        differences.extend(replay.differences);
+        updates.extend(replay.actual);
+    }
+    println!(
+        "{replayed} sessions, {responses} responses, {} differences",
+        differences.len()
+    );
+
+    if differences.is_empty() {
+        return Ok(());
+    }
+    if !args.update {
+        return Err(ReplayError::Mismatch(differences.len()));
+    }
+    for (index, actual) in updates {
+        records[index].message = actual;
+    }
+    let mut output = Vec::new();
+    for record in &records {
+        serde_json::to_writer(&mut output, record)?;
+        output.push(b'\n');
+    }
+    fs::File::create(&args.file)?.write_all(&output)?;
+    println!("updated {}", args.file.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// 回放 `testdata/golden` 下的所有录制
+    #[tokio::test]
+    async fn test_golden_recordings() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/golden");
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        let state = Arc::new(AppState::new(Config::default()).unwrap());
+        for path in paths {
+            let records = load(&path).unwrap();
+            for (session, indexes) in sessions(&records) {
+                let replay = replay_session(
+                    state.clone(),
+                    &records,
+                    &indexes,
+                    &[],
+                    Duration::from_secs(5),
+                )
+                .await
+                .unwrap();
+                assert!(replay.responses > 0, "{}: {session}", path.display());
+                let differences: Vec<String> =
+                    replay.differences.iter().map(ToString::to_string).collect();
+                assert!(
+                    differences.is_empty(),
+                    "{}:\n{}",
+                    path.display(),
+                    differences.join("\n")
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let expected = json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "result": { "content": [{ "type": "text", "text": "3" }], "isError": false },
+        });
+        let actual = json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "result": {
+                "content": [{ "type": "text", "text": "4" }, { "type": "text", "text": "x" }],
+                "isError": false,
+                "a/b": 1,
+            },
+        });
+        let mut differences = Vec::new();
+        compare(
+            &expected,
+            Some(&actual),
+            &mut String::new(),
+            &[],
+            &mut differences,
+        );
+        assert_eq!(
+            differences,
+            [
+                ("/result/a~1b".to_string(), None, Some(json!(1))),
+                (
+                    "/result/content/0/text".to_string(),
+                    Some(json!("3")),
+                    Some(json!("4"))
+                ),
+                (
+                    "/result/content/1".to_string(),
+                    None,
+                    Some(json!({ "type": "text", "text": "x" }))
+                ),
+            ]
+        );
+
+        let ignore = ["/result/content".to_string(), "/result/a~1b".to_string()];
+        differences.clear();
+        compare(
+            &expected,
+            Some(&actual),
+            &mut String::new(),
+            &ignore,
+            &mut differences,
+        );
+        assert!(differences.is_empty());
+
+        compare(&expected, None, &mut String::new(), &[], &mut differences);
+        assert_eq!(differences, [(String::new(), Some(expected), None)]);
+    }
+}
@@ -0,0 +1,627 @@
+//! 断线续传
+//!
+//! HTTP 传输为每个 SSE 事件分配 `<stream>/<seq>` 形式的事件 ID, 并在每个会话中按数量与
+//! 时间保留最近的事件。连接断开后服务端继续把事件写入日志, 客户端携带 `Last-Event-ID`
+//! 重连时补发之后的事件, 流尚未结束时继续推送新事件。
+//!
+//! - streamable HTTP: [`crate::http_sessions::HttpSessionManager`] 为每个 POST 响应流与
+//!   GET 流分配流编号, 重连时向 MCP 端点发送带 `Last-Event-ID` 的 GET
+//! - SSE: 流编号为会话 ID, 重连时向 SSE 端点发送带 `Last-Event-ID` 的 GET, 断开后会话
+//!   保留 `max_age_secs` 等待重连
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{Body, BodyDataStream, Bytes},
+    extract::{Request, State},
+    http::{
+        HeaderValue, Method,
+        header::{CACHE_CONTROL, CONTENT_TYPE},
+    },
+    middleware::Next,
+    response::Response,
+};
+use futures::{
+    StreamExt,
+    channel::mpsc::{UnboundedReceiver, UnboundedSender, unbounded},
+    stream::{Chain, Iter},
+};
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::{sessions::endpoint_session_id, state::AppState};
+
+pub const LAST_EVENT_ID: &str = "last-event-id";
+
+/// 没有事件时检查 SSE 客户端是否已断开的间隔
+const DETACH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 事件日志配置, 对应配置文件的 `[event_log]` 表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventLogConfig {
+    /// 每个会话保留的事件数, 0 表示不支持续传
+    pub max_events: usize,
+    /// 事件保留时间 (秒), 也是 SSE 会话断开后等待重连的时间
+    pub max_age_secs: u64,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        Self {
+            max_events: 256,
+            max_age_secs: 300,
+        }
+    }
+}
+
+impl EventLogConfig {
+    pub fn enabled(&self) -> bool {
+        self.max_events > 0
+    }
+
+    pub fn max_age(&self) -> Duration {
+        Duration::from_secs(self.max_age_secs)
+    }
+}
+
+pub fn event_id(stream: impl Display, seq: u64) -> String {
+    format!("{stream}/{seq}")
+}
+
+/// 解析 `<stream>/<seq>`
+pub fn parse_event_id(id: &str) -> Option<(&str, u64)> {
+    let (stream, seq) = id.rsplit_once('/')?;
+    Some((stream, seq.parse().ok()?))
+}
+
+/// 订阅返回的事件流: 先补发日志中的事件, 再接收新事件, 流结束时结束
+pub type EventStream<T> = Chain<Iter<std::vec::IntoIter<T>>, UnboundedReceiver<T>>;
+
+/// 一个会话的事件日志
+///
+/// 序号在日志内单调递增, 所有流共享数量与时间上限。
+#[derive(Debug)]
+pub struct EventLog<T> {
+    max_events: usize,
+    max_age: Duration,
+    state: Mutex<LogState<T>>,
+    closed: CancellationToken,
+}
+
+#[derive(Debug)]
+struct LogState<T> {
+    next_stream: u64,
+    next_seq: u64,
+    events: VecDeque<LoggedEvent<T>>,
+    streams: HashMap<u64, StreamState<T>>,
+}
+
+#[derive(Debug)]
+struct LoggedEvent<T> {
+    stream: u64,
+    seq: u64,
+    at: Instant,
+    data: T,
+}
+
+#[derive(Debug)]
+struct StreamState<T> {
+    open: bool,
+    /// 日志中保留的事件数
+    retained: usize,
+    /// 已淘汰的最大序号
+    evicted: Option<u64>,
+    readers: Vec<UnboundedSender<T>>,
+}
+
+impl<T: Clone> EventLog<T> {
+    pub fn new(config: &EventLogConfig) -> Self {
+        Self {
+            max_events: config.max_events,
+            max_age: config.max_age(),
+            state: Mutex::new(LogState {
+                next_stream: 0,
+                next_seq: 0,
+                events: VecDeque::new(),
+                streams: HashMap::new(),
+            }),
+            closed: CancellationToken::new(),
+        }
+    }
+
+    /// 新建一个流, 返回流编号
+    pub fn open_stream(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let stream = state.next_stream;
+        state.next_stream += 1;
+        state.streams.insert(
+            stream,
+            StreamState {
+                open: true,
+                retained: 0,
+                evicted: None,
+                readers: Vec::new(),
+            },
+        );
+        stream
+    }
+
+    /// 分配序号, 写入日志并推送给订阅者
+    pub fn push(&self, stream: u64, event: impl FnOnce(u64) -> T) {
+        let mut state = self.state.lock().unwrap();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        let data = event(seq);
+        let Some(s) = state.streams.get_mut(&stream) else {
+            return;
+        };
+        s.readers
+            .retain(|reader| reader.unbounded_send(data.clone()).is_ok());
+        if self.max_events == 0 {
+            s.evicted = Some(seq);
+            return;
+        }
+        s.retained += 1;
+        state.events.push_back(LoggedEvent {
+            stream,
+            seq,
+            at: Instant::now(),
+            data,
+        });
+        self.evict(&mut state);
+    }
+
+    /// 只推送给当前订阅者, 不写入日志, 用于保活注释
+    pub fn broadcast(&self, stream: u64, data: T) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(s) = state.streams.get_mut(&stream) {
+            s.readers
+                .retain(|reader| reader.unbounded_send(data.clone()).is_ok());
+        }
+    }
+
+    /// 流已结束, 订阅者在收完事件后结束
+    pub fn finish(&self, stream: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(s) = state.streams.get_mut(&stream) {
+            s.open = false;
+            s.readers.clear();
+            if s.retained == 0 {
+                state.streams.remove(&stream);
+            }
+        }
+    }
+
+    /// 订阅流, `after` 为客户端收到的最后一个序号
+    ///
+    /// 流不存在或 `after` 之后的事件已被淘汰时返回 `None`。
+    pub fn subscribe(&self, stream: u64, after: Option<u64>) -> Option<EventStream<T>> {
+        let mut state = self.state.lock().unwrap();
+        self.evict(&mut state);
+        let s = state.streams.get(&stream)?;
+        if let Some(after) = after
+            && s.evicted.is_some_and(|evicted| evicted > after)
+        {
+            return None;
+        }
+        let open = s.open;
+        let replay: Vec<T> = match after {
+            Some(after) => state
+                .events
+                .iter()
+                .filter(|e| e.stream == stream && e.seq > after)
+                .map(|e| e.data.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+        let (tx, rx) = unbounded();
+        if open && let Some(s) = state.streams.get_mut(&stream) {
+            s.readers.push(tx);
+        }
+        Some(futures::stream::iter(replay).chain(rx))
+    }
+
+    /// 是否还有订阅者连接
+    pub fn has_readers(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .streams
+            .values()
+            .flat_map(|s| &s.readers)
+            .any(|reader| !reader.is_closed())
+    }
+
+    /// 结束所有流, 会话关闭时调用
+    pub fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        for s in state.streams.values_mut() {
+            s.open = false;
+            s.readers.clear();
+        }
+        self.closed.cancel();
+    }
+
+    pub async fn closed(&self) {
+        self.closed.cancelled().await
+    }
+
+    fn evict(&self, state: &mut LogState<T>) {
+        while let Some(front) = state.events.front() {
+            let full = state.events.len() > self.max_events;
+            if !full && front.at.elapsed() <= self.max_age {
+                break;
+            }
+            let event = state.events.pop_front().expect("front exists");
+            if let Some(s) = state.streams.get_mut(&event.stream) {
+                s.retained -= 1;
+                s.evicted = Some(event.seq);
+                if !s.open && s.retained == 0 {
+                    state.streams.remove(&event.stream);
+                }
+            }
+        }
+    }
+}
+
+/// SSE 会话的事件日志, 以会话 ID 为键
+#[derive(Debug, Default)]
+pub struct SseEventLogs {
+    logs: Mutex<HashMap<String, Arc<EventLog<Bytes>>>>,
+}
+
+impl SseEventLogs {
+    fn get(&self, id: &str) -> Option<Arc<EventLog<Bytes>>> {
+        self.logs.lock().unwrap().get(id).cloned()
+    }
+
+    fn insert(&self, id: &str, log: Arc<EventLog<Bytes>>) {
+        self.logs.lock().unwrap().insert(id.to_string(), log);
+    }
+
+    fn remove(&self, id: &str) -> Option<Arc<EventLog<Bytes>>> {
+        self.logs.lock().unwrap().remove(id)
+    }
+
+    /// 结束会话的所有连接, 不再允许续传
+    pub fn close(&self, id: &str) {
+        if let Some(log) = self.remove(id) {
+            log.close();
+        }
+    }
+}
+
+/// SSE 中间件: 为事件分配 ID, 客户端携带 `Last-Event-ID` 重连时从日志续传
+///
+/// 客户端断开后继续读取 rmcp 的响应流写入日志, 超过 `max_age_secs` 没有重连时才结束会话。
+pub async fn resumable_sse_stream(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let config = &state.config.event_log;
+    if !config.enabled() || req.method() != Method::GET {
+        return next.run(req).await;
+    }
+
+    if let Some((id, seq)) = req
+        .headers()
+        .get(LAST_EVENT_ID)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_event_id)
+    {
+        match state
+            .sse_logs
+            .get(id)
+            .and_then(|log| log.subscribe(0, Some(seq)))
+        {
+            Some(events) => {
+                tracing::debug!(session = id, seq, "resuming sse stream");
+                return Response::builder()
+                    .header(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"))
+                    .header(CACHE_CONTROL, HeaderValue::from_static("no-cache"))
+                    .body(Body::from_stream(events.map(Ok::<_, Infallible>)))
+                    .expect("valid response");
+            }
+            // 无法续传时按新连接处理, 客户端会收到新的 endpoint 事件
+            None => tracing::debug!(session = id, seq, "sse stream is not resumable"),
+        }
+    }
+
+    let response = next.run(req).await;
+    let is_sse = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if !is_sse {
+        return response;
+    }
+
+    let log = Arc::new(EventLog::new(config));
+    let stream = log.open_stream();
+    let events = log.subscribe(stream, None).expect("stream is open");
+    let (parts, body) = response.into_parts();
+    tokio::spawn(pump_sse(
+        state.clone(),
+        log,
+        stream,
+        body.into_data_stream(),
+    ));
+    Response::from_parts(parts, Body::from_stream(events.map(Ok::<_, Infallible>)))
+}
+
+/// 读取 rmcp 的 SSE 响应流写入日志, 直到流结束、会话关闭或客户端断开超过保留时间
+async fn pump_sse(
+    state: Arc<AppState>,
+    log: Arc<EventLog<Bytes>>,
+    stream: u64,
+    mut body: BodyDataStream,
+) {
+    let max_age = state.config.event_log.max_age();
+    let mut buffer = Vec::new();
+    let mut session: Option<String> = None;
+    let mut detached: Option<Instant> = None;
+    loop {
+        if log.has_readers() {
+            detached = None;
+        } else if detached.get_or_insert_with(Instant::now).elapsed() > max_age {
+            tracing::debug!(session, "sse client did not reconnect");
+            break;
+        }
+
+        let chunk = tokio::select! {
+            chunk = body.next() => chunk,
+            _ = log.closed() => break,
+            _ = tokio::time::sleep(DETACH_CHECK_INTERVAL) => continue,
+        };
+        let Some(Ok(chunk)) = chunk else {
+            break;
+        };
+        buffer.extend_from_slice(&chunk);
+        while let Some(event) = next_event(&mut buffer) {
+            if session.is_none()
+                && let Some(id) = endpoint_session_id(&event)
+            {
+                state.sse_logs.insert(&id, log.clone());
+                session = Some(id);
+            }
+            let is_comment = event.starts_with(b":");
+            match &session {
+                Some(id) if !is_comment => log.push(stream, |seq| {
+                    let mut data = format!("id: {}\n", event_id(id, seq)).into_bytes();
+                    data.extend_from_slice(&event);
+                    Bytes::from(data)
+                }),
+                _ => log.broadcast(stream, Bytes::from(event)),
+            }
+        }
+    }
+
+    log.close();
+    if let Some(id) = session {
+        state.sse_logs.remove(&id);
+    }
+}
+
+/// 从缓冲区取出一个以空行结束的完整事件
+fn next_event(buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let end = buffer.windows(2).position(|w| w == b"\n\n")? + 2;
+    Some(buffer.drain(..end).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use rmcp::{
+        ErrorData as McpError, RoleServer, ServerHandler,
+        model::{
+            CallToolRequestParam, CallToolResult, Content, ListToolsResult, PaginatedRequestParam,
+            ProgressNotificationParam, ServerCapabilities, ServerInfo,
+        },
+        service::RequestContext,
+        transport::streamable_http_server::{StreamableHttpServerConfig, StreamableHttpService},
+    };
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::{config::Config, http_sessions::HttpSessionManager, recording::Recorder};
+
+    #[tokio::test]
+    async fn test_event_log_retention() {
+        let config = EventLogConfig {
+            max_events: 3,
+            max_age_secs: 60,
+        };
+        let log = EventLog::<u64>::new(&config);
+        let a = log.open_stream();
+        let b = log.open_stream();
+        for _ in 0..3 {
+            log.push(a, |seq| seq);
+        }
+        log.push(b, |seq| seq);
+        log.push(b, |seq| seq);
+
+        // 超出数量上限时淘汰最早的事件
+        assert!(log.subscribe(a, Some(0)).is_none());
+        let events: Vec<_> = log.subscribe(a, Some(1)).unwrap().take(1).collect().await;
+        assert_eq!(events, [2]);
+
+        log.finish(b);
+        let events: Vec<_> = log.subscribe(b, Some(0)).unwrap().collect().await;
+        assert_eq!(events, [3, 4]);
+        assert!(log.subscribe(7, None).is_none());
+
+        let mut buffer = b"event: endpoint\ndata: /message?sessionId=s1\n\n:\n\nda".to_vec();
+        let event = next_event(&mut buffer).unwrap();
+        assert_eq!(endpoint_session_id(&event).as_deref(), Some("s1"));
+        assert_eq!(next_event(&mut buffer).unwrap(), b":\n\n");
+        assert!(next_event(&mut buffer).is_none());
+        assert_eq!(parse_event_id("a-b/3"), Some(("a-b", 3)));
+    }
+
+    /// 每 100ms 报告一次进度, 共 5 次
+    #[derive(Clone)]
+    struct SlowServer;
+
+    impl ServerHandler for SlowServer {
+        fn get_info(&self) -> ServerInfo {
+            ServerInfo {
+                capabilities: ServerCapabilities::builder().enable_tools().build(),
+                ..Default::default()
+            }
+        }
+
+        async fn list_tools(
+            &self,
+            _request: Option<PaginatedRequestParam>,
+            _context: RequestContext<RoleServer>,
+        ) -> Result<ListToolsResult, McpError> {
+            Ok(ListToolsResult::default())
+        }
+
+        async fn call_tool(
+            &self,
+            _request: CallToolRequestParam,
+            context: RequestContext<RoleServer>,
+        ) -> Result<CallToolResult, McpError> {
+            let token = context.meta.get_progress_token().expect("progress token");
+            for progress in 1..=5 {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                let _ = context
+                    .peer
+                    .notify_progress(ProgressNotificationParam {
+                        progress_token: token.clone(),
+                        progress: progress as f64,
+                        total: Some(5.0),
+                        message: None,
+                    })
+                    .await;
+            }
+            Ok(CallToolResult::success(vec![Content::text("done")]))
+        }
+    }
+
+    /// 解析 SSE 事件的 ID 与 JSON 数据
+    fn parse_events(text: &str) -> Vec<(String, Value)> {
+        text.split("\n\n")
+            .filter_map(|event| {
+                let mut id = None;
+                let mut data = None;
+                for line in event.lines() {
+                    if let Some(v) = line.strip_prefix("id: ") {
+                        id = Some(v.to_string());
+                    } else if let Some(v) = line.strip_prefix("data: ") {
+                        data = serde_json::from_str(v).ok();
+                    }
+                }
+                Some((id?, data?))
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_resume_after_dropped_connection() {
+        let manager =
+            Arc::new(HttpSessionManager::new(&Config::default(), Recorder::default()).unwrap());
+        let service = StreamableHttpService::new(
+            || Ok(SlowServer),
+            manager,
+            StreamableHttpServerConfig::default(),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        let app = axum::Router::new().nest_service("/mcp", service);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let http = reqwest::Client::new();
+        let post = |session: Option<&str>, body: Value| {
+            let mut request = http
+                .post(&url)
+                .header("accept", "application/json, text/event-stream")
+                .json(&body);
+            if let Some(session) = session {
+                request = request.header("mcp-session-id", session);
+            }
+            request.send()
+        };
+        let response = post(
+            None,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "clientInfo": {"name": "test", "version": "1"},
+            }}),
+        )
+        .await
+        .unwrap();
+        let session = response.headers()["mcp-session-id"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        response.text().await.unwrap();
+        post(
+            Some(&session),
+            json!({"jsonrpc": "2.0", "method": "notifications/initialized"}),
+        )
+        .await
+        .unwrap();
+
+        // 收到第一个进度通知后断开连接
+        let response = post(
+            Some(&session),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "tools/call", "params": {
+                "name": "slow",
+                "_meta": {"progressToken": "p"},
+            }}),
+        )
+        .await
+        .unwrap();
+        let mut body = response.bytes_stream();
+        let mut received = String::new();
+        while parse_events(&received).is_empty() {
+            received.push_str(std::str::from_utf8(&body.next().await.unwrap().unwrap()).unwrap());
+        }
+        drop(body);
+        let (last_event_id, first) = parse_events(&received).remove(0);
+        assert_eq!(first["params"]["progress"], 1.0);
+
+        // 工具在断开期间继续执行, 重连后补发剩余的进度通知与结果
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let resumed = http
+            .get(&url)
+            .header("accept", "text/event-stream")
+            .header("mcp-session-id", &session)
+            .header(LAST_EVENT_ID, &last_event_id)
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let events = parse_events(&resumed);
+        let progress: Vec<_> = events
+            .iter()
+            .filter_map(|(_, data)| data["params"]["progress"].as_f64())
+            .collect();
+        assert_eq!(progress, [2.0, 3.0, 4.0, 5.0]);
+        let (_, result) = events.last().unwrap();
+        assert_eq!(result["id"], 2);
+        assert_eq!(result["result"]["content"][0]["text"], "done");
+
+        // 事件 ID 不存在时无法续传
+        let response = http
+            .get(&url)
+            .header("accept", "text/event-stream")
+            .header("mcp-session-id", &session)
+            .header(LAST_EVENT_ID, "99/0")
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_server_error());
+    }
+}
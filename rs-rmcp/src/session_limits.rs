@@ -0,0 +1,463 @@
+//! streamable HTTP、WebSocket 与 Unix 套接字 (`jsonrpc`) 会话的过期与数量限制
+//!
+//! - 超过 `idle_timeout_secs` 没有 JSON-RPC 消息, 或存在超过 `max_lifetime_secs` 的会话被关闭
+//! - 新建会话前检查全局与每个调用方身份的会话数上限
+//! - 使用已过期或被强制关闭的 `Mcp-Session-Id` 的请求返回 404 与说明原因的 JSON-RPC 错误,
+//!   客户端应重新 initialize; 未知或已被客户端删除的会话同样返回 404
+//! - 缺少 `Mcp-Session-Id` 的请求不是 initialize 时返回 400
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Json,
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderValue, Method, StatusCode, header::RETRY_AFTER},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use rmcp::model::{ConstString, ErrorCode, InitializeResultMethod};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{identity::CallerIdentity, metrics::SessionTransport, state::AppState};
+
+/// 会话已过期或被关闭
+pub const SESSION_EXPIRED: ErrorCode = ErrorCode(-32001);
+/// 会话数达到上限
+pub const TOO_MANY_SESSIONS: ErrorCode = ErrorCode(-32003);
+
+/// 过期检查间隔
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5);
+/// 已过期会话 ID 的保留时间, 之后按未知会话处理
+const EXPIRED_RETENTION: Duration = Duration::from_secs(3600);
+/// 保留的已过期会话 ID 上限
+const MAX_EXPIRED: usize = 10_000;
+
+/// 会话限制配置, 对应配置文件的 `[sessions]` 表, 取值为 0 表示不限制
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionLimitsConfig {
+    /// 空闲超时 (秒)
+    pub idle_timeout_secs: u64,
+    /// 最长存活时间 (秒)
+    pub max_lifetime_secs: u64,
+    /// 最大并发会话数
+    pub max_sessions: usize,
+    /// 每个调用方身份的最大并发会话数
+    pub max_sessions_per_identity: usize,
+}
+
+impl Default for SessionLimitsConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: 1800,
+            max_lifetime_secs: 0,
+            max_sessions: 0,
+            max_sessions_per_identity: 0,
+        }
+    }
+}
+
+impl SessionLimitsConfig {
+    fn limit<T: PartialEq + Default>(value: T) -> Option<T> {
+        (value != T::default()).then_some(value)
+    }
+
+    fn idle_timeout(&self) -> Option<Duration> {
+        Self::limit(self.idle_timeout_secs).map(Duration::from_secs)
+    }
+
+    fn max_lifetime(&self) -> Option<Duration> {
+        Self::limit(self.max_lifetime_secs).map(Duration::from_secs)
+    }
+}
+
+/// 会话结束原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    IdleTimeout(Duration),
+    MaxLifetime(Duration),
+    Admin,
+}
+
+impl std::fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CloseReason::IdleTimeout(d) => write!(f, "idle for more than {}s", d.as_secs()),
+            CloseReason::MaxLifetime(d) => {
+                write!(f, "exceeded the maximum lifetime of {}s", d.as_secs())
+            }
+            CloseReason::Admin => f.write_str("closed by an administrator"),
+        }
+    }
+}
+
+/// 会话限制状态
+#[derive(Debug)]
+pub struct SessionLimits {
+    config: SessionLimitsConfig,
+    /// 已关闭的会话 ID 与原因
+    closed: Mutex<HashMap<String, (Instant, CloseReason)>>,
+    /// 串行化新建会话, 保证计数与创建之间没有竞争
+    pub admission: tokio::sync::Mutex<()>,
+}
+
+impl SessionLimits {
+    pub fn new(config: SessionLimitsConfig) -> Self {
+        Self {
+            config,
+            closed: Mutex::default(),
+            admission: tokio::sync::Mutex::default(),
+        }
+    }
+
+    /// 记录会话关闭原因
+    pub fn record_closed(&self, id: &str, reason: CloseReason) {
+        let now = Instant::now();
+        let mut closed = self.closed.lock().unwrap();
+        if closed.len() >= MAX_EXPIRED {
+            closed.retain(|_, (at, _)| now.duration_since(*at) < EXPIRED_RETENTION);
+        }
+        if closed.len() < MAX_EXPIRED {
+            closed.insert(id.to_string(), (now, reason));
+        }
+    }
+
+    pub fn closed_reason(&self, id: &str) -> Option<CloseReason> {
+        let closed = self.closed.lock().unwrap();
+        let (at, reason) = closed.get(id)?;
+        (at.elapsed() < EXPIRED_RETENTION).then_some(*reason)
+    }
+
+    /// 需要过期关闭的会话
+    fn expired(&self, state: &AppState) -> Vec<(String, CloseReason)> {
+        let now = Utc::now();
+        let elapsed = |since: chrono::DateTime<Utc>| (now - since).to_std().unwrap_or_default();
+        state
+            .sessions
+            .list()
+            .into_iter()
+            .filter(|entry| limited(entry.transport))
+            .filter_map(|entry| {
+                let id = entry.id()?;
+                let summary = entry.summary();
+                if let Some(max) = self.config.max_lifetime()
+                    && elapsed(summary.created_at) > max
+                {
+                    return Some((id, CloseReason::MaxLifetime(max)));
+                }
+                if let Some(idle) = self.config.idle_timeout()
+                    && summary.in_flight == 0
+                    && elapsed(summary.last_activity) > idle
+                {
+                    return Some((id, CloseReason::IdleTimeout(idle)));
+                }
+                None
+            })
+            .collect()
+    }
+
+    /// 新建会话前检查上限
+    pub fn admit(
+        &self,
+        state: &AppState,
+        identity: Option<&CallerIdentity>,
+    ) -> Result<(), TooManySessions> {
+        let sessions: Vec<_> = state
+            .sessions
+            .list()
+            .into_iter()
+            .filter(|entry| limited(entry.transport))
+            .collect();
+        if let Some(max) = SessionLimitsConfig::limit(self.config.max_sessions)
+            && sessions.len() >= max
+        {
+            return Err(TooManySessions {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                message: format!("too many sessions: limit is {max}"),
+            });
+        }
+        if let Some(max) = SessionLimitsConfig::limit(self.config.max_sessions_per_identity)
+            && let Some(identity) = identity
+            && sessions
+                .iter()
+                .filter(|entry| entry.summary().identity.as_ref() == Some(&identity.0))
+                .count()
+                >= max
+        {
+            return Err(TooManySessions {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                message: format!("too many sessions for {identity}: limit is {max}"),
+            });
+        }
+        Ok(())
+    }
+
+    fn limits_sessions(&self) -> bool {
+        self.config.max_sessions > 0 || self.config.max_sessions_per_identity > 0
+    }
+}
+
+/// 受过期与数量限制的传输
+fn limited(transport: SessionTransport) -> bool {
+    matches!(
+        transport,
+        SessionTransport::StreamableHttp | SessionTransport::Websocket | SessionTransport::Unix
+    )
+}
+
+/// 启动后台过期检查任务
+pub fn spawn_sweeper(state: Arc<AppState>) {
+    let limits = &state.session_limits.config;
+    if limits.idle_timeout().is_none() && limits.max_lifetime().is_none() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            sweep(&state).await;
+        }
+    });
+}
+
+/// 关闭所有需要过期的会话
+async fn sweep(state: &AppState) {
+    for (id, reason) in state.session_limits.expired(state) {
+        tracing::info!(session = id, %reason, "session expired");
+        state.close_session(&id, reason).await;
+    }
+}
+
+pub fn error_body(code: ErrorCode, message: String) -> Json<serde_json::Value> {
+    Json(json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": { "code": code.0, "message": message },
+    }))
+}
+
+/// 会话数达到上限, 全局上限返回 503, 身份上限返回 429
+#[derive(Debug)]
+pub struct TooManySessions {
+    status: StatusCode,
+    message: String,
+}
+
+impl std::fmt::Display for TooManySessions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl IntoResponse for TooManySessions {
+    fn into_response(self) -> Response {
+        let mut response =
+            (self.status, error_body(TOO_MANY_SESSIONS, self.message)).into_response();
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from_static("30"));
+        response
+    }
+}
+
+/// streamable HTTP 中间件: 校验会话头, 新建会话前检查上限
+///
+/// 有状态模式下 rmcp 对缺少会话头返回 401 或 422、对未知会话返回 401, 这里按规范返回
+/// 400 与 404。
+pub async fn enforce(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let limits = &state.session_limits;
+    let stateful = state.config.http.stateful_mode;
+    if let Some(id) = req
+        .headers()
+        .get("mcp-session-id")
+        .and_then(|v| v.to_str().ok())
+    {
+        if let Some(reason) = limits.closed_reason(id) {
+            let message = format!("session {id} expired: {reason}; send a new initialize request");
+            return (StatusCode::NOT_FOUND, error_body(SESSION_EXPIRED, message)).into_response();
+        }
+        if stateful && !state.http_sessions.is_local(id).await {
+            let message = format!("session {id} not found; send a new initialize request");
+            return (StatusCode::NOT_FOUND, error_body(SESSION_EXPIRED, message)).into_response();
+        }
+        return next.run(req).await;
+    }
+
+    // 没有会话 ID 的请求只能是 initialize
+    let req = match stateful {
+        true => match require_initialize(req).await {
+            Ok(req) => req,
+            Err(response) => return response,
+        },
+        false if req.method() == Method::POST => req,
+        false => return next.run(req).await,
+    };
+    if !limits.limits_sessions() {
+        return next.run(req).await;
+    }
+    let _admission = limits.admission.lock().await;
+    if let Err(e) = limits.admit(&state, req.extensions().get::<CallerIdentity>()) {
+        return e.into_response();
+    }
+    next.run(req).await
+}
+
+/// 缺少会话头的请求不是 initialize 时返回 400
+async fn require_initialize(req: Request) -> Result<Request, Response> {
+    let missing = || {
+        let message = "missing Mcp-Session-Id header: send an initialize request first".to_string();
+        (
+            StatusCode::BAD_REQUEST,
+            error_body(ErrorCode::INVALID_REQUEST, message),
+        )
+            .into_response()
+    };
+    if req.method() != Method::POST {
+        return Err(missing());
+    }
+    let (parts, body) = req.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())?;
+    let is_initialize = serde_json::from_slice::<serde_json::Value>(&bytes)
+        .is_ok_and(|message| message["method"] == InitializeResultMethod::VALUE);
+    match is_initialize {
+        true => Ok(Request::from_parts(parts, Body::from(bytes))),
+        false => Err(missing()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rmcp::transport::streamable_http_server::{
+        StreamableHttpServerConfig, StreamableHttpService,
+    };
+
+    use crate::{calculator, config::Config};
+
+    use super::*;
+
+    #[test]
+    fn test_expired_and_admit() {
+        let config = Config {
+            sessions: SessionLimitsConfig {
+                idle_timeout_secs: 60,
+                max_lifetime_secs: 0,
+                max_sessions: 2,
+                max_sessions_per_identity: 1,
+            },
+            ..Default::default()
+        };
+        let state = AppState::new(config).unwrap();
+        let limits = &state.session_limits;
+        let alice = CallerIdentity("alice".to_string());
+
+        let first = state.sessions.register(SessionTransport::StreamableHttp);
+        first.touch(Some("s1"), Some("alice"));
+        let _sse = state.sessions.register(SessionTransport::Sse);
+        assert!(limits.admit(&state, None).is_ok());
+        let e = limits.admit(&state, Some(&alice)).unwrap_err();
+        assert_eq!(e.status, StatusCode::TOO_MANY_REQUESTS);
+
+        let second = state.sessions.register(SessionTransport::StreamableHttp);
+        second.touch(Some("s2"), None);
+        let e = limits.admit(&state, None).unwrap_err();
+        assert_eq!(e.status, StatusCode::SERVICE_UNAVAILABLE);
+
+        assert!(limits.expired(&state).is_empty());
+        first.set_last_activity(Utc::now() - chrono::Duration::seconds(61));
+        assert_eq!(
+            limits.expired(&state),
+            [(
+                "s1".to_string(),
+                CloseReason::IdleTimeout(Duration::from_secs(60))
+            )]
+        );
+
+        limits.record_closed("s1", CloseReason::Admin);
+        assert_eq!(limits.closed_reason("s1"), Some(CloseReason::Admin));
+        assert_eq!(limits.closed_reason("s2"), None);
+    }
+
+    #[tokio::test]
+    async fn test_initialize_only_session_expires() {
+        let config = Config {
+            sessions: SessionLimitsConfig {
+                idle_timeout_secs: 60,
+                max_sessions: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = Arc::new(AppState::new(config).unwrap());
+        let service_state = state.clone();
+        let service = StreamableHttpService::new(
+            move || {
+                Ok(calculator::session_service(
+                    service_state.clone(),
+                    SessionTransport::StreamableHttp,
+                ))
+            },
+            state.http_sessions.clone(),
+            StreamableHttpServerConfig::default(),
+        );
+        let app = axum::Router::new()
+            .nest_service("/mcp", service)
+            .layer(axum::middleware::from_fn_with_state(state.clone(), enforce));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let http = reqwest::Client::new();
+        let initialize = || {
+            http.post(&url)
+                .header("accept", "application/json, text/event-stream")
+                .json(
+                    &json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {
+                        "protocolVersion": "2025-03-26",
+                        "capabilities": {},
+                        "clientInfo": {"name": "test", "version": "1"},
+                    }}),
+                )
+                .send()
+        };
+
+        // 只发送 initialize, 不再发送任何请求
+        let response = initialize().await.unwrap();
+        let session = response.headers()["mcp-session-id"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        response.text().await.unwrap();
+        let entry = state
+            .sessions
+            .get(&session)
+            .expect("id recorded on initialize");
+        let response = initialize().await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        entry.set_last_activity(Utc::now() - chrono::Duration::seconds(61));
+        drop(entry);
+        sweep(&state).await;
+        assert_eq!(
+            state.session_limits.closed_reason(&session),
+            Some(CloseReason::IdleTimeout(Duration::from_secs(60)))
+        );
+
+        // 会话结束后释放名额
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !state.sessions.list().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(initialize().await.unwrap().status().is_success());
+    }
+}
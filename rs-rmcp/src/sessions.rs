@@ -0,0 +1,522 @@
+//! 会话登记
+//!
+//! rmcp 的 `LocalSessionManager` 与 `SseServer` 不对外暴露会话, 这里由
+//! [`crate::instrument::Instrumented`] 为每个会话登记客户端信息、活动时间、
+//! 执行中的请求、资源订阅与日志级别, 供管理接口查看与强制关闭, 网关据此把下游的
+//! 资源更新与日志通知只发给需要的会话。
+//!
+//! streamable HTTP 的会话 ID 在 initialize 时由 [`crate::http_sessions::HttpSessionManager`] 补全,
+//! 其他使用 `LocalSessionManager` 的服务在收到后续请求或通知时补全。
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::header::CONTENT_TYPE,
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use futures::{
+    StreamExt,
+    channel::mpsc::{UnboundedSender, unbounded},
+};
+use rmcp::{
+    Peer, RoleServer,
+    model::{ClientCapabilities, Implementation, InitializeRequestParam, LoggingLevel, RequestId},
+};
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    client_profile::{ClientProfile, Quirk},
+    metrics::SessionTransport,
+    state::AppState,
+};
+
+/// 所有会话
+#[derive(Debug, Default)]
+pub struct Sessions {
+    next_key: AtomicU64,
+    entries: Mutex<HashMap<u64, Arc<SessionEntry>>>,
+    /// SSE 会话 ID 到其 GET 响应流
+    sse_streams: Mutex<HashMap<String, SseStream>>,
+}
+
+/// SSE 会话的 GET 响应流
+#[derive(Debug)]
+struct SseStream {
+    close: CancellationToken,
+    /// 不经过 rmcp 直接写入流的事件
+    replies: UnboundedSender<Bytes>,
+}
+
+impl Sessions {
+    /// 登记新会话, 返回值释放时注销
+    pub fn register(self: &Arc<Self>, transport: SessionTransport) -> SessionHandle {
+        let key = self.next_key.fetch_add(1, Ordering::Relaxed);
+        let now = Utc::now();
+        let entry = Arc::new(SessionEntry {
+            id: RwLock::new(None),
+            transport,
+            created_at: now,
+            last_activity: Mutex::new(now),
+            identity: RwLock::new(None),
+            client: RwLock::new(None),
+            profile: RwLock::new(None),
+            in_flight: Mutex::new(BTreeMap::new()),
+            subscriptions: Mutex::new(BTreeSet::new()),
+            log_level: RwLock::new(None),
+            peer: RwLock::new(None),
+            close: CancellationToken::new(),
+        });
+        self.entries.lock().unwrap().insert(key, entry.clone());
+        SessionHandle {
+            sessions: self.clone(),
+            key,
+            entry,
+        }
+    }
+
+    /// 所有会话, 按创建时间排序
+    pub fn list(&self) -> Vec<Arc<SessionEntry>> {
+        let mut entries: Vec<_> = self.entries.lock().unwrap().values().cloned().collect();
+        entries.sort_by_key(|entry| entry.created_at);
+        entries
+    }
+
+    /// 按会话 ID 查找
+    pub fn get(&self, id: &str) -> Option<Arc<SessionEntry>> {
+        self.entries
+            .lock()
+            .unwrap()
+            .values()
+            .find(|entry| entry.id().as_deref() == Some(id))
+            .cloned()
+    }
+
+    /// 订阅了资源的会话的客户端
+    pub fn subscribers(&self, uri: &str) -> Vec<Peer<RoleServer>> {
+        self.peers_where(|entry| entry.subscriptions.lock().unwrap().contains(uri))
+    }
+
+    /// 通过 `logging/setLevel` 设置的级别允许 `level` 的会话的客户端
+    pub fn log_receivers(&self, level: LoggingLevel) -> Vec<Peer<RoleServer>> {
+        self.peers_where(|entry| {
+            entry
+                .log_level
+                .read()
+                .unwrap()
+                .is_some_and(|min| level as u8 >= min as u8)
+        })
+    }
+
+    /// 接收工具、提示词与资源 list_changed 通知的会话的客户端
+    pub fn list_changed_peers(&self) -> Vec<Peer<RoleServer>> {
+        self.peers_where(|entry| {
+            entry
+                .profile
+                .read()
+                .unwrap()
+                .as_ref()
+                .is_some_and(ClientProfile::wants_list_changed)
+        })
+    }
+
+    fn peers_where(&self, filter: impl Fn(&SessionEntry) -> bool) -> Vec<Peer<RoleServer>> {
+        self.entries
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|entry| filter(entry))
+            .filter_map(|entry| entry.peer.read().unwrap().clone())
+            .filter(|peer| !peer.is_transport_closed())
+            .collect()
+    }
+
+    /// 结束 SSE 会话的 GET 响应流
+    pub fn close_sse_stream(&self, id: &str) {
+        if let Some(stream) = self.sse_streams.lock().unwrap().remove(id) {
+            stream.close.cancel();
+        }
+    }
+
+    /// 把 rmcp 无法处理的请求的响应写入 SSE 会话的 GET 响应流, 会话不存在时返回 `false`
+    pub fn reply_sse(&self, id: &str, message: &serde_json::Value) -> bool {
+        let event = format!("event: message\ndata: {message}\n\n");
+        self.sse_streams
+            .lock()
+            .unwrap()
+            .get(id)
+            .is_some_and(|stream| stream.replies.unbounded_send(Bytes::from(event)).is_ok())
+    }
+}
+
+/// 强制关闭 SSE 会话时结束对应的 GET 响应流, 并允许向流中写入 rmcp 之外的响应
+///
+/// rmcp 的 SSE 响应流在客户端断开前不会结束, 会话 ID 取自首个 `endpoint` 事件。
+pub async fn closable_sse_stream(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(req).await;
+    let is_sse = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if !is_sse {
+        return response;
+    }
+
+    let token = CancellationToken::new();
+    let (replies, replies_rx) = unbounded();
+    let mut registration = SseStreamRegistration {
+        sessions: state.sessions.clone(),
+        id: None,
+    };
+    let (parts, body) = response.into_parts();
+    let events = body.into_data_stream().map({
+        let token = token.clone();
+        move |chunk| {
+            if registration.id.is_none()
+                && let Ok(bytes) = &chunk
+                && let Some(id) = endpoint_session_id(bytes)
+            {
+                registration.register(
+                    id,
+                    SseStream {
+                        close: token.clone(),
+                        replies: replies.clone(),
+                    },
+                );
+            }
+            chunk
+        }
+    });
+    // rmcp 的流结束时一并结束
+    let events = events
+        .map(Some)
+        .chain(futures::stream::once(async { None }));
+    let replies = replies_rx.map(|reply| Some(Ok(reply)));
+    let stream = futures::stream::select(events, replies)
+        .take_while(|chunk| std::future::ready(chunk.is_some()))
+        .filter_map(std::future::ready)
+        .take_until(token.cancelled_owned());
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+/// `data: /message?sessionId=<id>`
+pub fn endpoint_session_id(chunk: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(chunk).ok()?;
+    let (_, rest) = text.split_once("sessionId=")?;
+    let id: String = rest
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    (!id.is_empty()).then_some(id)
+}
+
+/// 响应流释放时注销
+struct SseStreamRegistration {
+    sessions: Arc<Sessions>,
+    id: Option<String>,
+}
+
+impl SseStreamRegistration {
+    fn register(&mut self, id: String, stream: SseStream) {
+        self.sessions
+            .sse_streams
+            .lock()
+            .unwrap()
+            .insert(id.clone(), stream);
+        self.id = Some(id);
+    }
+}
+
+impl Drop for SseStreamRegistration {
+    fn drop(&mut self) {
+        if let Some(id) = &self.id {
+            self.sessions.sse_streams.lock().unwrap().remove(id);
+        }
+    }
+}
+
+/// 会话登记的所有权, 释放时注销会话
+#[derive(Debug)]
+pub struct SessionHandle {
+    sessions: Arc<Sessions>,
+    key: u64,
+    entry: Arc<SessionEntry>,
+}
+
+impl std::ops::Deref for SessionHandle {
+    type Target = SessionEntry;
+
+    fn deref(&self) -> &SessionEntry {
+        &self.entry
+    }
+}
+
+impl Drop for SessionHandle {
+    fn drop(&mut self) {
+        self.sessions.entries.lock().unwrap().remove(&self.key);
+    }
+}
+
+/// 单个会话的状态
+#[derive(Debug)]
+pub struct SessionEntry {
+    id: RwLock<Option<String>>,
+    pub transport: SessionTransport,
+    pub created_at: DateTime<Utc>,
+    last_activity: Mutex<DateTime<Utc>>,
+    identity: RwLock<Option<String>>,
+    client: RwLock<Option<InitializeRequestParam>>,
+    profile: RwLock<Option<ClientProfile>>,
+    in_flight: Mutex<BTreeMap<String, InFlight>>,
+    subscriptions: Mutex<BTreeSet<String>>,
+    log_level: RwLock<Option<LoggingLevel>>,
+    /// initialized 通知后可用于推送通知的客户端
+    peer: RwLock<Option<Peer<RoleServer>>>,
+    /// 取消时关闭会话
+    close: CancellationToken,
+}
+
+#[derive(Debug)]
+struct InFlight {
+    method: &'static str,
+    started_at: DateTime<Utc>,
+    ct: CancellationToken,
+}
+
+impl SessionEntry {
+    pub fn id(&self) -> Option<String> {
+        self.id.read().unwrap().clone()
+    }
+
+    /// 记录一次活动, 同时补全会话 ID 与调用方身份
+    pub fn touch(&self, id: Option<&str>, identity: Option<&str>) {
+        *self.last_activity.lock().unwrap() = Utc::now();
+        for (slot, value) in [(&self.id, id), (&self.identity, identity)] {
+            if let Some(value) = value
+                && slot.read().unwrap().is_none()
+            {
+                *slot.write().unwrap() = Some(value.to_string());
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub fn set_last_activity(&self, at: DateTime<Utc>) {
+        *self.last_activity.lock().unwrap() = at;
+    }
+
+    pub fn set_client(&self, client: InitializeRequestParam) {
+        *self.client.write().unwrap() = Some(client);
+    }
+
+    pub fn set_profile(&self, profile: ClientProfile) {
+        *self.profile.write().unwrap() = Some(profile);
+    }
+
+    /// initialize 成功后生成的客户端画像
+    pub fn profile(&self) -> Option<ClientProfile> {
+        self.profile.read().unwrap().clone()
+    }
+
+    /// 登记执行中的请求, 返回值释放时移除
+    pub fn begin_request(
+        &self,
+        id: &RequestId,
+        method: &'static str,
+        ct: CancellationToken,
+    ) -> InFlightGuard<'_> {
+        let id = id.to_string();
+        self.in_flight.lock().unwrap().insert(
+            id.clone(),
+            InFlight {
+                method,
+                started_at: Utc::now(),
+                ct,
+            },
+        );
+        InFlightGuard { entry: self, id }
+    }
+
+    /// 取消执行中的请求, 请求不存在时返回 `false`
+    pub fn cancel_request(&self, id: &str) -> bool {
+        match self.in_flight.lock().unwrap().get(id) {
+            Some(request) => {
+                request.ct.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn subscribe(&self, uri: &str) {
+        self.subscriptions.lock().unwrap().insert(uri.to_string());
+    }
+
+    pub fn unsubscribe(&self, uri: &str) {
+        self.subscriptions.lock().unwrap().remove(uri);
+    }
+
+    pub fn set_log_level(&self, level: LoggingLevel) {
+        *self.log_level.write().unwrap() = Some(level);
+    }
+
+    pub fn set_peer(&self, peer: Peer<RoleServer>) {
+        *self.peer.write().unwrap() = Some(peer);
+    }
+
+    /// 已收到客户端的 initialized 通知
+    #[cfg(test)]
+    pub fn is_initialized(&self) -> bool {
+        self.peer.read().unwrap().is_some()
+    }
+
+    /// 会话关闭信号, SSE 与 stdio 传输以此作为服务的取消令牌
+    pub fn close_token(&self) -> CancellationToken {
+        self.close.clone()
+    }
+
+    pub fn close(&self) {
+        self.close.cancel();
+    }
+
+    pub fn summary(&self) -> SessionSummary {
+        let client = self.client.read().unwrap();
+        SessionSummary {
+            id: self.id(),
+            transport: self.transport.as_str().to_string(),
+            identity: self.identity.read().unwrap().clone(),
+            client_info: client.as_ref().map(|c| c.client_info.clone()),
+            // 协商完成前为客户端请求的版本
+            protocol_version: self
+                .profile()
+                .map(|profile| profile.negotiated.version)
+                .or_else(|| client.as_ref().map(|c| c.protocol_version.clone()))
+                .map(|version| version.to_string()),
+            created_at: self.created_at,
+            last_activity: *self.last_activity.lock().unwrap(),
+            in_flight: self.in_flight.lock().unwrap().len(),
+        }
+    }
+
+    pub fn detail(&self) -> SessionDetail {
+        let now = Utc::now();
+        SessionDetail {
+            summary: self.summary(),
+            client_capabilities: self
+                .client
+                .read()
+                .unwrap()
+                .as_ref()
+                .map(|c| c.capabilities.clone()),
+            quirks: self
+                .profile()
+                .map(|profile| profile.quirks)
+                .unwrap_or_default(),
+            requests: self
+                .in_flight
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(id, request)| InFlightRequest {
+                    request_id: id.clone(),
+                    method: request.method.to_string(),
+                    started_at: request.started_at,
+                    elapsed_ms: (now - request.started_at).num_milliseconds(),
+                })
+                .collect(),
+            subscriptions: self.subscriptions.lock().unwrap().iter().cloned().collect(),
+        }
+    }
+}
+
+/// 执行中请求的登记, 释放时移除
+#[derive(Debug)]
+pub struct InFlightGuard<'a> {
+    entry: &'a SessionEntry,
+    id: String,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.entry.in_flight.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// 会话列表项
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub id: Option<String>,
+    pub transport: String,
+    pub identity: Option<String>,
+    pub client_info: Option<Implementation>,
+    pub protocol_version: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_activity: DateTime<Utc>,
+    /// 执行中的请求数
+    pub in_flight: usize,
+}
+
+/// 会话详情
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionDetail {
+    #[serde(flatten)]
+    pub summary: SessionSummary,
+    pub client_capabilities: Option<ClientCapabilities>,
+    /// 按 `[[clients.quirks]]` 识别的兼容问题
+    pub quirks: Vec<Quirk>,
+    pub requests: Vec<InFlightRequest>,
+    /// 已订阅的资源 URI
+    pub subscriptions: Vec<String>,
+}
+
+/// 执行中的请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InFlightRequest {
+    pub request_id: String,
+    pub method: String,
+    pub started_at: DateTime<Utc>,
+    pub elapsed_ms: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_and_cancel() {
+        let sessions = Arc::new(Sessions::default());
+        let handle = sessions.register(SessionTransport::StreamableHttp);
+        assert!(sessions.get("s1").is_none());
+        handle.touch(Some("s1"), Some("alice"));
+        handle.touch(Some("ignored"), None);
+
+        let entry = sessions.get("s1").unwrap();
+        assert_eq!(entry.summary().identity.as_deref(), Some("alice"));
+
+        let ct = CancellationToken::new();
+        let guard = entry.begin_request(&RequestId::Number(7), "tools/call", ct.clone());
+        assert_eq!(entry.detail().requests[0].request_id, "7");
+        assert!(!entry.cancel_request("8"));
+        assert!(entry.cancel_request("7"));
+        assert!(ct.is_cancelled());
+        drop(guard);
+        assert_eq!(entry.summary().in_flight, 0);
+
+        drop(handle);
+        assert!(sessions.list().is_empty());
+    }
+}
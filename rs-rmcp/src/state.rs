@@ -0,0 +1,98 @@
+//! 服务共享状态
+
+use std::sync::{Arc, RwLock};
+
+use rmcp::transport::streamable_http_server::SessionManager;
+
+use crate::{
+    audit::Auditor,
+    config::Config,
+    error::Error,
+    gateway::Gateway,
+    health::Health,
+    http_sessions::HttpSessionManager,
+    metrics::{Metrics, SessionTransport},
+    ratelimit::RateLimiter,
+    recording::Recorder,
+    registry::{Registry, RegistryChanges},
+    resumable::SseEventLogs,
+    session_limits::{CloseReason, SessionLimits},
+    sessions::Sessions,
+};
+
+/// 所有会话共享的状态, 每个会话的 [`crate::calculator::Calculator`] 持有同一份
+#[derive(Debug)]
+pub struct AppState {
+    /// 启动时的配置, 热加载只替换注册表
+    pub config: Config,
+    pub limiter: RateLimiter,
+    pub metrics: Metrics,
+    pub audit: Auditor,
+    pub health: Health,
+    pub sessions: Arc<Sessions>,
+    pub session_limits: SessionLimits,
+    /// streamable HTTP 会话, 强制关闭与多副本转发时使用
+    pub http_sessions: Arc<HttpSessionManager>,
+    /// SSE 会话的事件日志, 用于断线续传
+    pub sse_logs: SseEventLogs,
+    /// 下游 MCP 服务
+    pub gateway: Gateway,
+    /// 各传输共用的消息录制
+    pub recorder: Recorder,
+    registry: RwLock<Arc<Registry>>,
+}
+
+impl AppState {
+    pub fn new(config: Config) -> Result<Self, Error> {
+        let recorder = Recorder::new(&config.record)?;
+        Ok(Self {
+            limiter: RateLimiter::new(config.rate_limit.clone()),
+            metrics: Metrics::new(),
+            audit: Auditor::new(&config.audit)?,
+            health: Health::default(),
+            sessions: Arc::default(),
+            session_limits: SessionLimits::new(config.sessions.clone()),
+            http_sessions: Arc::new(HttpSessionManager::new(&config, recorder.clone())?),
+            sse_logs: SseEventLogs::default(),
+            gateway: Gateway::new(&config.gateway),
+            recorder,
+            registry: RwLock::new(Arc::new(Registry::new(&config))),
+            config,
+        })
+    }
+
+    /// 当前注册表快照
+    pub fn registry(&self) -> Arc<Registry> {
+        self.registry.read().unwrap().clone()
+    }
+
+    /// 原子替换注册表, 返回发生变化的列表
+    pub fn replace_registry(&self, registry: Registry) -> RegistryChanges {
+        let mut current = self.registry.write().unwrap();
+        let changes = registry.changes(&current);
+        *current = Arc::new(registry);
+        changes
+    }
+
+    /// 强制关闭会话, 会话不存在时返回 `false`
+    pub async fn close_session(&self, id: &str, reason: CloseReason) -> bool {
+        let Some(entry) = self.sessions.get(id) else {
+            return false;
+        };
+        self.session_limits.record_closed(id, reason);
+        entry.close();
+        match entry.transport {
+            SessionTransport::StreamableHttp => {
+                if let Err(e) = self.http_sessions.close_session(&id.into()).await {
+                    tracing::error!("failed to close session {id}: {e}");
+                }
+            }
+            SessionTransport::Sse => {
+                self.sessions.close_sse_stream(id);
+                self.sse_logs.close(id);
+            }
+            SessionTransport::Stdio | SessionTransport::Websocket | SessionTransport::Unix => {}
+        }
+        true
+    }
+}
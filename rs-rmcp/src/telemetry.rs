@@ -0,0 +1,279 @@
+//! OpenTelemetry 链路追踪
+//!
+//! - 每个 JSON-RPC 请求一个 span, 见 [`crate::instrument::Instrumented`]
+//! - 导出到 OTLP (HTTP) 采集器, 或以 JSON Lines 写入文件便于测试
+//! - 从 MCP `_meta` 或 HTTP 头提取 W3C `traceparent`, 与调用方链路关联
+use std::{
+    fs::File,
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use opentelemetry::{
+    Context, KeyValue,
+    propagation::{Extractor, TextMapPropagator},
+    trace::{Status, TraceContextExt, TracerProvider},
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    Resource,
+    error::{OTelSdkError, OTelSdkResult},
+    propagation::TraceContextPropagator,
+    trace::{SdkTracer, SdkTracerProvider, SpanData, SpanExporter},
+};
+use rmcp::model::{Extensions, Meta};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing_opentelemetry::OpenTelemetryLayer;
+
+use crate::error::Error;
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+
+/// 链路导出方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceExporter {
+    /// 不导出
+    #[default]
+    None,
+    /// OTLP HTTP 采集器
+    Otlp,
+    /// JSON Lines 文件
+    File,
+}
+
+/// 链路追踪配置, 对应配置文件的 `[telemetry]` 表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    pub exporter: TraceExporter,
+    /// OTLP traces 端点
+    pub otlp_endpoint: String,
+    /// 文件导出路径
+    pub file: PathBuf,
+    pub service_name: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            exporter: TraceExporter::None,
+            otlp_endpoint: "http://localhost:4318/v1/traces".to_string(),
+            file: PathBuf::from("traces.jsonl"),
+            service_name: env!("CARGO_PKG_NAME").to_string(),
+        }
+    }
+}
+
+/// 根据配置创建 TracerProvider, 未启用导出时返回 `None`
+///
+/// 退出前需调用 [`SdkTracerProvider::shutdown`] 刷新未导出的 span。
+pub fn init(config: &TelemetryConfig) -> Result<Option<SdkTracerProvider>, Error> {
+    let builder = SdkTracerProvider::builder().with_resource(
+        Resource::builder()
+            .with_service_name(config.service_name.clone())
+            .build(),
+    );
+    let provider = match config.exporter {
+        TraceExporter::None => return Ok(None),
+        TraceExporter::Otlp => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .with_endpoint(&config.otlp_endpoint)
+                .build()?;
+            builder.with_batch_exporter(exporter).build()
+        }
+        TraceExporter::File => builder
+            .with_simple_exporter(FileExporter::create(&config.file)?)
+            .build(),
+    };
+    Ok(Some(provider))
+}
+
+/// tracing 到 OpenTelemetry 的桥接层
+pub fn layer<S>(provider: &SdkTracerProvider) -> OpenTelemetryLayer<S, SdkTracer>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+}
+
+/// W3C trace context 载体
+struct TraceHeaders<'a> {
+    traceparent: &'a str,
+    tracestate: Option<&'a str>,
+}
+
+impl Extractor for TraceHeaders<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        match key {
+            TRACEPARENT => Some(self.traceparent),
+            TRACESTATE => self.tracestate,
+            _ => None,
+        }
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        vec![TRACEPARENT, TRACESTATE]
+    }
+}
+
+/// 提取调用方的链路上下文, `_meta` 优先于 HTTP 头
+pub fn parent_context(meta: &Meta, extensions: &Extensions) -> Option<Context> {
+    let from_meta = meta
+        .0
+        .get(TRACEPARENT)
+        .and_then(Value::as_str)
+        .map(|tp| TraceHeaders {
+            traceparent: tp,
+            tracestate: meta.0.get(TRACESTATE).and_then(Value::as_str),
+        });
+    let headers = extensions
+        .get::<axum::http::request::Parts>()
+        .map(|parts| &parts.headers);
+    let from_headers = headers.and_then(|headers| {
+        Some(TraceHeaders {
+            traceparent: headers.get(TRACEPARENT)?.to_str().ok()?,
+            tracestate: headers.get(TRACESTATE).and_then(|v| v.to_str().ok()),
+        })
+    });
+
+    let carrier = from_meta.or(from_headers)?;
+    let context = TraceContextPropagator::new().extract(&carrier);
+    context.span().span_context().is_valid().then_some(context)
+}
+
+/// 以 JSON Lines 写入文件的导出器
+#[derive(Debug)]
+pub struct FileExporter {
+    file: Mutex<File>,
+}
+
+impl FileExporter {
+    pub fn create(path: &std::path::Path) -> Result<Self, Error> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl SpanExporter for FileExporter {
+    fn export(&self, batch: Vec<SpanData>) -> impl Future<Output = OTelSdkResult> + Send {
+        let mut lines = String::new();
+        for span in &batch {
+            lines.push_str(&span_to_json(span).to_string());
+            lines.push('\n');
+        }
+        let result = self
+            .file
+            .lock()
+            .unwrap()
+            .write_all(lines.as_bytes())
+            .map_err(|e| OTelSdkError::InternalFailure(e.to_string()));
+        std::future::ready(result)
+    }
+}
+
+fn span_to_json(span: &SpanData) -> Value {
+    let unix_nanos = |time: SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64)
+    };
+    let attributes: serde_json::Map<String, Value> = span
+        .attributes
+        .iter()
+        .map(|KeyValue { key, value, .. }| (key.to_string(), attribute_to_json(value)))
+        .collect();
+    let status = match &span.status {
+        Status::Unset => json!({ "code": "unset" }),
+        Status::Ok => json!({ "code": "ok" }),
+        Status::Error { description } => json!({ "code": "error", "message": description }),
+    };
+
+    json!({
+        "traceId": span.span_context.trace_id().to_string(),
+        "spanId": span.span_context.span_id().to_string(),
+        "parentSpanId": span.parent_span_id.to_string(),
+        "name": span.name,
+        "kind": format!("{:?}", span.span_kind),
+        "startTimeUnixNano": unix_nanos(span.start_time),
+        "endTimeUnixNano": unix_nanos(span.end_time),
+        "attributes": attributes,
+        "status": status,
+    })
+}
+
+fn attribute_to_json(value: &opentelemetry::Value) -> Value {
+    match value {
+        opentelemetry::Value::Bool(v) => json!(v),
+        opentelemetry::Value::I64(v) => json!(v),
+        opentelemetry::Value::F64(v) => json!(v),
+        value => json!(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    #[test]
+    fn test_parent_context_from_meta() {
+        let mut meta = Meta::new();
+        meta.0.insert(
+            TRACEPARENT.to_string(),
+            json!(format!("00-{TRACE_ID}-00f067aa0ba902b7-01")),
+        );
+        let context = parent_context(&meta, &Extensions::new()).unwrap();
+        assert_eq!(
+            context.span().span_context().trace_id().to_string(),
+            TRACE_ID
+        );
+
+        meta.0.insert(TRACEPARENT.to_string(), json!("garbage"));
+        assert!(parent_context(&meta, &Extensions::new()).is_none());
+        assert!(parent_context(&Meta::new(), &Extensions::new()).is_none());
+    }
+
+    #[test]
+    fn test_file_exporter() {
+        let dir = std::env::temp_dir().join(format!("rs-mcpr-trace-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = TelemetryConfig {
+            exporter: TraceExporter::File,
+            file: dir.join("traces.jsonl"),
+            ..Default::default()
+        };
+        let provider = init(&config).unwrap().unwrap();
+
+        let subscriber = tracing_subscriber::registry().with(layer(&provider));
+        tracing::subscriber::with_default(subscriber, || {
+            let mut meta = Meta::new();
+            meta.0.insert(
+                TRACEPARENT.to_string(),
+                json!(format!("00-{TRACE_ID}-00f067aa0ba902b7-01")),
+            );
+            let span = tracing::info_span!("mcp.request", rpc.method = "tools/call");
+            span.set_parent(parent_context(&meta, &Extensions::new()).unwrap());
+            let _entered = span.enter();
+        });
+        provider.shutdown().unwrap();
+
+        let text = std::fs::read_to_string(&config.file).unwrap();
+        let span: Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(span["traceId"], TRACE_ID);
+        assert_eq!(span["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(span["attributes"]["rpc.method"], "tools/call");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
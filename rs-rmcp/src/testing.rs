@@ -0,0 +1,381 @@
+//! 测试辅助
+//!
+//! [`Harness`] 在进程内启动任意 MCP 服务并连接一个已初始化的客户端, 提供工具调用、资源读取、
+//! 提示词渲染与通知断言等辅助方法, 失败时直接 panic。
+//!
+//! [`Fixture`] 选择传输: `Stdio` 使用与标准输入输出相同的按行 JSON 帧, 经内存管道连接;
+//! `Sse` 与 `StreamableHttp` 监听 `127.0.0.1` 的随机端口。同一组用例遍历 [`Fixture::ALL`]
+//! 即可在所有传输上运行。
+use std::{ops::Deref, sync::Arc, time::Duration};
+
+use rmcp::{
+    ErrorData as McpError, Peer, RoleClient, RoleServer, Service, ServiceError, ServiceExt,
+    model::{
+        CallToolRequestParam, CallToolResult, ClientInfo, GetPromptRequestParam, GetPromptResult,
+        InitializeResult, ReadResourceRequestParam, ReadResourceResult, ResourceContents,
+        ServerNotification,
+    },
+    transport::{
+        sse_server::{SseServer, SseServerConfig},
+        streamable_http_server::{
+            StreamableHttpServerConfig, StreamableHttpService, session::local::LocalSessionManager,
+        },
+    },
+};
+use serde_json::Value;
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    calculator,
+    client::{self, McpClient, Target},
+    metrics::SessionTransport,
+    state::AppState,
+};
+
+/// 等待响应与通知的时间
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 测试使用的传输
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fixture {
+    Stdio,
+    Sse,
+    StreamableHttp,
+}
+
+impl Fixture {
+    pub const ALL: [Fixture; 3] = [Fixture::Stdio, Fixture::Sse, Fixture::StreamableHttp];
+
+    pub fn session_transport(self) -> SessionTransport {
+        match self {
+            Fixture::Stdio => SessionTransport::Stdio,
+            Fixture::Sse => SessionTransport::Sse,
+            Fixture::StreamableHttp => SessionTransport::StreamableHttp,
+        }
+    }
+}
+
+/// 进程内的服务与已初始化的客户端
+pub struct Harness {
+    fixture: Fixture,
+    client: McpClient,
+    notifications: mpsc::UnboundedReceiver<ServerNotification>,
+    ct: CancellationToken,
+    server: JoinHandle<()>,
+}
+
+impl Deref for Harness {
+    type Target = Peer<RoleClient>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl Harness {
+    /// 经内存管道连接单个服务实例
+    pub async fn duplex<S: Service<RoleServer>>(service: S) -> Self {
+        Self::duplex_as(client::client_info(), service).await
+    }
+
+    /// 同 [`Harness::duplex`], 客户端以 `info` 初始化, 用于模拟特定的客户端
+    pub async fn duplex_as<S: Service<RoleServer>>(info: ClientInfo, service: S) -> Self {
+        let ct = CancellationToken::new();
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        let server_ct = ct.clone();
+        let server = tokio::spawn(async move {
+            if let Ok(server) = service
+                .serve_with_ct(tokio::io::split(server_io), server_ct)
+                .await
+            {
+                let _ = server.waiting().await;
+            }
+        });
+        let (client, notifications) =
+            client::connect_transport_as(info, tokio::io::split(client_io))
+                .await
+                .expect("connect over duplex");
+        Self {
+            fixture: Fixture::Stdio,
+            client,
+            notifications,
+            ct,
+            server,
+        }
+    }
+
+    /// 在指定传输上启动服务, 每个会话调用一次 `service` 创建服务实例
+    pub async fn start<S, F>(fixture: Fixture, service: F) -> Self
+    where
+        S: Service<RoleServer>,
+        F: Fn() -> S + Send + Sync + 'static,
+    {
+        if fixture == Fixture::Stdio {
+            return Self::duplex(service()).await;
+        }
+
+        let ct = CancellationToken::new();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind ephemeral port");
+        let address = listener.local_addr().expect("local address");
+        let (router, target) = match fixture {
+            Fixture::Sse => {
+                let (mut sse_server, router) = SseServer::new(SseServerConfig {
+                    bind: address,
+                    sse_path: "/sse".to_string(),
+                    post_path: "/message".to_string(),
+                    ct: ct.clone(),
+                    sse_keep_alive: None,
+                });
+                let ct = ct.clone();
+                tokio::spawn(async move {
+                    while let Some(transport) = sse_server.next_transport().await {
+                        let service = service();
+                        let ct = ct.child_token();
+                        tokio::spawn(async move {
+                            if let Ok(server) = service.serve_with_ct(transport, ct).await {
+                                let _ = server.waiting().await;
+                            }
+                        });
+                    }
+                });
+                (router, Target::Sse(format!("http://{address}/sse")))
+            }
+            Fixture::StreamableHttp => {
+                let http_service = StreamableHttpService::new(
+                    move || Ok(service()),
+                    Arc::new(LocalSessionManager::default()),
+                    StreamableHttpServerConfig::default(),
+                );
+                (
+                    axum::Router::new().nest_service("/mcp", http_service),
+                    Target::Http(format!("http://{address}/mcp")),
+                )
+            }
+            Fixture::Stdio => unreachable!(),
+        };
+        let shutdown = ct.clone();
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, router)
+                .with_graceful_shutdown(shutdown.cancelled_owned())
+                .await;
+        });
+        let (client, notifications) = client::connect(&target, Default::default())
+            .await
+            .unwrap_or_else(|e| panic!("connect over {fixture:?}: {e}"));
+        Self {
+            fixture,
+            client,
+            notifications,
+            ct,
+            server,
+        }
+    }
+
+    /// 使用共享状态的 [`calculator::Calculator`]
+    pub async fn calculator(fixture: Fixture, state: Arc<AppState>) -> Self {
+        Self::start(fixture, move || {
+            calculator::session_service(state.clone(), fixture.session_transport())
+        })
+        .await
+    }
+
+    /// 服务端的初始化结果
+    pub fn server_info(&self) -> &InitializeResult {
+        self.peer_info().expect("initialized")
+    }
+
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> CallToolResult {
+        let arguments = match arguments {
+            Value::Object(arguments) => Some(arguments),
+            Value::Null => None,
+            other => panic!("tool arguments must be an object, got {other}"),
+        };
+        self.client
+            .call_tool(CallToolRequestParam {
+                name: name.to_string().into(),
+                arguments,
+            })
+            .await
+            .unwrap_or_else(|e| panic!("{:?} tools/call {name}: {e}", self.fixture))
+    }
+
+    /// 调用成功的工具, 返回拼接后的文本内容
+    pub async fn call_text(&self, name: &str, arguments: Value) -> String {
+        let result = self.call_tool(name, arguments).await;
+        assert_ne!(
+            result.is_error,
+            Some(true),
+            "tool {name} failed: {result:?}"
+        );
+        result
+            .content
+            .iter()
+            .flatten()
+            .filter_map(|content| content.as_text().map(|text| text.text.as_str()))
+            .collect()
+    }
+
+    /// 调用应当返回 JSON-RPC 错误的工具
+    pub async fn call_error(&self, name: &str, arguments: Value) -> McpError {
+        let result = self
+            .client
+            .call_tool(CallToolRequestParam {
+                name: name.to_string().into(),
+                arguments: arguments.as_object().cloned(),
+            })
+            .await;
+        match result {
+            Err(ServiceError::McpError(error)) => error,
+            other => panic!(
+                "{:?} tools/call {name}: expected an error, got {other:?}",
+                self.fixture
+            ),
+        }
+    }
+
+    pub async fn read_resource(&self, uri: &str) -> ReadResourceResult {
+        self.client
+            .read_resource(ReadResourceRequestParam {
+                uri: uri.to_string(),
+            })
+            .await
+            .unwrap_or_else(|e| panic!("{:?} resources/read {uri}: {e}", self.fixture))
+    }
+
+    /// 读取资源, 返回拼接后的文本内容
+    pub async fn read_text(&self, uri: &str) -> String {
+        self.read_resource(uri)
+            .await
+            .contents
+            .into_iter()
+            .filter_map(|contents| match contents {
+                ResourceContents::TextResourceContents { text, .. } => Some(text),
+                ResourceContents::BlobResourceContents { .. } => None,
+            })
+            .collect()
+    }
+
+    pub async fn get_prompt(&self, name: &str, arguments: Value) -> GetPromptResult {
+        self.client
+            .get_prompt(GetPromptRequestParam {
+                name: name.to_string(),
+                arguments: arguments.as_object().cloned(),
+            })
+            .await
+            .unwrap_or_else(|e| panic!("{:?} prompts/get {name}: {e}", self.fixture))
+    }
+
+    /// 下一条服务端通知
+    pub async fn notification(&mut self) -> ServerNotification {
+        match tokio::time::timeout(TIMEOUT, self.notifications.recv()).await {
+            Ok(Some(notification)) => notification,
+            Ok(None) => panic!("{:?}: connection closed", self.fixture),
+            Err(_) => panic!("{:?}: no notification within {TIMEOUT:?}", self.fixture),
+        }
+    }
+
+    /// 等待满足条件的通知, 跳过之前的其它通知
+    pub async fn expect_notification(
+        &mut self,
+        matches: impl Fn(&ServerNotification) -> bool,
+    ) -> ServerNotification {
+        loop {
+            let notification = self.notification().await;
+            if matches(&notification) {
+                return notification;
+            }
+        }
+    }
+
+    /// `wait` 时间内没有收到通知
+    pub async fn assert_no_notification(&mut self, wait: Duration) {
+        if let Ok(Some(notification)) = tokio::time::timeout(wait, self.notifications.recv()).await
+        {
+            panic!(
+                "{:?}: unexpected notification {notification:?}",
+                self.fixture
+            );
+        }
+    }
+
+    /// 结束会话并停止服务
+    pub async fn close(self) {
+        self.client.close().await;
+        self.ct.cancel();
+        self.server.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rmcp::model::ErrorCode;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        config::Config,
+        registry::{Registry, RegistryConfig},
+        reload,
+    };
+
+    #[tokio::test]
+    async fn test_calculator_suite() {
+        for fixture in Fixture::ALL {
+            let state = Arc::new(AppState::new(Config::default()).unwrap());
+            let mut harness = Harness::calculator(fixture, state.clone()).await;
+            assert_eq!(
+                harness.server_info().instructions.as_deref(),
+                Some("A simple calculator")
+            );
+
+            let tools = harness.list_all_tools().await.unwrap();
+            assert!(tools.iter().any(|tool| tool.name == "sum"), "{fixture:?}");
+            assert_eq!(
+                harness.call_text("sum", json!({ "a": 2, "b": 3 })).await,
+                "5"
+            );
+            let error = harness.call_error("nope", json!({})).await;
+            assert_eq!(error.code, ErrorCode::INVALID_PARAMS);
+
+            assert_eq!(
+                harness.read_text("test://dynamic/resource/42").await,
+                "read: //dynamic/resource/42"
+            );
+            let prompt = harness
+                .get_prompt("code_review", json!({ "pr_number": "42" }))
+                .await;
+            assert!(!prompt.messages.is_empty());
+
+            // 客户端登记后, 注册表变更推送 list_changed
+            for _ in 0..50 {
+                if state.sessions.list().iter().any(|s| s.is_initialized()) {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            harness
+                .assert_no_notification(Duration::from_millis(50))
+                .await;
+            let config = Config {
+                registry: RegistryConfig {
+                    tools: vec!["sum".to_string()],
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let changes = state.replace_registry(Registry::new(&config));
+            reload::notify(&state, &changes);
+            harness
+                .expect_notification(|n| {
+                    matches!(n, ServerNotification::ToolListChangedNotification(_))
+                })
+                .await;
+            assert_eq!(harness.list_all_tools().await.unwrap().len(), 1);
+
+            harness.close().await;
+        }
+    }
+}
@@ -0,0 +1,419 @@
+//! TLS / mTLS 支持
+//!
+//! - 从 PEM 文件加载服务端证书与私钥
+//! - 证书文件变更后自动热加载
+//! - 可选的客户端证书校验 (mTLS), 客户端证书主题作为调用方身份
+//! - 副本之间转发请求使用的客户端配置, 见 [`crate::cluster`]
+//! - 生成本地开发用的自签名证书
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, connect_info::Connected},
+    middleware::Next,
+    response::Response,
+    serve::{IncomingStream, Listener},
+};
+use log::{error, info, warn};
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    KeyUsagePurpose,
+};
+use rustls::{
+    ClientConfig, RootCertStore, ServerConfig,
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{TlsAcceptor, server::TlsStream};
+
+use crate::{
+    error::{Error, TlsError},
+    identity::CallerIdentity,
+};
+
+/// 证书文件变更检测间隔
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+/// TLS 握手超时
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// TLS 配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// 服务端证书链 (PEM)
+    pub cert: PathBuf,
+    /// 服务端私钥 (PEM)
+    pub key: PathBuf,
+    /// 客户端证书 CA (PEM), 设置后启用 mTLS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ca: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    /// 参与热加载检测的文件
+    fn watched_files(&self) -> Vec<&Path> {
+        let mut files = vec![self.cert.as_path(), self.key.as_path()];
+        if let Some(ca) = &self.client_ca {
+            files.push(ca.as_path());
+        }
+        files
+    }
+
+    /// 构建 rustls 服务端配置
+    pub fn build_server_config(&self) -> Result<Arc<ServerConfig>, Error> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(TlsError::from)?;
+
+        let builder = match &self.client_ca {
+            Some(ca) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(ca)? {
+                    roots.add(cert).map_err(TlsError::from)?;
+                }
+                let verifier = WebPkiClientVerifier::builder_with_provider(roots.into(), provider)
+                    .build()
+                    .map_err(TlsError::from)?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut config = builder
+            .with_single_cert(load_certs(&self.cert)?, load_key(&self.key)?)
+            .map_err(TlsError::from)?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(Arc::new(config))
+    }
+}
+
+/// 构建 HTTPS 客户端配置: 以 `ca` 校验服务端证书, 对方要求 mTLS 时出示 `client_auth` 证书与私钥
+pub fn build_client_config(
+    ca: &Path,
+    client_auth: Option<(&Path, &Path)>,
+) -> Result<ClientConfig, Error> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(ca)? {
+        roots.add(cert).map_err(TlsError::from)?;
+    }
+    let builder =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(TlsError::from)?
+            .with_root_certificates(roots);
+    let mut config = match client_auth {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+            .map_err(TlsError::from)?,
+        None => builder.with_no_client_auth(),
+    };
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// 读取 PEM 证书链
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let data = read_pem(path)?;
+    let certs = rustls_pemfile::certs(&mut data.as_slice()).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificate(path.to_path_buf()).into());
+    }
+    Ok(certs)
+}
+
+/// 读取 PEM 私钥
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, Error> {
+    let data = read_pem(path)?;
+    rustls_pemfile::private_key(&mut data.as_slice())?
+        .ok_or_else(|| TlsError::NoPrivateKey(path.to_path_buf()).into())
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>, TlsError> {
+    std::fs::read(path).map_err(|source| TlsError::ReadPem {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// 连接信息, 包含客户端证书主题
+#[derive(Debug, Clone)]
+pub struct TlsPeer {
+    pub remote_addr: SocketAddr,
+    pub client_subject: Option<String>,
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for TlsPeer {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        stream.remote_addr().clone()
+    }
+}
+
+/// TLS 监听器
+///
+/// 握手在独立任务中完成, 避免慢客户端阻塞 accept。
+pub struct TlsListener {
+    rx: mpsc::Receiver<(TlsStream<TcpStream>, TlsPeer)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    pub async fn bind(address: &str, config: TlsConfig) -> Result<Self, Error> {
+        let server_config = Arc::new(RwLock::new(config.build_server_config()?));
+        let listener = TcpListener::bind(address).await?;
+        let local_addr = listener.local_addr()?;
+
+        tokio::spawn(watch_certificates(config, server_config.clone()));
+
+        let (tx, rx) = mpsc::channel(64);
+        tokio::spawn(async move {
+            loop {
+                let (stream, remote_addr) = match listener.accept().await {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!("tls accept error: {e}");
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let acceptor = TlsAcceptor::from(server_config.read().unwrap().clone());
+                let conn_tx = tx.clone();
+                tokio::spawn(async move {
+                    let stream = match tokio::time::timeout(
+                        HANDSHAKE_TIMEOUT,
+                        acceptor.accept(stream),
+                    )
+                    .await
+                    {
+                        Ok(Ok(stream)) => stream,
+                        Ok(Err(e)) => {
+                            warn!("tls handshake with {remote_addr} failed: {e}");
+                            return;
+                        }
+                        Err(_) => {
+                            warn!("tls handshake with {remote_addr} timed out");
+                            return;
+                        }
+                    };
+                    let peer = TlsPeer {
+                        remote_addr,
+                        client_subject: client_subject(&stream),
+                    };
+                    let _ = conn_tx.send((stream, peer)).await;
+                });
+                if tx.is_closed() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self { rx, local_addr })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = TlsPeer;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.rx.recv().await {
+            Some(conn) => conn,
+            // 接收任务只会在监听器被丢弃后退出
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(TlsPeer {
+            remote_addr: self.local_addr,
+            client_subject: None,
+        })
+    }
+}
+
+/// 解析客户端证书主题
+fn client_subject(stream: &TlsStream<TcpStream>) -> Option<String> {
+    let cert = stream.get_ref().1.peer_certificates()?.first()?;
+    match x509_parser::parse_x509_certificate(cert.as_ref()) {
+        Ok((_, cert)) => Some(cert.subject().to_string()),
+        Err(e) => {
+            warn!("failed to parse client certificate: {e}");
+            None
+        }
+    }
+}
+
+/// 定期检查证书文件修改时间, 变更后重新加载
+async fn watch_certificates(config: TlsConfig, server_config: Arc<RwLock<Arc<ServerConfig>>>) {
+    let modified = |config: &TlsConfig| -> Vec<Option<SystemTime>> {
+        config
+            .watched_files()
+            .into_iter()
+            .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    };
+
+    let mut last = modified(&config);
+    let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+    loop {
+        interval.tick().await;
+        let current = modified(&config);
+        if current == last {
+            continue;
+        }
+        last = current;
+
+        match config.build_server_config() {
+            Ok(new_config) => {
+                *server_config.write().unwrap() = new_config;
+                info!("tls certificates reloaded");
+            }
+            // 证书可能正在写入, 保留旧配置等待下次变更
+            Err(e) => error!("failed to reload tls certificates: {e}"),
+        }
+    }
+}
+
+/// 将 mTLS 客户端证书主题写入请求扩展
+pub async fn propagate_identity(mut req: Request, next: Next) -> Response {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<TlsPeer>>()
+        .map(|ConnectInfo(peer)| peer.clone());
+    if let Some(TlsPeer {
+        remote_addr,
+        client_subject: Some(subject),
+    }) = peer
+    {
+        tracing::debug!(%remote_addr, %subject, "mtls client");
+        req.extensions_mut().insert(CallerIdentity(subject));
+    }
+    next.run(req).await
+}
+
+/// 生成本地开发用证书: CA、由 CA 签发的服务端证书与客户端证书
+///
+/// 输出文件: `ca.pem`、`server.pem`、`server.key`、`client.pem`、`client.key`
+pub fn generate_dev_certificates(
+    out_dir: &Path,
+    sans: Vec<String>,
+    client_cn: &str,
+) -> Result<(), Error> {
+    std::fs::create_dir_all(out_dir)?;
+
+    let mut ca_params = CertificateParams::new(Vec::new()).map_err(TlsError::from)?;
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "rs-mcpr development CA");
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    let ca_key = KeyPair::generate().map_err(TlsError::from)?;
+    let ca_cert = ca_params.self_signed(&ca_key).map_err(TlsError::from)?;
+
+    let mut server_params = CertificateParams::new(sans).map_err(TlsError::from)?;
+    server_params
+        .distinguished_name
+        .push(DnType::CommonName, "rs-mcpr server");
+    server_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    let server_key = KeyPair::generate().map_err(TlsError::from)?;
+    let server_cert = server_params
+        .signed_by(&server_key, &ca_cert, &ca_key)
+        .map_err(TlsError::from)?;
+
+    let mut client_params = CertificateParams::new(Vec::new()).map_err(TlsError::from)?;
+    client_params
+        .distinguished_name
+        .push(DnType::CommonName, client_cn);
+    client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let client_key = KeyPair::generate().map_err(TlsError::from)?;
+    let client_cert = client_params
+        .signed_by(&client_key, &ca_cert, &ca_key)
+        .map_err(TlsError::from)?;
+
+    std::fs::write(out_dir.join("ca.pem"), ca_cert.pem())?;
+    std::fs::write(out_dir.join("server.pem"), server_cert.pem())?;
+    std::fs::write(out_dir.join("server.key"), server_key.serialize_pem())?;
+    std::fs::write(out_dir.join("client.pem"), client_cert.pem())?;
+    std::fs::write(out_dir.join("client.key"), client_key.serialize_pem())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::{ClientConfig, pki_types::ServerName};
+    use tokio_rustls::TlsConnector;
+
+    use super::*;
+
+    fn dev_certificates(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rs-mcpr-{name}-{}", std::process::id()));
+        generate_dev_certificates(&dir, vec!["localhost".to_string()], "test-client").unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_mtls_client_subject() {
+        let dir = dev_certificates("mtls");
+        let config = TlsConfig {
+            cert: dir.join("server.pem"),
+            key: dir.join("server.key"),
+            client_ca: Some(dir.join("ca.pem")),
+        };
+        let mut listener = TlsListener::bind("127.0.0.1:0", config).await.unwrap();
+        let addr = listener.local_addr().unwrap().remote_addr;
+
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(load_certs(&dir.join("ca.pem")).unwrap().remove(0))
+            .unwrap();
+        let client_config =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots)
+                .with_client_auth_cert(
+                    load_certs(&dir.join("client.pem")).unwrap(),
+                    load_key(&dir.join("client.key")).unwrap(),
+                )
+                .unwrap();
+
+        let client = tokio::spawn(async move {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            TlsConnector::from(Arc::new(client_config))
+                .connect(ServerName::try_from("localhost").unwrap(), stream)
+                .await
+                .unwrap()
+        });
+
+        let (_stream, peer) = listener.accept().await;
+        let _client = client.await.unwrap();
+        assert_eq!(peer.client_subject.as_deref(), Some("CN=test-client"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_private_key() {
+        let dir = dev_certificates("missing-key");
+        let config = TlsConfig {
+            cert: dir.join("server.pem"),
+            key: dir.join("server.pem"),
+            client_ca: None,
+        };
+        assert!(matches!(
+            config.build_server_config(),
+            Err(Error::Tls(TlsError::NoPrivateKey(_)))
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,321 @@
+//! Unix 域套接字传输
+//!
+//! 同一主机上的客户端不占用 TCP 端口, 通过 Unix 域套接字连接:
+//! - `http`: 在套接字上提供与 TCP 相同的 streamable HTTP 与 SSE 端点
+//! - `jsonrpc`: 每个连接是一个会话, 与 stdio 传输相同按行收发 JSON-RPC 消息
+//!
+//! 启动时清理上次异常退出遗留的套接字文件, 退出时删除套接字文件。
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// 套接字上的协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnixProtocol {
+    /// streamable HTTP 与 SSE
+    Http,
+    /// 按行分隔的 JSON-RPC 消息
+    Jsonrpc,
+}
+
+/// Unix 域套接字配置, 对应配置文件的 `[unix]` 表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnixConfig {
+    pub path: PathBuf,
+    pub protocol: UnixProtocol,
+    /// 套接字文件权限, 八进制, 如 `"660"`; 不设置时由 umask 决定
+    #[serde(deserialize_with = "octal_digits")]
+    pub mode: Option<String>,
+    /// 套接字文件的所有者 uid
+    pub uid: Option<u32>,
+    /// 套接字文件的所属组 gid
+    pub gid: Option<u32>,
+}
+
+impl Default for UnixConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("rs-mcpr.sock"),
+            protocol: UnixProtocol::Http,
+            mode: None,
+            uid: None,
+            gid: None,
+        }
+    }
+}
+
+impl UnixConfig {
+    /// 解析 `mode`, 不是 0 到 777 的八进制数时返回错误信息
+    pub fn mode(&self) -> Result<Option<u32>, String> {
+        let Some(mode) = &self.mode else {
+            return Ok(None);
+        };
+        let digits = mode.trim_start_matches("0o");
+        match u32::from_str_radix(digits, 8) {
+            Ok(mode) if mode <= 0o777 => Ok(Some(mode)),
+            _ => Err(format!("`{mode}` is not an octal file mode such as 660")),
+        }
+    }
+}
+
+/// `mode` 也接受整数, 如环境变量 `RS_MCPR_UNIX__MODE=660`, 按其十进制写法视为八进制数字
+fn octal_digits<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Mode {
+        Digits(String),
+        Number(u32),
+    }
+    Ok(
+        Option::<Mode>::deserialize(deserializer)?.map(|mode| match mode {
+            Mode::Digits(digits) => digits,
+            Mode::Number(number) => number.to_string(),
+        }),
+    )
+}
+
+#[cfg(unix)]
+pub use imp::{bind, serve_jsonrpc};
+
+#[cfg(unix)]
+mod imp {
+    use std::{
+        io,
+        os::unix::fs::{FileTypeExt, PermissionsExt},
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    use rmcp::ServiceExt;
+    use tokio::net::UnixListener;
+
+    use super::UnixConfig;
+    use crate::{calculator, jsonrpc, metrics::SessionTransport, state::AppState};
+
+    /// 套接字文件的所有权, 释放时删除文件
+    #[derive(Debug)]
+    pub struct SocketGuard(PathBuf);
+
+    impl Drop for SocketGuard {
+        fn drop(&mut self) {
+            if let Err(e) = std::fs::remove_file(&self.0)
+                && e.kind() != io::ErrorKind::NotFound
+            {
+                tracing::error!("failed to remove socket {}: {e}", self.0.display());
+            }
+        }
+    }
+
+    /// 监听套接字并设置权限与所有者
+    pub fn bind(config: &UnixConfig) -> io::Result<(UnixListener, SocketGuard)> {
+        let path = &config.path;
+        remove_stale(path)?;
+        let listener = UnixListener::bind(path)?;
+        let guard = SocketGuard(path.clone());
+        let mode = config
+            .mode()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if let Some(mode) = mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+        if config.uid.is_some() || config.gid.is_some() {
+            std::os::unix::fs::chown(path, config.uid, config.gid)?;
+        }
+        Ok((listener, guard))
+    }
+
+    /// 删除没有进程监听的套接字文件, 不删除其他类型的文件
+    fn remove_stale(path: &Path) -> io::Result<()> {
+        let metadata = match std::fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is in use by another process", path.display()),
+            ));
+        }
+        tracing::info!("removing stale socket {}", path.display());
+        std::fs::remove_file(path)
+    }
+
+    /// 按行收发 JSON-RPC, 每个连接一个会话, `shutdown` 完成后停止接受连接
+    pub async fn serve_jsonrpc(
+        state: Arc<AppState>,
+        listener: UnixListener,
+        shutdown: impl Future<Output = ()>,
+    ) {
+        let mut shutdown = std::pin::pin!(shutdown);
+        loop {
+            let stream = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        tracing::error!("failed to accept unix connection: {e}");
+                        continue;
+                    }
+                },
+                _ = &mut shutdown => break,
+            };
+            if let Ok(cred) = stream.peer_cred() {
+                tracing::debug!(uid = cred.uid(), pid = cred.pid(), "unix connection");
+            }
+            let limits = &state.session_limits;
+            let service = {
+                let _admission = limits.admission.lock().await;
+                if let Err(e) = limits.admit(&state, None) {
+                    tracing::warn!("closing unix connection: {e}");
+                    continue;
+                }
+                calculator::session_service(state.clone(), SessionTransport::Unix)
+            };
+            let ct = service.close_token();
+            let (read, write) = stream.into_split();
+            let transport = state
+                .recorder
+                .record(SessionTransport::Unix, jsonrpc::line_transport(read, write));
+            tokio::spawn(async move {
+                match service.serve_with_ct(transport, ct).await {
+                    Ok(server) => {
+                        let _ = server.waiting().await;
+                    }
+                    Err(e) => tracing::error!("unix serving error: {:?}", e),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{
+        os::unix::fs::{FileTypeExt, PermissionsExt},
+        sync::Arc,
+        time::Duration,
+    };
+
+    use serde_json::{Value, json};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::UnixStream,
+    };
+
+    use super::*;
+    use crate::{config::Config, session_limits::SessionLimitsConfig, state::AppState};
+
+    #[tokio::test]
+    async fn test_bind_cleans_up_stale_socket() {
+        let dir = std::env::temp_dir().join(format!("rs-mcpr-unix-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = UnixConfig {
+            path: dir.join("mcp.sock"),
+            mode: Some("600".to_string()),
+            ..Default::default()
+        };
+
+        // 遗留的套接字文件没有进程监听
+        drop(std::os::unix::net::UnixListener::bind(&config.path).unwrap());
+        let (listener, guard) = bind(&config).unwrap();
+        let metadata = std::fs::metadata(&config.path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+        // 正在监听的套接字不会被删除
+        let e = bind(&config).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::AddrInUse);
+        drop(listener);
+        drop(guard);
+        assert!(!config.path.exists());
+
+        std::fs::write(&config.path, "").unwrap();
+        let e = bind(&config).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::AlreadyExists);
+
+        assert!(
+            UnixConfig {
+                mode: Some("1777".to_string()),
+                ..Default::default()
+            }
+            .mode()
+            .is_err()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_jsonrpc_session_limit() {
+        let dir = std::env::temp_dir().join(format!("rs-mcpr-unix-limit-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = Config {
+            sessions: SessionLimitsConfig {
+                max_sessions: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = Arc::new(AppState::new(config).unwrap());
+        let unix = UnixConfig {
+            path: dir.join("mcp.sock"),
+            protocol: UnixProtocol::Jsonrpc,
+            ..Default::default()
+        };
+        let (listener, _guard) = bind(&unix).unwrap();
+        tokio::spawn(serve_jsonrpc(
+            state.clone(),
+            listener,
+            std::future::pending(),
+        ));
+
+        let (read, mut first) = UnixStream::connect(&unix.path).await.unwrap().into_split();
+        let mut lines = BufReader::new(read).lines();
+        let mut send = async |message: Value| {
+            first
+                .write_all(format!("{message}\n").as_bytes())
+                .await
+                .unwrap();
+        };
+        send(
+            json!({"jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "clientInfo": {"name": "test", "version": "1"},
+            }}),
+        )
+        .await;
+        lines.next_line().await.unwrap().unwrap();
+        send(json!({"jsonrpc": "2.0", "method": "notifications/initialized"})).await;
+        send(json!({"jsonrpc": "2.0", "id": 1, "method": "ping"})).await;
+        let response: Value =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(response["id"], 1);
+
+        // 超出上限的连接被直接关闭
+        let second = UnixStream::connect(&unix.path).await.unwrap();
+        let mut second_lines = BufReader::new(second).lines();
+        let closed = tokio::time::timeout(Duration::from_secs(5), second_lines.next_line())
+            .await
+            .expect("connection closed");
+        assert!(matches!(closed, Ok(None) | Err(_)));
+        assert_eq!(state.sessions.list().len(), 1);
+
+        send(json!({"jsonrpc": "2.0", "id": 2, "method": "ping"})).await;
+        let response: Value =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(response["id"], 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
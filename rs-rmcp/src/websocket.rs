@@ -0,0 +1,314 @@
+//! WebSocket 传输
+//!
+//! 每个 WebSocket 连接是一个 MCP 会话, 每个文本帧是一条 JSON-RPC 消息。
+//! 路由与 streamable HTTP 共用服务工厂和中间件 (来源校验、客户端证书身份、限流、会话限制),
+//! 握手请求的 [`Parts`] 带上服务端生成的 `Mcp-Session-Id` 头后透传到每条消息的请求上下文,
+//! 会话登记与审计因此与 HTTP 传输一致。
+//!
+//! 服务端每隔 `ws_ping_interval_secs` 发送 ping, 两个间隔内没有收到任何帧时断开;
+//! 超过 `ws_max_message_bytes` 的消息会断开连接。
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{
+        Request, State,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code},
+    },
+    http::{HeaderValue, request::Parts},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use futures::{SinkExt, StreamExt, channel::mpsc};
+use rmcp::{
+    RoleServer, ServiceExt,
+    model::{ClientJsonRpcMessage, GetExtensions, ServerJsonRpcMessage},
+    transport::{
+        IntoTransport,
+        common::server_side_http::{SessionId, session_id},
+    },
+};
+use serde_json::Value;
+use tokio::time::Instant;
+
+use crate::{
+    calculator::{self, Calculator},
+    identity::CallerIdentity,
+    instrument::Instrumented,
+    jsonrpc,
+    metrics::SessionTransport,
+    recording::Recorder,
+    state::AppState,
+};
+
+/// WebSocket 子协议, 客户端提供时选用
+const SUBPROTOCOL: &str = "mcp";
+
+/// 服务与连接之间的消息缓冲
+const CHANNEL_CAPACITY: usize = 16;
+
+pub fn router(state: Arc<AppState>) -> axum::Router {
+    axum::Router::new()
+        .route(&state.config.http.ws_path, get(upgrade))
+        .with_state(state)
+}
+
+async fn upgrade(
+    State(state): State<Arc<AppState>>,
+    ws: WebSocketUpgrade,
+    req: Request,
+) -> Response {
+    let (mut parts, _) = req.into_parts();
+    let id = session_id();
+    parts.headers.insert(
+        "mcp-session-id",
+        HeaderValue::from_str(&id).expect("valid session id"),
+    );
+
+    let limits = &state.session_limits;
+    let service = {
+        let _admission = limits.admission.lock().await;
+        if let Err(e) = limits.admit(&state, parts.extensions.get::<CallerIdentity>()) {
+            return e.into_response();
+        }
+        calculator::session_service(state.clone(), SessionTransport::Websocket)
+    };
+    tracing::info!(session = %id, "websocket session opened");
+
+    let http = &state.config.http;
+    let ping_interval = http.ws_ping_interval();
+    let recorder = state.recorder.clone();
+    ws.protocols([SUBPROTOCOL])
+        .max_message_size(http.ws_max_message_bytes)
+        .max_frame_size(http.ws_max_message_bytes)
+        .on_upgrade(move |socket| serve(service, recorder, id, parts, socket, ping_interval))
+}
+
+async fn serve(
+    service: Instrumented<Calculator>,
+    recorder: Recorder,
+    id: SessionId,
+    parts: Parts,
+    socket: WebSocket,
+    ping_interval: Option<Duration>,
+) {
+    let (inbound_tx, inbound_rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let (outbound_tx, outbound_rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let pump = tokio::spawn(pump(socket, parts, inbound_tx, outbound_rx, ping_interval));
+
+    // 管理接口关闭会话时服务退出, 发送通道关闭后 pump 断开连接
+    let ct = service.close_token();
+    let transport = recorder.wrap(
+        SessionTransport::Websocket,
+        &id,
+        IntoTransport::<RoleServer, _, _>::into_transport((outbound_tx, inbound_rx)),
+    );
+    match service.serve_with_ct(transport, ct).await {
+        Ok(server) => {
+            let _ = server.waiting().await;
+        }
+        Err(e) => tracing::error!("websocket serving error: {:?}", e),
+    }
+    let _ = pump.await;
+}
+
+/// 在 WebSocket 帧与服务的消息通道之间转发, 并负责保活
+async fn pump(
+    mut socket: WebSocket,
+    parts: Parts,
+    mut inbound: mpsc::Sender<ClientJsonRpcMessage>,
+    mut outbound: mpsc::Receiver<ServerJsonRpcMessage>,
+    ping_interval: Option<Duration>,
+) {
+    let mut ping =
+        ping_interval.map(|period| tokio::time::interval_at(Instant::now() + period, period));
+    let mut last_seen = Instant::now();
+
+    let close = loop {
+        tokio::select! {
+            frame = socket.recv() => {
+                last_seen = Instant::now();
+                match frame {
+                    Some(Ok(Message::Text(text))) => match decode(&text, &parts) {
+                        Ok(message) => {
+                            if inbound.send(message).await.is_err() {
+                                break None;
+                            }
+                        }
+                        Err(Some(error)) => {
+                            if socket.send(Message::Text(error.to_string().into())).await.is_err() {
+                                break None;
+                            }
+                        }
+                        Err(None) => tracing::debug!("ignoring unknown notification: {text}"),
+                    },
+                    Some(Ok(Message::Binary(_))) => {
+                        break Some((close_code::UNSUPPORTED, "binary messages are not supported"));
+                    }
+                    Some(Ok(Message::Close(_))) | None => break None,
+                    // ping 由底层自动回复 pong
+                    Some(Ok(Message::Ping(_) | Message::Pong(_))) => {}
+                    Some(Err(e)) => {
+                        tracing::debug!("websocket receive error: {e}");
+                        break Some((close_code::POLICY, "invalid frame"));
+                    }
+                }
+            }
+            message = outbound.next() => match message {
+                Some(message) => {
+                    let text = match serde_json::to_string(&message) {
+                        Ok(text) => text,
+                        Err(e) => {
+                            tracing::error!("failed to serialize message: {e}");
+                            continue;
+                        }
+                    };
+                    if socket.send(Message::Text(text.into())).await.is_err() {
+                        break None;
+                    }
+                }
+                None => break Some((close_code::NORMAL, "session closed")),
+            },
+            _ = async { ping.as_mut().expect("ping enabled").tick().await }, if ping.is_some() => {
+                let period = ping_interval.expect("ping enabled");
+                if last_seen.elapsed() > period * 2 {
+                    break Some((close_code::AWAY, "ping timeout"));
+                }
+                if socket.send(Message::Ping(Default::default())).await.is_err() {
+                    break None;
+                }
+            }
+        }
+    };
+
+    if let Some((code, reason)) = close {
+        tracing::debug!(code, reason, "closing websocket");
+        let frame = CloseFrame {
+            code,
+            reason: reason.into(),
+        };
+        let _ = socket.send(Message::Close(Some(frame))).await;
+    }
+}
+
+/// 解析客户端消息并附上握手请求, 见 [`jsonrpc::decode`]
+fn decode(text: &str, parts: &Parts) -> Result<ClientJsonRpcMessage, Option<Value>> {
+    let mut message = jsonrpc::decode(text)?;
+    match &mut message {
+        ClientJsonRpcMessage::Request(req) => {
+            req.request.extensions_mut().insert(parts.clone());
+        }
+        ClientJsonRpcMessage::Notification(not) => {
+            not.notification.extensions_mut().insert(parts.clone());
+        }
+        _ => {}
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{SinkExt, StreamExt};
+    use rmcp::model::ErrorCode;
+    use serde_json::json;
+    use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+
+    use super::*;
+    use crate::{
+        config::{Config, HttpConfig},
+        session_limits::CloseReason,
+    };
+
+    type Socket = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    async fn call(socket: &mut Socket, message: Value) -> Value {
+        socket
+            .send(WsMessage::text(message.to_string()))
+            .await
+            .unwrap();
+        loop {
+            match socket.next().await.unwrap().unwrap() {
+                WsMessage::Text(text) => break serde_json::from_str(&text).unwrap(),
+                WsMessage::Ping(_) | WsMessage::Pong(_) => {}
+                other => panic!("unexpected frame: {other:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_websocket_session() {
+        let config = Config {
+            http: HttpConfig {
+                ws_max_message_bytes: 4096,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = Arc::new(AppState::new(config).unwrap());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+        let app = router(state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let (mut socket, _) = connect_async(&url).await.unwrap();
+        let response = call(
+            &mut socket,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "clientInfo": {"name": "test", "version": "1"},
+            }}),
+        )
+        .await;
+        assert!(response["result"]["serverInfo"].is_object());
+        let response = call(&mut socket, json!("not a message")).await;
+        assert_eq!(response["error"]["code"], ErrorCode::PARSE_ERROR.0);
+        let response = call(
+            &mut socket,
+            json!({"jsonrpc": "2.0", "id": 9, "method": "nope"}),
+        )
+        .await;
+        assert_eq!(response["id"], 9);
+        assert_eq!(response["error"]["code"], ErrorCode::METHOD_NOT_FOUND.0);
+
+        socket
+            .send(WsMessage::text(
+                json!({"jsonrpc": "2.0", "method": "notifications/initialized"}).to_string(),
+            ))
+            .await
+            .unwrap();
+        let response = call(
+            &mut socket,
+            json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}),
+        )
+        .await;
+        assert!(!response["result"]["tools"].as_array().unwrap().is_empty());
+
+        // 会话与 HTTP 传输一样登记, 可由管理接口关闭
+        let entry = state.sessions.list().pop().unwrap();
+        assert_eq!(entry.transport, SessionTransport::Websocket);
+        let id = entry.id().unwrap();
+        assert!(state.close_session(&id, CloseReason::Admin).await);
+        loop {
+            match socket.next().await {
+                Some(Ok(WsMessage::Close(frame))) => {
+                    assert_eq!(u16::from(frame.unwrap().code), close_code::NORMAL);
+                    break;
+                }
+                Some(Ok(_)) => {}
+                other => panic!("unexpected frame: {other:?}"),
+            }
+        }
+
+        // 超过大小限制的消息断开连接
+        let (mut socket, _) = connect_async(&url).await.unwrap();
+        socket
+            .send(WsMessage::text("x".repeat(8192)))
+            .await
+            .unwrap();
+        while let Some(Ok(frame)) = socket.next().await {
+            assert!(matches!(frame, WsMessage::Close(_)), "{frame:?}");
+        }
+    }
+}